@@ -0,0 +1,8 @@
+#!/bin/sh
+# Builds the npm package in client/pkg and replaces the package.json that
+# wasm-pack generates with the reviewed one in client/npm, so that the
+# package name, published files and entry points never drift.
+set -eu
+client=$(dirname "$0")/..
+wasm-pack build "$client" --release --target bundler --scope holo-host -- --no-default-features
+cp "$client/npm/package.json" "$client/pkg/package.json"
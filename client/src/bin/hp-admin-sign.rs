@@ -0,0 +1,491 @@
+//! Signs HPOS admin calls from shell scripts and CI, without the browser.
+//!
+//! ```sh
+//! export HPOS_HC_PUBLIC_KEY=uhCAk… HP_ADMIN_EMAIL=ops@example.com
+//! # Only for hosts that record a KDF descriptor, as settings.admin.kdf
+//! export HP_ADMIN_KDF=hp-admin-crypto/kdf/v1:m=131072,t=3,p=1
+//! hp-admin-sign public-key
+//! hp-admin-sign headers GET /api/v1/status
+//! hp-admin-sign --rfc9421 headers GET https://host/api/v1/status
+//! echo "$PASSWORD" | hp-admin-sign --password-stdin request PUT https://host/api/v1/config -d @config.json
+//!
+//! # A CI bot with its own key, limited to reading status
+//! hp-admin-sign new-key bot.key
+//! hp-admin-sign delegate <bot public key> --method GET --path /api/v1/status > bot.delegation
+//! hp-admin-sign --key-file bot.key --delegation bot.delegation request GET https://host/api/v1/status
+//!
+//! # Setting up a new host: refuse a weak password, and use the recommended
+//! # KDF, whose descriptor is printed on standard error for the host config
+//! hp-admin-sign public-key --new
+//!
+//! # After changing the admin password: endorse the new key with the old one,
+//! # keeping the host's KDF (--kdf legacy if it records none)
+//! NEW_KEY=$(HP_ADMIN_PASSWORD=$NEW_PASSWORD hp-admin-sign public-key --new)
+//! hp-admin-sign endorse "$NEW_KEY" > rotation
+//! hp-admin-sign request POST https://host/api/v1/hp-admin-rotate -d @rotation
+//! ```
+
+use std::error::Error;
+use std::io::{BufRead, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::{Args, Parser, Subcommand};
+use url::Url;
+use zeroize::Zeroizing;
+
+use hp_admin_crypto::delegation::{DelegationChain, DELEGATION};
+use hp_admin_crypto::{decode_public_key, encode_public_key};
+use hp_admin_keypair::{strength, HpAdminKeypair, KdfDescriptor};
+
+/// Environment variable holding the admin password, as an alternative to the
+/// prompt and `--password-stdin`. Deliberately not a flag, so the password
+/// never shows up in the process list.
+const PASSWORD_ENV: &str = "HP_ADMIN_PASSWORD";
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Sign HP Admin calls to HPOS from the command line")]
+struct Cli {
+    /// HPOS holochain agent public key of the host; required unless signing
+    /// with --key-file.
+    #[arg(long, env = "HPOS_HC_PUBLIC_KEY")]
+    hc_public_key: Option<String>,
+
+    /// Admin email; prompted for if not given.
+    #[arg(long, env = "HP_ADMIN_EMAIL")]
+    email: Option<String>,
+
+    /// Read the admin password from the first line of standard input instead
+    /// of $HP_ADMIN_PASSWORD or a prompt.
+    #[arg(long)]
+    password_stdin: bool,
+
+    /// KDF descriptor the host records with its admin key, in
+    /// `settings.admin.kdf`; hosts without one use the original parameters,
+    /// `legacy`. `recommended`, the default for `public-key --new`, picks
+    /// the descriptor for new hosts.
+    #[arg(long, env = "HP_ADMIN_KDF", value_parser = parse_kdf)]
+    kdf: Option<KdfDescriptor>,
+
+    /// Sign with RFC 9421 `Signature-Input`/`Signature` headers and a
+    /// `Content-Digest` instead of the X-Hpos-Admin headers.
+    #[arg(long)]
+    rfc9421: bool,
+
+    /// Sign with the key in this file, as written by `new-key`, instead of
+    /// deriving the admin key from email and password.
+    #[arg(long, env = "HP_ADMIN_KEY_FILE")]
+    key_file: Option<PathBuf>,
+
+    /// Delegation certificates, as printed by `delegate`, vouching for the
+    /// key in --key-file; sent with every call.
+    #[arg(long, env = "HP_ADMIN_DELEGATION", requires = "key_file")]
+    delegation: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the derived admin public key, to check against the host config.
+    PublicKey {
+        /// The password is new, for a new host or a password change: refuse
+        /// it if it is too weak, derive the key with the recommended KDF
+        /// unless --kdf says otherwise, and print the KDF descriptor to
+        /// record with the key on standard error.
+        #[arg(long)]
+        new: bool,
+    },
+
+    /// Write a new random key to a file, for use with --key-file, and print
+    /// its public key.
+    NewKey { path: PathBuf },
+
+    /// Print a delegation certificate letting another key make some calls.
+    ///
+    /// With --delegation, the new certificate extends that chain, and can
+    /// only narrow its scope.
+    Delegate {
+        /// Base64 public key of the delegate.
+        public_key: String,
+        /// Allowed method; may be repeated.
+        #[arg(long, required = true)]
+        method: Vec<String>,
+        /// Allowed URI path prefix, matching whole segments; may be repeated.
+        #[arg(long, required = true)]
+        path: Vec<String>,
+        /// Seconds until the certificate expires.
+        #[arg(long, default_value_t = 30 * 24 * 3600)]
+        valid_for: u64,
+    },
+
+    /// Print an endorsement of the key derived from a new password, signed
+    /// with the current key; POST it to the host's rotation endpoint.
+    Endorse {
+        /// Base64 public key derived from the new password.
+        public_key: String,
+    },
+
+    /// Print the signature headers for a call, one `name: value` per line.
+    Headers {
+        method: String,
+        /// Request URI: path plus optional query string, or the absolute URL
+        /// with --rfc9421.
+        uri: String,
+        #[command(flatten)]
+        body: BodyArgs,
+        /// Challenge nonce issued by the host's verification server.
+        #[arg(long)]
+        nonce: Option<String>,
+    },
+
+    /// Sign and send a call, printing the response body.
+    Request {
+        method: String,
+        url: Url,
+        #[command(flatten)]
+        body: BodyArgs,
+        /// Extra request header, `Name: value`; may be repeated.
+        #[arg(long, short = 'H')]
+        header: Vec<String>,
+        /// Fetch a challenge nonce from this URL and sign it into the call.
+        #[arg(long)]
+        nonce_url: Option<Url>,
+        /// Also print the response status line and headers.
+        #[arg(long, short = 'i')]
+        include: bool,
+    },
+}
+
+#[derive(Debug, Args)]
+struct BodyArgs {
+    /// Request body; `@file` reads it from a file and `@-` from standard input.
+    #[arg(long, short = 'd')]
+    data: Option<String>,
+}
+
+impl BodyArgs {
+    fn reads_stdin(&self) -> bool {
+        self.data.as_deref() == Some("@-")
+    }
+
+    fn read(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        match self.data.as_deref() {
+            None => Ok(Vec::new()),
+            Some("@-") => {
+                let mut body = Vec::new();
+                std::io::stdin().read_to_end(&mut body)?;
+                Ok(body)
+            }
+            Some(data) => match data.strip_prefix('@') {
+                Some(path) => std::fs::read(PathBuf::from(path))
+                    .map_err(|e| format!("cannot read body from {path}: {e}").into()),
+                None => Ok(data.as_bytes().to_vec()),
+            },
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("hp-admin-sign: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    if let Command::NewKey { path } = &cli.command {
+        let public_key = new_key(path)?;
+        println!("{}", encode_public_key(&public_key));
+        return Ok(());
+    }
+
+    let body_from_stdin = match &cli.command {
+        Command::PublicKey { .. }
+        | Command::NewKey { .. }
+        | Command::Delegate { .. }
+        | Command::Endorse { .. } => false,
+        Command::Headers { body, .. } | Command::Request { body, .. } => body.reads_stdin(),
+    };
+    if cli.password_stdin && body_from_stdin {
+        return Err("--password-stdin and -d @- cannot both read standard input".into());
+    }
+
+    let new_password = matches!(cli.command, Command::PublicKey { new: true });
+    let keypair = keypair(&cli, new_password)?;
+    let delegation = cli
+        .delegation
+        .as_deref()
+        .map(|path| -> Result<DelegationChain, Box<dyn Error>> {
+            let chain = std::fs::read_to_string(path)
+                .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+            Ok(chain.trim().parse()?)
+        })
+        .transpose()?;
+    match cli.command {
+        Command::PublicKey { new } => {
+            if new {
+                eprintln!("settings.admin.kdf: {}", kdf(&cli, new));
+            }
+            println!("{}", encode_public_key(&keypair.public_key()));
+        }
+        Command::NewKey { .. } => unreachable!("handled before deriving a key"),
+        Command::Delegate {
+            public_key,
+            method,
+            path,
+            valid_for,
+        } => {
+            let methods: Vec<&str> = method.iter().map(String::as_str).collect();
+            let prefixes: Vec<&str> = path.iter().map(String::as_str).collect();
+            let certificate = keypair.delegate(
+                &decode_public_key(&public_key)?,
+                &methods,
+                &prefixes,
+                now()? + valid_for,
+            )?;
+            let chain = match delegation {
+                Some(chain) => chain.push(certificate)?,
+                None => DelegationChain::new(vec![certificate])?,
+            };
+            println!("{chain}");
+        }
+        Command::Endorse { public_key } => {
+            let endorsement = keypair.endorse(&decode_public_key(&public_key)?, now()?)?;
+            println!("{endorsement}");
+        }
+        Command::Headers {
+            method,
+            uri,
+            body,
+            nonce,
+        } => {
+            let body = body.read()?;
+            let signed = sign(
+                &keypair,
+                cli.rfc9421,
+                delegation.as_ref(),
+                &method,
+                &uri,
+                &body,
+                nonce.as_deref(),
+            )?;
+            for (name, value) in signed {
+                println!("{name}: {value}");
+            }
+        }
+        Command::Request {
+            method,
+            url,
+            body,
+            header,
+            nonce_url,
+            include,
+        } => {
+            let body = body.read()?;
+            let nonce = nonce_url.map(|url| fetch_nonce(&url)).transpose()?;
+            // RFC 9421 covers the whole URL, the legacy payload only the
+            // path and query.
+            let uri = match url.query() {
+                _ if cli.rfc9421 => url.to_string(),
+                Some(query) => format!("{}?{query}", url.path()),
+                None => url.path().to_owned(),
+            };
+            let signed = sign(
+                &keypair,
+                cli.rfc9421,
+                delegation.as_ref(),
+                &method,
+                &uri,
+                &body,
+                nonce.as_deref(),
+            )?;
+            send(&method, &url, &header, &signed, &body, include)?;
+        }
+    }
+    Ok(())
+}
+
+/// The key to sign with. For a `new_password`, refuses one that is too weak.
+fn keypair(cli: &Cli, new_password: bool) -> Result<HpAdminKeypair, Box<dyn Error>> {
+    if let Some(path) = &cli.key_file {
+        if new_password {
+            return Err("--new checks a password, so cannot be used with --key-file".into());
+        }
+        let encoded = Zeroizing::new(
+            std::fs::read_to_string(path)
+                .map_err(|e| format!("cannot read {}: {e}", path.display()))?,
+        );
+        let seed = Zeroizing::new(
+            BASE64_STANDARD
+                .decode(encoded.trim())
+                .ok()
+                .and_then(|seed| <[u8; 32]>::try_from(seed).ok())
+                .ok_or_else(|| format!("{} is not a key written by new-key", path.display()))?,
+        );
+        return Ok(HpAdminKeypair::from_seed(&seed));
+    }
+    let hc_public_key = cli
+        .hc_public_key
+        .as_deref()
+        .ok_or("--hc-public-key or HPOS_HC_PUBLIC_KEY is required")?;
+    let email = match &cli.email {
+        Some(email) => email.clone(),
+        None => prompt("Email: ")?,
+    };
+    let password = Zeroizing::new(if cli.password_stdin {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+        line.trim_end_matches(['\r', '\n']).to_owned()
+    } else if let Ok(password) = std::env::var(PASSWORD_ENV) {
+        password
+    } else {
+        rpassword::prompt_password("Password: ")?
+    });
+    if new_password {
+        strength::check_password(&password, &email)?;
+    }
+    Ok(HpAdminKeypair::with_kdf(
+        &kdf(cli, new_password),
+        hc_public_key,
+        &email,
+        &password,
+    )?)
+}
+
+/// The KDF to derive the admin key with: the one given, else the
+/// recommended one for a new password and the original one otherwise.
+fn kdf(cli: &Cli, new_password: bool) -> KdfDescriptor {
+    cli.kdf.unwrap_or(if new_password {
+        KdfDescriptor::RECOMMENDED
+    } else {
+        KdfDescriptor::LEGACY
+    })
+}
+
+fn parse_kdf(s: &str) -> Result<KdfDescriptor, String> {
+    match s {
+        "legacy" => Ok(KdfDescriptor::LEGACY),
+        "recommended" => Ok(KdfDescriptor::RECOMMENDED),
+        s => s.parse().map_err(|e: hp_admin_crypto::Error| e.to_string()),
+    }
+}
+
+/// Writes a new random key to `path`, readable only by the owner, and
+/// returns its public half.
+fn new_key(path: &Path) -> Result<hp_admin_crypto::VerifyingKey, Box<dyn Error>> {
+    let mut seed = Zeroizing::new([0; 32]);
+    getrandom::fill(&mut *seed).map_err(|e| format!("cannot generate a key: {e}"))?;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| format!("cannot create {}: {e}", path.display()))?;
+    writeln!(file, "{}", BASE64_STANDARD.encode(*seed))?;
+    Ok(HpAdminKeypair::from_seed(&seed).public_key())
+}
+
+fn now() -> Result<u64, Box<dyn Error>> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+fn prompt(label: &str) -> Result<String, Box<dyn Error>> {
+    eprint!("{label}");
+    std::io::stderr().flush()?;
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_owned())
+}
+
+/// Signs a call at the current time and returns the headers to send.
+fn sign(
+    keypair: &HpAdminKeypair,
+    rfc9421: bool,
+    delegation: Option<&DelegationChain>,
+    method: &str,
+    uri: &str,
+    body: &[u8],
+    nonce: Option<&str>,
+) -> Result<Vec<(&'static str, String)>, Box<dyn Error>> {
+    let now = now()?;
+    let owned = |(name, value): (&'static str, &str)| (name, value.to_owned());
+    let mut headers: Vec<_> = if rfc9421 {
+        let signed = keypair.sign_message(method, uri, body, now, nonce)?;
+        signed.iter().map(owned).collect()
+    } else {
+        let signed = match nonce {
+            Some(nonce) => keypair.sign_with_nonce(method, uri, body, now, nonce)?,
+            None => keypair.sign(method, uri, body, now)?,
+        };
+        signed.iter().map(owned).collect()
+    };
+    if let Some(chain) = delegation {
+        headers.push((DELEGATION, chain.to_string()));
+    }
+    Ok(headers)
+}
+
+fn fetch_nonce(url: &Url) -> Result<String, Box<dyn Error>> {
+    let response: serde_json::Value =
+        serde_json::from_str(&ureq::post(url.as_str()).call()?.into_string()?)?;
+    response["nonce"]
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| format!("no nonce in response from {url}").into())
+}
+
+fn send(
+    method: &str,
+    url: &Url,
+    extra_headers: &[String],
+    signed: &[(&'static str, String)],
+    body: &[u8],
+    include: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut request = ureq::request(method, url.as_str());
+    for header in extra_headers {
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| format!("header {header:?} is not `Name: value`"))?;
+        request = request.set(name.trim(), value.trim());
+    }
+    for (name, value) in signed {
+        request = request.set(name, value);
+    }
+
+    let (response, failed) = match request.send_bytes(body) {
+        Ok(response) => (response, false),
+        Err(ureq::Error::Status(_, response)) => (response, true),
+        Err(e) => return Err(e.into()),
+    };
+    let status = response.status();
+    let mut out = std::io::stdout().lock();
+    if include {
+        writeln!(
+            out,
+            "{} {} {}",
+            response.http_version(),
+            status,
+            response.status_text()
+        )?;
+        for name in response.headers_names() {
+            for value in response.all(&name) {
+                writeln!(out, "{name}: {value}")?;
+            }
+        }
+        writeln!(out)?;
+    }
+    std::io::copy(&mut response.into_reader(), &mut out)?;
+    if failed {
+        return Err(format!("{method} {url} failed with HTTP {status}").into());
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Deterministic derivation of the admin key seed.
+//!
+//! HP Admin has no key file: the admin keypair is rebuilt from what the user
+//! types every time they log in. Any implementation that wants to produce the
+//! same key must follow these steps exactly.
+//!
+//! 1. The HPOS holochain agent public key is decoded to its 32 raw Ed25519
+//!    bytes, which become the Argon2 salt. Both the HoloHash form
+//!    (`uhCAk…`) and plain base64 are accepted, so the salt does not depend
+//!    on how the key was written down.
+//! 2. The email is trimmed and lower-cased. The Argon2 password is the
+//!    big-endian `u32` byte length of the email, the email bytes, then the
+//!    password bytes verbatim. The length prefix keeps `("ab", "c")` and
+//!    `("a", "bc")` apart.
+//! 3. Argon2id (version 0x13) with the host's cost parameters and no secret
+//!    or associated data produces the 32-byte Ed25519 seed.
+//!
+//! A host records how its admin key was derived in a [`KdfDescriptor`],
+//! such as `hp-admin-crypto/kdf/v1:m=131072,t=3,p=1`, naming the version of
+//! this construction and the costs; see [`hp_admin_crypto::kdf`]. Hosts set
+//! up before descriptors existed have none and use
+//! [`KdfDescriptor::LEGACY`]; new hosts should use
+//! [`KdfDescriptor::RECOMMENDED`].
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
+use zeroize::Zeroizing;
+
+pub use hp_admin_crypto::kdf::{KdfDescriptor, KdfParams};
+
+use crate::error::{Error, Result};
+
+/// HoloHash type prefix of an `AgentPubKey`.
+const AGENT_PUB_KEY_PREFIX: [u8; 3] = [0x84, 0x20, 0x24];
+
+/// Derives the admin Ed25519 seed with [`KdfParams::V1`], as for hosts
+/// without a [`KdfDescriptor`].
+pub fn derive_seed(hc_public_key: &str, email: &str, password: &str) -> Result<[u8; 32]> {
+    derive_seed_with(KdfParams::V1, hc_public_key, email, password)
+}
+
+/// Derives the admin Ed25519 seed with explicit cost parameters.
+pub fn derive_seed_with(
+    params: KdfParams,
+    hc_public_key: &str,
+    email: &str,
+    password: &str,
+) -> Result<[u8; 32]> {
+    let salt = decode_agent_key(hc_public_key)?;
+    let input = kdf_input(email, password)?;
+
+    let params = Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        Some(32),
+    )
+    .map_err(Error::Kdf)?;
+    let mut seed = [0; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(&input, &salt, &mut seed)
+        .map_err(Error::Kdf)?;
+    Ok(seed)
+}
+
+/// Decodes the holochain agent public key to its raw 32 Ed25519 bytes.
+pub fn decode_agent_key(hc_public_key: &str) -> Result<[u8; 32]> {
+    let s = hc_public_key.trim();
+    if let Some(hash) = s.strip_prefix('u') {
+        if let Ok(bytes) = BASE64_URL_SAFE_NO_PAD.decode(hash) {
+            if bytes.len() == 39 && bytes[..3] == AGENT_PUB_KEY_PREFIX {
+                let mut key = [0; 32];
+                key.copy_from_slice(&bytes[3..35]);
+                return Ok(key);
+            }
+        }
+    }
+    hp_admin_crypto::decode_public_key(s)
+        .map(|key| key.to_bytes())
+        .map_err(|_| Error::InvalidAgentKey(hc_public_key.to_owned()))
+}
+
+fn kdf_input(email: &str, password: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let email = email.trim().to_lowercase();
+    if email.is_empty() {
+        return Err(Error::EmptyEmail);
+    }
+    if password.is_empty() {
+        return Err(Error::EmptyPassword);
+    }
+    let mut input = Zeroizing::new(Vec::with_capacity(4 + email.len() + password.len()));
+    input.extend_from_slice(&(email.len() as u32).to_be_bytes());
+    input.extend_from_slice(email.as_bytes());
+    input.extend_from_slice(password.as_bytes());
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AGENT: &str = "uhCAkQC-bNhjhXyh9ievxZMlGtP4iA47YKy09-oFpoJakDYQTXwnH";
+
+    #[test]
+    fn agent_key_forms_give_the_same_salt() {
+        let raw = decode_agent_key(AGENT).unwrap();
+        let plain = hp_admin_crypto::encode_public_key(
+            &hp_admin_crypto::VerifyingKey::from_bytes(&raw).unwrap(),
+        );
+        assert_eq!(decode_agent_key(&plain).unwrap(), raw);
+    }
+
+    #[test]
+    fn rejects_bad_agent_key() {
+        assert!(matches!(
+            decode_agent_key("HcScjcgR7aR9sm5fqjjy"),
+            Err(Error::InvalidAgentKey(_))
+        ));
+    }
+
+    #[test]
+    fn email_is_normalized_and_length_prefixed() {
+        assert_eq!(
+            *kdf_input(" Admin@Example.COM ", "pw").unwrap(),
+            b"\0\0\0\x11admin@example.compw"
+        );
+        assert_ne!(
+            *kdf_input("ab", "c").unwrap(),
+            *kdf_input("a", "bc").unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_empty_credentials() {
+        assert_eq!(kdf_input(" ", "pw").unwrap_err(), Error::EmptyEmail);
+        assert_eq!(kdf_input("a@b", "").unwrap_err(), Error::EmptyPassword);
+    }
+}
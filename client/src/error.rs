@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Error {
+    #[error("invalid HPOS holochain agent public key {0:?}")]
+    InvalidAgentKey(String),
+    #[error("email must not be empty")]
+    EmptyEmail,
+    #[error("password must not be empty")]
+    EmptyPassword,
+    #[error("password is too weak (scores {score} of 4): {reason}")]
+    WeakPassword { score: u8, reason: String },
+    #[error("key derivation failed: {0}")]
+    Kdf(argon2::Error),
+    #[error(transparent)]
+    Payload(#[from] hp_admin_crypto::Error),
+}
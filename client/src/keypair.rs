@@ -0,0 +1,183 @@
+use hp_admin_crypto::delegation::Certificate;
+use hp_admin_crypto::{
+    ContentDigest, Delegation, Endorsement, KdfDescriptor, KeyRotation, MessageSignatureHeaders,
+    Payload, SignatureHeaders, SigningKey, VerifyingKey,
+};
+
+use crate::derive::{derive_seed, derive_seed_with};
+use crate::error::Result;
+
+/// The HP Admin Ed25519 keypair, rebuilt from the HPOS agent public key and
+/// the admin's email and password.
+pub struct HpAdminKeypair {
+    key: SigningKey,
+}
+
+impl HpAdminKeypair {
+    pub fn new(hc_public_key: &str, email: &str, password: &str) -> Result<Self> {
+        let seed = zeroize::Zeroizing::new(derive_seed(hc_public_key, email, password)?);
+        Ok(Self::from_seed(&seed))
+    }
+
+    /// Like [`new`](Self::new), for a host that records how its admin key
+    /// is derived.
+    pub fn with_kdf(
+        kdf: &KdfDescriptor,
+        hc_public_key: &str,
+        email: &str,
+        password: &str,
+    ) -> Result<Self> {
+        let seed = zeroize::Zeroizing::new(derive_seed_with(
+            kdf.params(),
+            hc_public_key,
+            email,
+            password,
+        )?);
+        Ok(Self::from_seed(&seed))
+    }
+
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        HpAdminKeypair {
+            key: SigningKey::from_bytes(seed),
+        }
+    }
+
+    pub fn public_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    /// Signs a call and returns the headers to send with it, including a
+    /// `Content-Digest` of the body with every supported algorithm.
+    ///
+    /// `timestamp` is the current time in seconds since the Unix epoch; it is
+    /// passed in because there is no portable clock on every target.
+    pub fn sign(
+        &self,
+        method: &str,
+        uri: &str,
+        body: &[u8],
+        timestamp: u64,
+    ) -> Result<SignatureHeaders> {
+        let payload = Payload::new(method, uri, body, timestamp)?;
+        Ok(SignatureHeaders::sign(&self.key, &payload)
+            .with_content_digest(&ContentDigest::of(body)))
+    }
+
+    /// Like [`sign`](Self::sign), but also signs a challenge nonce obtained
+    /// from the host's verification server.
+    pub fn sign_with_nonce(
+        &self,
+        method: &str,
+        uri: &str,
+        body: &[u8],
+        timestamp: u64,
+        nonce: &str,
+    ) -> Result<SignatureHeaders> {
+        let payload = Payload::new(method, uri, body, timestamp)?.with_nonce(nonce)?;
+        Ok(SignatureHeaders::sign(&self.key, &payload)
+            .with_content_digest(&ContentDigest::of(body)))
+    }
+
+    /// Signs a call with RFC 9421 HTTP Message Signatures instead, returning
+    /// `Content-Digest`, `Signature-Input` and `Signature`.
+    ///
+    /// Unlike [`sign`](Self::sign), this covers the absolute `target_uri`,
+    /// scheme and host included, and the method exactly as it will be sent.
+    pub fn sign_message(
+        &self,
+        method: &str,
+        target_uri: &str,
+        body: &[u8],
+        created: u64,
+        nonce: Option<&str>,
+    ) -> Result<MessageSignatureHeaders> {
+        Ok(MessageSignatureHeaders::sign(
+            &self.key, method, target_uri, body, created, nonce,
+        )?)
+    }
+
+    /// Signs a certificate that lets `delegate`, say a CI bot's key, make
+    /// calls with one of `methods` to paths under one of `path_prefixes`
+    /// until `expires`, without ever holding this key.
+    pub fn delegate(
+        &self,
+        delegate: &VerifyingKey,
+        methods: &[&str],
+        path_prefixes: &[&str],
+        expires: u64,
+    ) -> Result<Certificate> {
+        Ok(Delegation::new(delegate, methods, path_prefixes, expires)?.sign(&self.key))
+    }
+
+    /// Endorses `new` as this key's successor, for when the admin password
+    /// changes; the host accepts both keys for a while after it receives the
+    /// endorsement.
+    pub fn endorse(&self, new: &VerifyingKey, timestamp: u64) -> Result<Endorsement> {
+        Ok(KeyRotation::new(&self.public_key(), new, timestamp)?.sign(&self.key)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_headers_verify_against_public_key() {
+        let keypair = HpAdminKeypair::from_seed(&[9; 32]);
+        let headers = keypair
+            .sign("get", "/api/v1/status", b"", 1_600_000_000)
+            .unwrap();
+        let payload = headers.payload("GET", "/api/v1/status").unwrap();
+        hp_admin_crypto::verify(
+            &keypair.public_key(),
+            &payload,
+            &headers.signature().unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn delegation_verifies_against_public_key() {
+        let admin = HpAdminKeypair::from_seed(&[9; 32]);
+        let bot = HpAdminKeypair::from_seed(&[10; 32]);
+        let certificate = admin
+            .delegate(
+                &bot.public_key(),
+                &["GET"],
+                &["/api/v1/status"],
+                1_600_000_000,
+            )
+            .unwrap();
+        certificate.verify(&admin.public_key()).unwrap();
+        assert_eq!(certificate.delegation().delegate(), &bot.public_key());
+    }
+
+    #[test]
+    fn endorsement_names_the_new_key() {
+        let old = HpAdminKeypair::from_seed(&[9; 32]);
+        let new = HpAdminKeypair::from_seed(&[10; 32]);
+        let endorsement = old.endorse(&new.public_key(), 1_600_000_000).unwrap();
+        endorsement.verify().unwrap();
+        assert_eq!(endorsement.rotation().old(), &old.public_key());
+        assert_eq!(endorsement.rotation().new_key(), &new.public_key());
+        assert!(old.endorse(&old.public_key(), 1_600_000_000).is_err());
+    }
+
+    #[test]
+    fn message_signature_verifies_against_public_key() {
+        use hp_admin_crypto::rfc9421::{MessageSignature, Request, CONTENT_DIGEST};
+
+        let keypair = HpAdminKeypair::from_seed(&[9; 32]);
+        let target = "https://hpos.example/api/v1/config";
+        let headers = keypair
+            .sign_message("PUT", target, b"{}", 1_600_000_000, None)
+            .unwrap();
+        let request = Request::new("PUT", target)
+            .unwrap()
+            .with_field(CONTENT_DIGEST, &headers.content_digest);
+        MessageSignature::from_headers(&headers.signature_input, &headers.signature)
+            .unwrap()
+            .verify(&keypair.public_key(), &request)
+            .unwrap();
+    }
+}
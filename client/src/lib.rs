@@ -0,0 +1,19 @@
+//! Client half of HP Admin authentication.
+//!
+//! The HP Admin UI rebuilds the admin Ed25519 keypair from the HPOS holochain
+//! agent public key and the admin's email and password (see [`derive`]), then
+//! signs each admin API call in the format defined by [`hp_admin_crypto`].
+//! Since the key is only as strong as the password, [`strength`] estimates
+//! how guessable a new password is.
+
+pub mod derive;
+mod error;
+mod keypair;
+pub mod strength;
+
+pub use derive::{derive_seed, KdfDescriptor, KdfParams};
+pub use error::{Error, Result};
+pub use keypair::HpAdminKeypair;
+
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
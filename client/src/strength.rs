@@ -0,0 +1,767 @@
+//! Estimating how guessable a password is, in the manner of zxcvbn.
+//!
+//! The admin key is derived from the password with a public salt, and the
+//! admin public key is no secret, so anyone can guess passwords offline at
+//! whatever rate the KDF allows. [`estimate`] counts the guesses such an
+//! attacker would need: it finds the cheapest way to build the password out
+//! of guessable pieces (common passwords and words, also capitalized,
+//! reversed or with l33t substitutions; the admin's own email; keyboard
+//! runs, sequences, repeats, years and dates) with brute force for the rest,
+//! and scores the total from 0 to 4 as zxcvbn does.
+//!
+//! The word lists are short, to keep the wasm package small, so the estimate
+//! is generous to words they miss. [`MIN_SCORE`] asks for zxcvbn's top score
+//! to make up for that.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::error::{Error, Result};
+
+/// The lowest score [`check_password`] accepts: zxcvbn's "strong protection
+/// from offline slow-hash scenario", at least 10^10 guesses.
+pub const MIN_SCORE: u8 = 4;
+
+/// Only this many characters are looked at, to bound the work.
+const MAX_LEN: usize = 100;
+
+/// Years are guessed outward from the current year, at least this many.
+const MIN_YEAR_SPACE: i32 = 20;
+
+/// Keyboard rows and diagonals, in US, German and French layouts.
+const KEYBOARDS: &[&str] = &[
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+    "qwertzuiop",
+    "yxcvbnm",
+    "azertyuiop",
+    "qsdfghjklm",
+    "wxcvbn",
+    "1qaz2wsx3edc4rfv5tgb6yhn7ujm8ik,9ol.0p;/",
+    "qazwsxedcrfvtgbyhnujmik,ol.p;/",
+    "zaq1xsw2cde3vfr4bgt5nhy6mju7,ki8.lo9/;p0",
+    "zaqxswcdevfrbgtnhymjukilop",
+];
+
+/// Guesses per key after the first of a keyboard run: starting keys times
+/// the average number of neighbors on a QWERTY keyboard.
+const KEYBOARD_GUESSES_PER_KEY: f64 = 94.0 * 4.6;
+
+/// How guessable a password is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strength {
+    /// From 0, guessable within a thousand tries, to 4, needing at least
+    /// 10^10.
+    pub score: u8,
+    /// Base-10 logarithm of the estimated number of guesses.
+    pub guesses_log10: f64,
+    /// What makes the password weak, if it is and something does.
+    pub warning: Option<&'static str>,
+    /// How to make it stronger; empty for a strong enough password.
+    pub suggestions: Vec<&'static str>,
+}
+
+impl Strength {
+    /// Whether the score is at least [`MIN_SCORE`].
+    pub fn is_strong_enough(&self) -> bool {
+        self.score >= MIN_SCORE
+    }
+}
+
+/// Estimates how guessable `password` is. `user_inputs`, such as the admin
+/// email, count as guessable as the most common passwords.
+pub fn estimate(password: &str, user_inputs: &[&str]) -> Strength {
+    estimate_as_of(password, user_inputs, current_year())
+}
+
+/// [`estimate`], counting recent years and dates from `year`.
+fn estimate_as_of(password: &str, user_inputs: &[&str], year: i32) -> Strength {
+    let chars: Vec<char> = password.chars().take(MAX_LEN).collect();
+    let user_inputs = Dictionary::from_inputs(user_inputs);
+    let mut estimator = Estimator {
+        user_inputs: &user_inputs,
+        year,
+        repeats: HashMap::new(),
+    };
+    let (guesses_log10, sequence) = estimator.most_guessable(&chars);
+    let score = match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    };
+    let mut strength = Strength {
+        score,
+        guesses_log10,
+        warning: None,
+        suggestions: Vec::new(),
+    };
+    if !strength.is_strong_enough() {
+        feedback(&mut strength, &chars, &sequence);
+    }
+    strength
+}
+
+/// Refuses an empty password, or one scoring below [`MIN_SCORE`] given the
+/// admin's `email`, for setting up a host or changing the password.
+pub fn check_password(password: &str, email: &str) -> Result<Strength> {
+    if password.is_empty() {
+        return Err(Error::EmptyPassword);
+    }
+    let strength = estimate(password, &email_inputs(email));
+    if !strength.is_strong_enough() {
+        return Err(Error::WeakPassword {
+            score: strength.score,
+            reason: strength
+                .warning
+                .or(strength.suggestions.first().copied())
+                .unwrap_or_default()
+                .to_owned(),
+        });
+    }
+    Ok(strength)
+}
+
+/// The user inputs for an admin email, to pass to [`estimate`]: the address
+/// and its parts, so `jo.doe@example.com` gives itself, `jo.doe`, `example`,
+/// `com`, `jo` and `doe`.
+pub fn email_inputs(email: &str) -> Vec<&str> {
+    let email = email.trim();
+    let mut inputs = vec![email];
+    if let Some((local, domain)) = email.split_once('@') {
+        inputs.push(local);
+        inputs.extend(domain.split('.'));
+    }
+    inputs.extend(email.split(|c: char| !c.is_alphanumeric()));
+    inputs.retain(|input| !input.is_empty());
+    inputs
+}
+
+/// Ranked words, most common first.
+struct Dictionary {
+    kind: DictionaryKind,
+    ranks: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DictionaryKind {
+    Passwords,
+    Words,
+    UserInputs,
+}
+
+impl Dictionary {
+    fn new<'a>(kind: DictionaryKind, words: impl Iterator<Item = &'a str>) -> Self {
+        let mut ranks = HashMap::new();
+        for word in words {
+            let rank = ranks.len() + 1;
+            ranks.entry(word.to_lowercase()).or_insert(rank);
+        }
+        Dictionary { kind, ranks }
+    }
+
+    fn from_inputs(inputs: &[&str]) -> Self {
+        Dictionary::new(DictionaryKind::UserInputs, inputs.iter().copied())
+    }
+
+    fn builtin() -> &'static [Dictionary; 2] {
+        static BUILTIN: OnceLock<[Dictionary; 2]> = OnceLock::new();
+        BUILTIN.get_or_init(|| {
+            [
+                Dictionary::new(
+                    DictionaryKind::Passwords,
+                    include_str!("strength/passwords.txt").lines(),
+                ),
+                Dictionary::new(
+                    DictionaryKind::Words,
+                    include_str!("strength/words.txt").lines(),
+                ),
+            ]
+        })
+    }
+}
+
+/// A guessable piece of the password, `chars[start..end]`.
+#[derive(Debug, Clone)]
+struct Match {
+    start: usize,
+    end: usize,
+    guesses_log10: f64,
+    pattern: Pattern,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pattern {
+    Dictionary {
+        kind: DictionaryKind,
+        rank: usize,
+        reversed: bool,
+        l33t: bool,
+    },
+    Keyboard,
+    Sequence,
+    Repeat {
+        base_len: usize,
+    },
+    Year,
+    Date,
+    BruteForce,
+}
+
+struct Estimator<'a> {
+    user_inputs: &'a Dictionary,
+    /// The year recent years and dates are counted from.
+    year: i32,
+    /// Guesses for the repeated parts of repeats, which are estimated on
+    /// their own.
+    repeats: HashMap<Vec<char>, f64>,
+}
+
+impl Estimator<'_> {
+    /// The cheapest way to cover `chars` with matches and brute force, as
+    /// zxcvbn counts it: the product of the guesses for each piece, times
+    /// the factorial of their number for the attacker not knowing the
+    /// order.
+    fn most_guessable(&mut self, chars: &[char]) -> (f64, Vec<Match>) {
+        let n = chars.len();
+        if n == 0 {
+            return (0.0, Vec::new());
+        }
+        let mut candidates = self.matches(chars);
+        for start in 0..n {
+            for end in start + 1..=n {
+                candidates.push(Match {
+                    start,
+                    end,
+                    guesses_log10: brute_force(end - start),
+                    pattern: Pattern::BruteForce,
+                });
+            }
+        }
+
+        // best[k][end]: the fewest guesses covering chars[..end] with k
+        // pieces, and the candidate ending it.
+        let mut best: Vec<Vec<Option<(f64, usize)>>> = vec![vec![None; n + 1]; n + 1];
+        best[0][0] = Some((0.0, usize::MAX));
+        candidates.sort_by_key(|m| m.end);
+        for (index, m) in candidates.iter().enumerate() {
+            for k in 1..=n {
+                let Some((before, _)) = best[k - 1][m.start] else {
+                    continue;
+                };
+                let guesses = before + m.guesses_log10;
+                if best[k][m.end].is_none_or(|(current, _)| guesses < current) {
+                    best[k][m.end] = Some((guesses, index));
+                }
+            }
+        }
+        let (guesses_log10, mut k) = (1..=n)
+            .filter_map(|k| Some((best[k][n]?.0 + log10_factorial(k), k)))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .expect("brute force covers every password");
+
+        let mut sequence = Vec::with_capacity(k);
+        let mut end = n;
+        while k > 0 {
+            let (_, index) = best[k][end].expect("on the best path");
+            let m = &candidates[index];
+            end = m.start;
+            sequence.push(m.clone());
+            k -= 1;
+        }
+        sequence.reverse();
+        (guesses_log10, sequence)
+    }
+
+    /// Every guessable piece of `chars` bar brute force.
+    fn matches(&mut self, chars: &[char]) -> Vec<Match> {
+        let lower: Vec<char> = chars.iter().map(|&c| lowercase(c)).collect();
+        let mut matches = Vec::new();
+        let mut add = |start: usize, end: usize, guesses: f64, pattern: Pattern| {
+            // zxcvbn's floor: an attacker tries a few of anything first.
+            let floor = if end - start == 1 { 10.0 } else { 50.0 };
+            matches.push(Match {
+                start,
+                end,
+                guesses_log10: guesses.max(floor).log10(),
+                pattern,
+            });
+        };
+
+        let n = chars.len();
+        for start in 0..n {
+            for end in start + 1..=n {
+                let original = &chars[start..end];
+                let word: String = lower[start..end].iter().collect();
+                let reversed: String = word.chars().rev().collect();
+                let unl33ted: String = word.chars().map(unl33t).collect();
+                let caps = uppercase_variations(original);
+                for dictionary in Dictionary::builtin().iter().chain([self.user_inputs]) {
+                    let kind = dictionary.kind;
+                    if let Some(&rank) = dictionary.ranks.get(&word) {
+                        let pattern = Pattern::Dictionary {
+                            kind,
+                            rank,
+                            reversed: false,
+                            l33t: false,
+                        };
+                        add(start, end, rank as f64 * caps, pattern);
+                    }
+                    if reversed != word {
+                        if let Some(&rank) = dictionary.ranks.get(&reversed) {
+                            let pattern = Pattern::Dictionary {
+                                kind,
+                                rank,
+                                reversed: true,
+                                l33t: false,
+                            };
+                            add(start, end, rank as f64 * caps * 2.0, pattern);
+                        }
+                    }
+                    if unl33ted != word {
+                        if let Some(&rank) = dictionary.ranks.get(&unl33ted) {
+                            let pattern = Pattern::Dictionary {
+                                kind,
+                                rank,
+                                reversed: false,
+                                l33t: true,
+                            };
+                            let l33t = l33t_variations(&lower[start..end]);
+                            add(start, end, rank as f64 * caps * l33t, pattern);
+                        }
+                    }
+                }
+
+                if end - start >= 3 && on_keyboard(&word) {
+                    let guesses = KEYBOARD_GUESSES_PER_KEY * (end - start - 1) as f64;
+                    add(start, end, guesses * caps, Pattern::Keyboard);
+                }
+                if let Some(guesses) = sequence(original) {
+                    add(start, end, guesses, Pattern::Sequence);
+                }
+                if let Some(year) = year(original) {
+                    add(start, end, year_space(year, self.year), Pattern::Year);
+                }
+                if let Some((year, separated)) = date(original) {
+                    let guesses =
+                        365.0 * year_space(year, self.year) * if separated { 4.0 } else { 1.0 };
+                    add(start, end, guesses, Pattern::Date);
+                }
+            }
+
+            for base_len in 1..=(n - start) / 2 {
+                let base = &chars[start..start + base_len];
+                let times = chars[start..]
+                    .chunks_exact(base_len)
+                    .take_while(|chunk| *chunk == base)
+                    .count();
+                if times < 2 {
+                    continue;
+                }
+                let base_guesses = self.repeated(base);
+                add(
+                    start,
+                    start + base_len * times,
+                    base_guesses * times as f64,
+                    Pattern::Repeat { base_len },
+                );
+            }
+        }
+        matches
+    }
+
+    /// Guesses for the part of a repeat that repeats.
+    fn repeated(&mut self, base: &[char]) -> f64 {
+        if let Some(&guesses) = self.repeats.get(base) {
+            return guesses;
+        }
+        let guesses = 10f64.powf(self.most_guessable(base).0);
+        self.repeats.insert(base.to_vec(), guesses);
+        guesses
+    }
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// The letter a l33t substitution stands for; one reading per character.
+fn unl33t(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '8' => 'b',
+        '(' => 'c',
+        '3' => 'e',
+        '6' | '9' => 'g',
+        '1' | '!' | '|' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' | '+' => 't',
+        '2' => 'z',
+        c => c,
+    }
+}
+
+fn brute_force(len: usize) -> f64 {
+    // zxcvbn's ten guesses a character, just above any match of that length.
+    if len == 1 {
+        11f64.log10()
+    } else {
+        len as f64
+    }
+}
+
+fn log10_factorial(k: usize) -> f64 {
+    (2..=k).map(|i| (i as f64).log10()).sum()
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (1..=k).fold(1.0, |c, i| c * (n + 1 - i) as f64 / i as f64)
+}
+
+/// How many ways the attacker tries to capitalize a word: none for all
+/// lower case, two for the usual first, last or all capitals, otherwise
+/// every placement of that many capitals.
+fn uppercase_variations(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_uppercase()).count();
+    let lower = word.iter().filter(|c| c.is_lowercase()).count();
+    let first_only = word.first().is_some_and(|c| c.is_uppercase()) && upper == 1;
+    let last_only = word.last().is_some_and(|c| c.is_uppercase()) && upper == 1;
+    if upper == 0 {
+        1.0
+    } else if lower == 0 || first_only || last_only {
+        2.0
+    } else {
+        (1..=upper.min(lower))
+            .map(|i| binomial(upper + lower, i))
+            .sum()
+    }
+}
+
+/// Like [`uppercase_variations`], for which of each letter's occurrences
+/// were substituted.
+fn l33t_variations(word: &[char]) -> f64 {
+    let mut letters: HashMap<char, (usize, usize)> = HashMap::new();
+    for &c in word {
+        let letter = unl33t(c);
+        let (subbed, plain) = letters.entry(letter).or_default();
+        if letter == c {
+            *plain += 1;
+        } else {
+            *subbed += 1;
+        }
+    }
+    letters
+        .values()
+        .filter(|(subbed, _)| *subbed > 0)
+        .map(|&(subbed, plain)| match plain {
+            0 => 2.0,
+            _ => (1..=subbed.min(plain))
+                .map(|i| binomial(subbed + plain, i))
+                .sum(),
+        })
+        .product()
+}
+
+fn on_keyboard(word: &str) -> bool {
+    KEYBOARDS
+        .iter()
+        .any(|row| row.contains(word) || row.chars().rev().collect::<String>().contains(word))
+}
+
+/// Guesses for a run of three or more digits or letters of one case that
+/// step evenly, like `abc`, `9753` or `ZYX`.
+fn sequence(chars: &[char]) -> Option<f64> {
+    if chars.len() < 3 {
+        return None;
+    }
+    let class = |c: char| {
+        if c.is_ascii_digit() {
+            Some(10.0)
+        } else if c.is_ascii_lowercase() || c.is_ascii_uppercase() {
+            Some(26.0)
+        } else {
+            None
+        }
+    };
+    let base = class(chars[0])?;
+    let step = chars[1] as i32 - chars[0] as i32;
+    let even = chars.windows(2).all(|pair| {
+        pair[1] as i32 - pair[0] as i32 == step
+            && class(pair[1]).is_some()
+            && pair[1].is_ascii_uppercase() == chars[0].is_ascii_uppercase()
+    });
+    if step == 0 || step.abs() > 5 || !even {
+        return None;
+    }
+    let start = if "aAzZ019".contains(chars[0]) {
+        4.0
+    } else {
+        base
+    };
+    let direction = if step > 0 { 1.0 } else { 2.0 };
+    Some(start * direction * chars.len() as f64)
+}
+
+fn year_space(year: i32, now: i32) -> f64 {
+    f64::from((year - now).abs().max(MIN_YEAR_SPACE))
+}
+
+/// The current year, from the browser's clock under wasm.
+#[cfg(target_arch = "wasm32")]
+fn current_year() -> i32 {
+    js_sys::Date::new_0().get_full_year() as i32
+}
+
+/// The current year, to within a day or so: plenty for guessing years.
+#[cfg(not(target_arch = "wasm32"))]
+fn current_year() -> i32 {
+    const SECS_PER_YEAR: u64 = 31_556_952;
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    1970 + (secs / SECS_PER_YEAR) as i32
+}
+
+fn number(chars: &[char]) -> Option<i32> {
+    if chars.is_empty() || !chars.iter().all(char::is_ascii_digit) {
+        return None;
+    }
+    chars.iter().collect::<String>().parse().ok()
+}
+
+/// A four-digit year from 1900 to 2099.
+fn year(chars: &[char]) -> Option<i32> {
+    let year = number(chars).filter(|_| chars.len() == 4)?;
+    (1900..=2099).contains(&year).then_some(year)
+}
+
+/// The year of a date written as digits, like `311299` or `19991231`, or
+/// with separators, like `31/12/1999`, and whether it had separators.
+fn date(chars: &[char]) -> Option<(i32, bool)> {
+    if !(6..=10).contains(&chars.len()) {
+        return None;
+    }
+    let separators: Vec<usize> = (0..chars.len())
+        .filter(|&i| " -./_".contains(chars[i]))
+        .collect();
+    let splits: Vec<[&[char]; 3]> = match separators[..] {
+        [] if chars.len() == 6 => vec![[&chars[..2], &chars[2..4], &chars[4..]]],
+        [] if chars.len() == 8 => vec![
+            [&chars[..2], &chars[2..4], &chars[4..]],
+            [&chars[..4], &chars[4..6], &chars[6..]],
+        ],
+        [first, second] if chars[first] == chars[second] => vec![[
+            &chars[..first],
+            &chars[first + 1..second],
+            &chars[second + 1..],
+        ]],
+        _ => return None,
+    };
+    let year = splits.into_iter().find_map(|[a, b, c]| {
+        let day_month = |x: &[char], y: &[char]| {
+            let (Some(x), Some(y)) = (number(x), number(y)) else {
+                return false;
+            };
+            let valid = |day, month| (1..=31).contains(&day) && (1..=12).contains(&month);
+            valid(x, y) || valid(y, x)
+        };
+        let full_year = |x: &[char]| match x.len() {
+            4 => year(x),
+            2 => number(x).map(|y| if y > 50 { 1900 + y } else { 2000 + y }),
+            _ => None,
+        };
+        if a.len() <= 2 && b.len() <= 2 && day_month(a, b) {
+            if let Some(year) = full_year(c) {
+                return Some(year);
+            }
+        }
+        if a.len() == 4 && c.len() <= 2 && day_month(b, c) {
+            return year(a);
+        }
+        None
+    })?;
+    Some((year, !separators.is_empty()))
+}
+
+/// zxcvbn-style advice, going by the longest guessable piece.
+fn feedback(strength: &mut Strength, chars: &[char], sequence: &[Match]) {
+    const ADD_WORDS: &str = "Add another word or two; uncommon words are better";
+    let longest = sequence
+        .iter()
+        .filter(|m| m.pattern != Pattern::BruteForce)
+        .max_by_key(|m| m.end - m.start);
+    let Some(m) = longest else {
+        strength.suggestions = vec![ADD_WORDS, "Use a longer password"];
+        return;
+    };
+    let mut suggestions = vec![ADD_WORDS];
+    let whole = sequence.len() == 1;
+    strength.warning = Some(match m.pattern {
+        Pattern::Dictionary {
+            kind,
+            rank,
+            reversed,
+            l33t,
+        } => {
+            let word = &chars[m.start..m.end];
+            if word.len() > 1 && word.iter().all(|c| !c.is_lowercase()) {
+                suggestions.push("All capitals are almost as easy to guess as none");
+            } else if word.first().is_some_and(|c| c.is_uppercase()) {
+                suggestions.push("A capital first letter does not help much");
+            }
+            if reversed {
+                suggestions.push("Reversed words are not much harder to guess");
+            }
+            if l33t {
+                suggestions.push("Predictable substitutions like '@' for 'a' do not help much");
+            }
+            match kind {
+                DictionaryKind::UserInputs => "Passwords made from your email are easy to guess",
+                DictionaryKind::Passwords if whole && rank <= 10 => {
+                    "This is one of the ten most common passwords"
+                }
+                DictionaryKind::Passwords if whole && rank <= 100 => {
+                    "This is one of the hundred most common passwords"
+                }
+                DictionaryKind::Passwords => "This is similar to a commonly used password",
+                DictionaryKind::Words if whole => "A word by itself is easy to guess",
+                DictionaryKind::Words => "Common words are easy to guess",
+            }
+        }
+        Pattern::Keyboard => {
+            suggestions.push("Avoid runs of neighboring keys");
+            "Straight rows of keys are easy to guess"
+        }
+        Pattern::Sequence => {
+            suggestions.push("Avoid sequences");
+            "Sequences like abc or 6543 are easy to guess"
+        }
+        Pattern::Repeat { base_len } => {
+            suggestions.push("Avoid repeated words and characters");
+            if base_len == 1 {
+                "Repeats like \"aaa\" are easy to guess"
+            } else {
+                "Repeats like \"abcabcabc\" are only slightly harder to guess than \"abc\""
+            }
+        }
+        Pattern::Year => {
+            suggestions.push("Avoid recent years and years associated with you");
+            "Recent years are easy to guess"
+        }
+        Pattern::Date => {
+            suggestions.push("Avoid dates and years associated with you");
+            "Dates are often easy to guess"
+        }
+        Pattern::BruteForce => unreachable!("filtered out"),
+    });
+    strength.suggestions = suggestions;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(password: &str) -> u8 {
+        estimate(password, &[]).score
+    }
+
+    #[test]
+    fn years_are_counted_from_the_current_year() {
+        assert!(current_year() >= 2026, "{}", current_year());
+        let guesses = |year| estimate_as_of("1999", &[], year).guesses_log10;
+        assert!(guesses(2000) < guesses(2099));
+    }
+
+    #[test]
+    fn common_passwords_and_patterns_score_low() {
+        for password in [
+            "password",
+            "P@ssw0rd",
+            "drowssap",
+            "qwertyuiop",
+            "1qaz2wsx",
+            "abcdefgh",
+            "13579",
+            "aaaaaaaaaaaa",
+            "abcabcabcabc",
+            "1987",
+            "31/12/1987",
+            "Sunshine2024",
+            "holoport123",
+            "zaq1xsw2cde3",
+        ] {
+            assert!(
+                score(password) <= 2,
+                "{password:?} scored {}",
+                score(password)
+            );
+        }
+        assert_eq!(score(""), 0);
+        assert_eq!(score("password"), 0);
+    }
+
+    #[test]
+    fn long_uncommon_passwords_score_high() {
+        for password in [
+            "kZ8#vQ2!mW9@pL",
+            "glacier tumbles orbit velvet",
+            "my cat eats 3 lemons at noon!",
+        ] {
+            assert_eq!(score(password), 4, "{password:?}");
+        }
+        // Guessable pieces only count for so much within a long password.
+        assert!(score("qwerty glacier tumbles orbit") >= 4);
+    }
+
+    #[test]
+    fn the_email_counts_against_the_password() {
+        assert!(score("pauline.dupont1") >= 3);
+        let strength = estimate(
+            "pauline.dupont1",
+            &email_inputs("Pauline.Dupont@example.org"),
+        );
+        assert!(strength.score <= 1, "{strength:?}");
+        assert_eq!(
+            strength.warning,
+            Some("Passwords made from your email are easy to guess")
+        );
+    }
+
+    #[test]
+    fn weak_passwords_come_with_advice() {
+        let strength = estimate("password", &[]);
+        assert_eq!(
+            strength.warning,
+            Some("This is one of the ten most common passwords")
+        );
+        assert!(!strength.suggestions.is_empty());
+        let strength = estimate("Qwertzuiop", &[]);
+        assert_eq!(
+            strength.warning,
+            Some("Straight rows of keys are easy to guess")
+        );
+
+        let strong = estimate("glacier tumbles orbit velvet", &[]);
+        assert_eq!((strong.warning, strong.suggestions.len()), (None, 0));
+    }
+
+    #[test]
+    fn check_refuses_weak_passwords() {
+        assert_eq!(
+            check_password("123456", "admin@example.com"),
+            Err(Error::WeakPassword {
+                score: 0,
+                reason: "This is one of the ten most common passwords".into()
+            })
+        );
+        assert_eq!(
+            check_password("", "admin@example.com"),
+            Err(Error::EmptyPassword)
+        );
+        assert!(check_password("glacier tumbles orbit velvet", "admin@example.com").is_ok());
+    }
+}
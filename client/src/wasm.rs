@@ -0,0 +1,198 @@
+//! JavaScript bindings used by the HP Admin browser UI.
+//!
+//! ```js
+//! import { HpAdminKeypair } from "@holo-host/hp-admin-keypair";
+//!
+//! const keypair = new HpAdminKeypair(hcPublicKey, email, password, kdf);
+//! const headers = keypair.sign("POST", "/api/v1/config", JSON.stringify(config));
+//! await fetch("/api/v1/config", { method: "POST", headers, body });
+//!
+//! // Or with RFC 9421 message signatures, over the absolute URL:
+//! const url = new URL("/api/v1/config", location.href).href;
+//! await fetch(url, { method: "POST", headers: keypair.signMessage("POST", url, body), body });
+//!
+//! // When the admin sets a password, refuse a weak one.
+//! const { acceptable, warning, suggestions } = passwordStrength(password, email);
+//! ```
+
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use hp_admin_crypto::{encode_public_key, KdfDescriptor};
+
+use crate::keypair::HpAdminKeypair;
+use crate::strength;
+
+#[wasm_bindgen(js_name = HpAdminKeypair)]
+pub struct JsHpAdminKeypair(HpAdminKeypair);
+
+#[wasm_bindgen(js_class = HpAdminKeypair)]
+impl JsHpAdminKeypair {
+    /// Derives the admin keypair; this runs Argon2 and takes a moment.
+    ///
+    /// `kdf` is the host's KDF descriptor, if it has one; hosts without one
+    /// use the original parameters.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        hc_public_key: &str,
+        email: &str,
+        password: &str,
+        kdf: Option<String>,
+    ) -> Result<JsHpAdminKeypair, JsError> {
+        let kdf = match kdf {
+            Some(kdf) => kdf.parse()?,
+            None => KdfDescriptor::LEGACY,
+        };
+        Ok(JsHpAdminKeypair(HpAdminKeypair::with_kdf(
+            &kdf,
+            hc_public_key,
+            email,
+            password,
+        )?))
+    }
+
+    /// Signs a call at the current time and returns an object mapping header
+    /// names to values, ready to pass as `fetch` headers.
+    ///
+    /// `body` may be a string, a `Uint8Array`, or `undefined` for no body.
+    pub fn sign(&self, method: &str, uri: &str, body: JsValue) -> Result<Object, JsError> {
+        let signed = self.0.sign(method, uri, &body_bytes(body)?, now())?;
+        headers_object(signed.iter())
+    }
+
+    /// Like `sign`, but also signs a challenge nonce fetched from the host's
+    /// verification server.
+    #[wasm_bindgen(js_name = signWithNonce)]
+    pub fn sign_with_nonce(
+        &self,
+        method: &str,
+        uri: &str,
+        body: JsValue,
+        nonce: &str,
+    ) -> Result<Object, JsError> {
+        let signed = self
+            .0
+            .sign_with_nonce(method, uri, &body_bytes(body)?, now(), nonce)?;
+        headers_object(signed.iter())
+    }
+
+    /// Signs a call at the current time with RFC 9421 HTTP Message
+    /// Signatures and returns the `Content-Digest`, `Signature-Input` and
+    /// `Signature` headers. `url` must be absolute; pass `nonce` to sign a
+    /// challenge nonce.
+    #[wasm_bindgen(js_name = signMessage)]
+    pub fn sign_message(
+        &self,
+        method: &str,
+        url: &str,
+        body: JsValue,
+        nonce: Option<String>,
+    ) -> Result<Object, JsError> {
+        let signed =
+            self.0
+                .sign_message(method, url, &body_bytes(body)?, now(), nonce.as_deref())?;
+        headers_object(signed.iter())
+    }
+
+    /// Signs a delegation certificate letting the base64
+    /// `delegate_public_key` make calls with one of `methods` to paths
+    /// under one of `path_prefixes` for the next `valid_for` seconds. The
+    /// result goes in the delegate's `X-Hpos-Admin-Delegation` header.
+    pub fn delegate(
+        &self,
+        delegate_public_key: &str,
+        methods: Vec<String>,
+        path_prefixes: Vec<String>,
+        valid_for: u32,
+    ) -> Result<String, JsError> {
+        let delegate = hp_admin_crypto::decode_public_key(delegate_public_key)?;
+        let methods: Vec<&str> = methods.iter().map(String::as_str).collect();
+        let path_prefixes: Vec<&str> = path_prefixes.iter().map(String::as_str).collect();
+        let certificate = self.0.delegate(
+            &delegate,
+            &methods,
+            &path_prefixes,
+            now() + u64::from(valid_for),
+        )?;
+        Ok(certificate.to_string())
+    }
+
+    /// Endorses the base64 `new_public_key`, derived from the admin's new
+    /// password, as this key's successor. The result is the body of the
+    /// `POST` that rotates the key on the host.
+    pub fn endorse(&self, new_public_key: &str) -> Result<String, JsError> {
+        let new = hp_admin_crypto::decode_public_key(new_public_key)?;
+        Ok(self.0.endorse(&new, now())?.to_string())
+    }
+
+    /// The admin public key as base64, as it appears in the HPOS config.
+    #[wasm_bindgen(js_name = publicKey)]
+    pub fn public_key(&self) -> String {
+        encode_public_key(&self.0.public_key())
+    }
+}
+
+/// Estimates how guessable a new admin `password` is, counting its
+/// resemblance to `email` against it. Returns `{ score, guessesLog10,
+/// acceptable, warning, suggestions }`: a score from 0 to 4, whether it is
+/// high enough to set up a host with, and advice to show when it is not.
+#[wasm_bindgen(js_name = passwordStrength)]
+pub fn password_strength(password: &str, email: &str) -> Result<Object, JsError> {
+    let estimate = strength::estimate(password, &strength::email_inputs(email));
+    let suggestions: Array = estimate
+        .suggestions
+        .iter()
+        .map(|&s| JsValue::from(s))
+        .collect();
+    let fields: [(&str, JsValue); 5] = [
+        ("score", estimate.score.into()),
+        ("guessesLog10", estimate.guesses_log10.into()),
+        ("acceptable", estimate.is_strong_enough().into()),
+        (
+            "warning",
+            estimate.warning.map_or(JsValue::NULL, JsValue::from),
+        ),
+        ("suggestions", suggestions.into()),
+    ];
+    let object = Object::new();
+    for (name, value) in fields {
+        Reflect::set(&object, &name.into(), &value)
+            .map_err(|_| JsError::new("failed to build strength object"))?;
+    }
+    Ok(object)
+}
+
+/// The KDF descriptor to record with a new host.
+#[wasm_bindgen(js_name = recommendedKdf)]
+pub fn recommended_kdf() -> String {
+    KdfDescriptor::RECOMMENDED.to_string()
+}
+
+fn now() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+fn headers_object<'a>(
+    signed: impl Iterator<Item = (&'static str, &'a str)>,
+) -> Result<Object, JsError> {
+    let headers = Object::new();
+    for (name, value) in signed {
+        Reflect::set(&headers, &name.into(), &value.into())
+            .map_err(|_| JsError::new("failed to build headers object"))?;
+    }
+    Ok(headers)
+}
+
+fn body_bytes(body: JsValue) -> Result<Vec<u8>, JsError> {
+    if body.is_undefined() || body.is_null() {
+        Ok(Vec::new())
+    } else if let Some(text) = body.as_string() {
+        Ok(text.into_bytes())
+    } else if body.is_instance_of::<Uint8Array>() {
+        Ok(Uint8Array::from(body).to_vec())
+    } else {
+        Err(JsError::new(
+            "body must be a string, a Uint8Array or undefined",
+        ))
+    }
+}
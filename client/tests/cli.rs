@@ -0,0 +1,209 @@
+//! Runs `hp-admin-sign` as scripts do, and checks what it prints against
+//! the verification server.
+
+use std::path::Path;
+
+use assert_cmd::Command;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+
+use hp_admin_crypto_server::admins::Admin;
+use hp_admin_crypto_server::verify::{
+    ORIGINAL_HOST, ORIGINAL_METHOD, ORIGINAL_SCHEME, ORIGINAL_URI,
+};
+use hp_admin_crypto_server::{AdminKeys, Verifier};
+use hp_admin_keypair::KdfDescriptor;
+
+const AGENT: &str = "uhCAkQC-bNhjhXyh9ievxZMlGtP4iA47YKy09-oFpoJakDYQTXwnH";
+const EMAIL: &str = "admin@example.com";
+const PASSWORD: &str = "correct horse battery staple";
+/// The key the first KDF v1 test vector derives from the above.
+const ADMIN_PUBLIC_KEY: &str = "+x0B5rH0tqYvbHXHrFYbJu9876199JjQQdWKZ//n32s=";
+
+/// `hp-admin-sign` for the test vector's host and admin, with a clean
+/// environment so that the caller's settings cannot leak in.
+fn sign() -> Command {
+    let mut command = Command::cargo_bin("hp-admin-sign").unwrap();
+    command
+        .env_clear()
+        .env("HPOS_HC_PUBLIC_KEY", AGENT)
+        .env("HP_ADMIN_EMAIL", EMAIL);
+    command
+}
+
+fn stdout(command: &mut Command) -> String {
+    String::from_utf8(command.assert().success().get_output().stdout.clone()).unwrap()
+}
+
+/// A subrequest as nginx would send it for the call, carrying the headers
+/// `hp-admin-sign headers` printed.
+fn subrequest(method: &str, uri: &str, printed: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in [
+        (ORIGINAL_METHOD, method),
+        (ORIGINAL_URI, uri),
+        (ORIGINAL_SCHEME, "https"),
+        (ORIGINAL_HOST, "hpos.example"),
+    ] {
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+    }
+    for line in printed.lines() {
+        let (name, value) = line.split_once(": ").unwrap();
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).unwrap(),
+            HeaderValue::from_str(value).unwrap(),
+        );
+    }
+    headers
+}
+
+fn verifier() -> Verifier {
+    let key = hp_admin_crypto::decode_public_key(ADMIN_PUBLIC_KEY).unwrap();
+    Verifier::new(AdminKeys::new(Admin::new(EMAIL, key).unwrap())).with_body_check(true)
+}
+
+#[test]
+fn password_sources_derive_the_same_key() {
+    let from_env = stdout(sign().env("HP_ADMIN_PASSWORD", PASSWORD).arg("public-key"));
+    assert_eq!(from_env.trim(), ADMIN_PUBLIC_KEY);
+    let from_stdin = stdout(
+        sign()
+            .args(["--password-stdin", "public-key"])
+            .write_stdin(format!("{PASSWORD}\n")),
+    );
+    assert_eq!(from_stdin, from_env);
+}
+
+#[test]
+fn new_passwords_default_to_the_recommended_kdf() {
+    let password = "plinth arbor quasar mellow dune 7";
+    let output = sign()
+        .env("HP_ADMIN_PASSWORD", password)
+        .args(["public-key", "--new"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!("settings.admin.kdf: {}\n", KdfDescriptor::RECOMMENDED)
+    );
+    let recommended = stdout(sign().env("HP_ADMIN_PASSWORD", password).args([
+        "--kdf",
+        "recommended",
+        "public-key",
+    ]));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), recommended);
+
+    // A password change on a host without a descriptor keeps the original
+    // KDF when told to.
+    let legacy = stdout(sign().env("HP_ADMIN_PASSWORD", password).args([
+        "--kdf",
+        "legacy",
+        "public-key",
+        "--new",
+    ]));
+    assert_ne!(legacy, recommended);
+    assert_eq!(
+        legacy,
+        stdout(sign().env("HP_ADMIN_PASSWORD", password).arg("public-key"))
+    );
+}
+
+#[test]
+fn printed_headers_verify() {
+    let dir = tempfile::tempdir().unwrap();
+    let body = br#"{"admin":{"email":"admin@example.com"}}"#;
+    let file = dir.path().join("config.json");
+    std::fs::write(&file, body).unwrap();
+    let data = format!("@{}", file.display());
+
+    let legacy = stdout(sign().env("HP_ADMIN_PASSWORD", PASSWORD).args([
+        "headers",
+        "PUT",
+        "/api/v1/config",
+        "-d",
+        &data,
+    ]));
+    let claims = verifier()
+        .verify(&subrequest("PUT", "/api/v1/config", &legacy), body)
+        .unwrap();
+    assert_eq!(claims.admin.as_deref(), Some(EMAIL));
+
+    let rfc9421 = stdout(
+        sign()
+            .args([
+                "--password-stdin",
+                "--rfc9421",
+                "headers",
+                "PUT",
+                "https://hpos.example/api/v1/config",
+                "-d",
+                &data,
+            ])
+            .write_stdin(format!("{PASSWORD}\n")),
+    );
+    assert!(rfc9421.contains("signature-input: "), "{rfc9421}");
+    verifier()
+        .verify(&subrequest("PUT", "/api/v1/config", &rfc9421), body)
+        .unwrap();
+
+    // A body other than the one signed is caught.
+    assert!(verifier()
+        .verify(&subrequest("PUT", "/api/v1/config", &legacy), b"{}")
+        .is_err());
+}
+
+#[test]
+fn body_from_stdin() {
+    let body = b"{\"admin\":true}";
+    let printed = stdout(
+        sign()
+            .env("HP_ADMIN_PASSWORD", PASSWORD)
+            .args(["headers", "POST", "/api/v1/config", "-d", "@-"])
+            .write_stdin(&body[..]),
+    );
+    verifier()
+        .verify(&subrequest("POST", "/api/v1/config", &printed), body)
+        .unwrap();
+}
+
+#[test]
+fn refuses_two_readers_of_stdin() {
+    let output = sign()
+        .args([
+            "--password-stdin",
+            "headers",
+            "POST",
+            "/api/v1/config",
+            "-d",
+            "@-",
+        ])
+        .write_stdin(format!("{PASSWORD}\n{{}}"))
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "hp-admin-sign: --password-stdin and -d @- cannot both read standard input\n"
+    );
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn reports_unreadable_bodies() {
+    let missing = Path::new("/nonexistent/config.json");
+    let output = sign()
+        .env("HP_ADMIN_PASSWORD", PASSWORD)
+        .args(["headers", "PUT", "/api/v1/config", "-d"])
+        .arg(format!("@{}", missing.display()))
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("hp-admin-sign: cannot read body from /nonexistent/config.json"),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,33 @@
+//! Keeps the checked-in npm package metadata in step with the crate.
+
+use serde_json::Value;
+
+fn package() -> Value {
+    serde_json::from_str(include_str!("../npm/package.json")).unwrap()
+}
+
+#[test]
+fn package_json_matches_cargo_metadata() {
+    let package = package();
+    assert_eq!(package["name"], "@holo-host/hp-admin-keypair");
+    assert_eq!(package["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(package["description"], env!("CARGO_PKG_DESCRIPTION"));
+    assert_eq!(package["license"], env!("CARGO_PKG_LICENSE"));
+    assert_eq!(package["repository"]["url"], env!("CARGO_PKG_REPOSITORY"));
+}
+
+#[test]
+fn entry_points_are_published() {
+    let package = package();
+    let files: Vec<&str> = package["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file.as_str().unwrap())
+        .collect();
+    for entry in ["main", "module", "types"] {
+        let file = package[entry].as_str().unwrap();
+        assert!(files.contains(&file), "{entry} {file} is not in files");
+    }
+    assert!(files.contains(&"hp_admin_keypair_bg.wasm"));
+}
@@ -0,0 +1,58 @@
+//! Checks the published derivation test vectors, which the HP Admin UI and
+//! HPOS tooling use to confirm they derive the same admin key.
+
+use hp_admin_keypair::derive::derive_seed_with;
+use hp_admin_keypair::{derive_seed, HpAdminKeypair, KdfDescriptor};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Vectors {
+    /// KDF descriptor of the hosts the vectors are for, if not the legacy
+    /// one.
+    kdf: Option<String>,
+    vectors: Vec<Vector>,
+}
+
+#[derive(Deserialize)]
+struct Vector {
+    hc_public_key: String,
+    email: String,
+    password: String,
+    seed: String,
+    admin_public_key: String,
+}
+
+#[test]
+fn kdf_v1_vectors() {
+    let vectors: Vectors =
+        serde_json::from_str(include_str!("../test-vectors/kdf-v1.json")).unwrap();
+    for v in vectors.vectors {
+        let seed = derive_seed(&v.hc_public_key, &v.email, &v.password).unwrap();
+        assert_eq!(hex::encode(seed), v.seed, "seed for {:?}", v.email);
+
+        let keypair = HpAdminKeypair::new(&v.hc_public_key, &v.email, &v.password).unwrap();
+        assert_eq!(
+            hp_admin_crypto::encode_public_key(&keypair.public_key()),
+            v.admin_public_key
+        );
+    }
+}
+
+#[test]
+fn kdf_v2_vectors() {
+    let vectors: Vectors =
+        serde_json::from_str(include_str!("../test-vectors/kdf-v2.json")).unwrap();
+    let kdf: KdfDescriptor = vectors.kdf.unwrap().parse().unwrap();
+    assert_eq!(kdf, KdfDescriptor::RECOMMENDED);
+    for v in vectors.vectors {
+        let seed = derive_seed_with(kdf.params(), &v.hc_public_key, &v.email, &v.password).unwrap();
+        assert_eq!(hex::encode(seed), v.seed, "seed for {:?}", v.email);
+
+        let keypair =
+            HpAdminKeypair::with_kdf(&kdf, &v.hc_public_key, &v.email, &v.password).unwrap();
+        assert_eq!(
+            hp_admin_crypto::encode_public_key(&keypair.public_key()),
+            v.admin_public_key
+        );
+    }
+}
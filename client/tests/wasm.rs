@@ -0,0 +1,185 @@
+//! Headless tests of the JavaScript bindings, run under Node with
+//! `wasm-pack test --node client`.
+
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::{Reflect, Uint8Array};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use hp_admin_crypto::{headers, SignatureHeaders};
+use hp_admin_keypair::wasm::{
+    password_strength, recommended_kdf, JsHpAdminKeypair as HpAdminKeypair,
+};
+
+const AGENT: &str = "uhCAkQC-bNhjhXyh9ievxZMlGtP4iA47YKy09-oFpoJakDYQTXwnH";
+
+fn keypair() -> HpAdminKeypair {
+    HpAdminKeypair::new(
+        AGENT,
+        "admin@example.com",
+        "correct horse battery staple",
+        None,
+    )
+    .unwrap()
+}
+
+fn header(object: &js_sys::Object, name: &str) -> String {
+    Reflect::get(object, &name.into())
+        .unwrap()
+        .as_string()
+        .unwrap()
+}
+
+#[wasm_bindgen_test]
+fn public_key_matches_test_vector() {
+    assert_eq!(
+        keypair().public_key(),
+        "+x0B5rH0tqYvbHXHrFYbJu9876199JjQQdWKZ//n32s="
+    );
+}
+
+#[wasm_bindgen_test]
+fn kdf_descriptor_selects_the_parameters() {
+    let keypair = HpAdminKeypair::new(
+        AGENT,
+        "admin@example.com",
+        "correct horse battery staple",
+        Some(recommended_kdf()),
+    )
+    .unwrap();
+    assert_eq!(
+        keypair.public_key(),
+        "OrdcW1zvJNKjR8pQdvwLQO9EkrbXNLQlacUEh8Kplr4="
+    );
+    assert!(HpAdminKeypair::new(AGENT, "admin@example.com", "pw", Some("v9".into())).is_err());
+}
+
+#[wasm_bindgen_test]
+fn password_strength_reports_score_and_advice() {
+    let field = |object: &js_sys::Object, name: &str| Reflect::get(object, &name.into()).unwrap();
+    let weak = password_strength("Admin2024", "admin@example.com").unwrap();
+    assert_eq!(field(&weak, "acceptable"), JsValue::FALSE);
+    assert!(field(&weak, "score").as_f64().unwrap() < 4.0);
+    assert!(field(&weak, "warning").is_string());
+
+    let strong = password_strength("glacier tumbles orbit velvet", "admin@example.com").unwrap();
+    assert_eq!(field(&strong, "acceptable"), JsValue::TRUE);
+    assert_eq!(field(&strong, "score").as_f64(), Some(4.0));
+    assert!(field(&strong, "warning").is_null());
+}
+
+#[wasm_bindgen_test]
+fn signed_headers_verify() {
+    let keypair = keypair();
+    let body = Uint8Array::from(&b"{\"admin\":true}"[..]);
+    let object = keypair.sign("post", "/api/v1/config", body.into()).unwrap();
+
+    let signed = SignatureHeaders {
+        signature: header(&object, headers::SIGNATURE),
+        timestamp: header(&object, headers::TIMESTAMP),
+        body_digest: Some(header(&object, headers::BODY_DIGEST)),
+        content_digest: Some(header(&object, headers::CONTENT_DIGEST)),
+        nonce: None,
+    };
+    let payload = signed.payload("POST", "/api/v1/config").unwrap();
+    assert_eq!(
+        *payload.body_digest(),
+        hp_admin_crypto::BodyDigest::of(b"{\"admin\":true}")
+    );
+    let public_key = hp_admin_crypto::decode_public_key(&keypair.public_key()).unwrap();
+    hp_admin_crypto::verify(&public_key, &payload, &signed.signature().unwrap()).unwrap();
+}
+
+#[wasm_bindgen_test]
+fn string_and_missing_bodies_are_accepted() {
+    let keypair = keypair();
+    assert!(keypair
+        .sign("GET", "/api/v1/status", JsValue::UNDEFINED)
+        .is_ok());
+    assert!(keypair.sign("PUT", "/api/v1/config", "{}".into()).is_ok());
+    assert!(keypair
+        .sign("PUT", "/api/v1/config", JsValue::from(1))
+        .is_err());
+}
+
+#[wasm_bindgen_test]
+fn nonce_is_signed_and_returned() {
+    let object = keypair()
+        .sign_with_nonce(
+            "GET",
+            "/api/v1/status",
+            JsValue::UNDEFINED,
+            "c2VydmVyIG5vbmNl",
+        )
+        .unwrap();
+    assert_eq!(header(&object, headers::NONCE), "c2VydmVyIG5vbmNl");
+}
+
+#[wasm_bindgen_test]
+fn message_signature_verifies() {
+    use hp_admin_crypto::rfc9421::{self, MessageSignature, Request};
+
+    let keypair = keypair();
+    let url = "https://hpos.example/api/v1/config";
+    let object = keypair
+        .sign_message("PUT", url, JsValue::from_str("{}"), None)
+        .unwrap();
+
+    let content_digest = header(&object, rfc9421::CONTENT_DIGEST);
+    assert_eq!(
+        content_digest,
+        hp_admin_crypto::ContentDigest::of(b"{}").to_string()
+    );
+    let signed = MessageSignature::from_headers(
+        &header(&object, rfc9421::SIGNATURE_INPUT),
+        &header(&object, rfc9421::SIGNATURE),
+    )
+    .unwrap();
+    let request = Request::new("PUT", url)
+        .unwrap()
+        .with_field(rfc9421::CONTENT_DIGEST, &content_digest);
+    let public_key = hp_admin_crypto::decode_public_key(&keypair.public_key()).unwrap();
+    signed.verify(&public_key, &request).unwrap();
+}
+
+#[wasm_bindgen_test]
+fn delegation_verifies() {
+    use hp_admin_crypto::DelegationChain;
+
+    let keypair = keypair();
+    let bot = hp_admin_crypto::SigningKey::from_bytes(&[8; 32]).verifying_key();
+    let chain: DelegationChain = keypair
+        .delegate(
+            &hp_admin_crypto::encode_public_key(&bot),
+            vec!["GET".into()],
+            vec!["/api/v1/status".into()],
+            3600,
+        )
+        .unwrap()
+        .parse()
+        .unwrap();
+    let public_key = hp_admin_crypto::decode_public_key(&keypair.public_key()).unwrap();
+    chain.verify(&public_key).unwrap();
+    assert_eq!(chain.delegate(), &bot);
+    assert!(chain.allows("GET", "/api/v1/status"));
+}
+
+#[wasm_bindgen_test]
+fn endorsement_verifies() {
+    use hp_admin_crypto::Endorsement;
+
+    let keypair = keypair();
+    let new = hp_admin_crypto::SigningKey::from_bytes(&[8; 32]).verifying_key();
+    let endorsement: Endorsement = keypair
+        .endorse(&hp_admin_crypto::encode_public_key(&new))
+        .unwrap()
+        .parse()
+        .unwrap();
+    endorsement.verify().unwrap();
+    assert_eq!(
+        hp_admin_crypto::encode_public_key(endorsement.rotation().old()),
+        keypair.public_key()
+    );
+    assert_eq!(endorsement.rotation().new_key(), &new);
+}
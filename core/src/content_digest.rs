@@ -0,0 +1,231 @@
+//! RFC 9530 `Content-Digest`: digests of the request body, one per
+//! algorithm, in the header that reverse proxies and logging tools already
+//! understand.
+//!
+//! ```text
+//! Content-Digest: sha-512=:<base64>:, sha-256=:<base64>:
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::digest::BodyDigest;
+use crate::error::{Error, Result};
+use crate::sf::{self, BareItem, Item, Member};
+
+/// Header name, lower-cased.
+pub const CONTENT_DIGEST: &str = "content-digest";
+
+/// The digest algorithms we produce and check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DigestAlgorithm {
+    Sha512,
+    Sha256,
+}
+
+impl DigestAlgorithm {
+    /// Every supported algorithm, strongest first.
+    pub const ALL: [DigestAlgorithm; 2] = [DigestAlgorithm::Sha512, DigestAlgorithm::Sha256];
+
+    /// The name in the IANA Hash Algorithms for HTTP Digest Fields registry.
+    pub fn name(self) -> &'static str {
+        match self {
+            DigestAlgorithm::Sha512 => "sha-512",
+            DigestAlgorithm::Sha256 => "sha-256",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|alg| alg.name() == name)
+    }
+
+    fn digest(self, body: &[u8]) -> Vec<u8> {
+        match self {
+            DigestAlgorithm::Sha512 => Sha512::digest(body).to_vec(),
+            DigestAlgorithm::Sha256 => Sha256::digest(body).to_vec(),
+        }
+    }
+
+    fn len(self) -> usize {
+        match self {
+            DigestAlgorithm::Sha512 => 64,
+            DigestAlgorithm::Sha256 => 32,
+        }
+    }
+}
+
+/// A parsed `Content-Digest` value.
+///
+/// Parsing keeps the digests of supported algorithms and skips the others,
+/// as RFC 9530 asks; a value with no supported algorithm at all is an
+/// [`Error::UnsupportedDigest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentDigest(Vec<(DigestAlgorithm, Vec<u8>)>);
+
+impl ContentDigest {
+    /// Digests `body` with every supported algorithm.
+    pub fn of(body: &[u8]) -> Self {
+        ContentDigest(
+            DigestAlgorithm::ALL
+                .into_iter()
+                .map(|alg| (alg, alg.digest(body)))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, alg: DigestAlgorithm) -> Option<&[u8]> {
+        self.0
+            .iter()
+            .find(|(a, _)| *a == alg)
+            .map(|(_, digest)| digest.as_slice())
+    }
+
+    /// The SHA-512 digest, which is the one the canonical payload signs.
+    pub fn sha512(&self) -> Option<BodyDigest> {
+        let bytes = self.get(DigestAlgorithm::Sha512)?;
+        Some(BodyDigest::from(<[u8; 64]>::try_from(bytes).ok()?))
+    }
+
+    pub fn algorithms(&self) -> impl Iterator<Item = DigestAlgorithm> + '_ {
+        self.0.iter().map(|(alg, _)| *alg)
+    }
+
+    /// Whether `body` matches every digest.
+    pub fn matches(&self, body: &[u8]) -> bool {
+        self.0
+            .iter()
+            .all(|(alg, digest)| alg.digest(body) == *digest)
+    }
+}
+
+impl From<BodyDigest> for ContentDigest {
+    fn from(digest: BodyDigest) -> Self {
+        ContentDigest(vec![(DigestAlgorithm::Sha512, digest.as_bytes().to_vec())])
+    }
+}
+
+impl fmt::Display for ContentDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (alg, digest)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}={}", alg.name(), BareItem::ByteSeq(digest.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ContentDigest {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |reason: String| Error::encoding("Content-Digest", reason);
+        let members = sf::parse_dictionary(s).map_err(invalid)?;
+        if members.is_empty() {
+            return Err(invalid("no digests".into()));
+        }
+
+        let mut digests = Vec::new();
+        for (name, member) in &members {
+            let Some(alg) = DigestAlgorithm::from_name(name) else {
+                continue;
+            };
+            let Member::Item(Item {
+                bare: BareItem::ByteSeq(digest),
+                ..
+            }) = member
+            else {
+                return Err(invalid(format!("{name} is not a byte sequence")));
+            };
+            if digest.len() != alg.len() {
+                return Err(invalid(format!(
+                    "{name} digest is {} bytes, expected {}",
+                    digest.len(),
+                    alg.len()
+                )));
+            }
+            digests.push((alg, digest.clone()));
+        }
+        if digests.is_empty() {
+            let found: Vec<&str> = members.iter().map(|(name, _)| name.as_str()).collect();
+            return Err(Error::UnsupportedDigest(found.join(", ")));
+        }
+        Ok(ContentDigest(digests))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &[u8] = br#"{"hello": "world"}"#;
+
+    #[test]
+    fn rfc9530_examples() {
+        assert_eq!(
+            ContentDigest::of(HELLO).to_string(),
+            "sha-512=:WZDPaVn/7XgHaAy8pmojAkGWoRx2UFChF41A2svX+TaPm+AbwAgBWnrIiYllu7BNNyealdVLvRwEmTHWXvJwew==:, \
+             sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:"
+        );
+    }
+
+    #[test]
+    fn round_trips_and_matches() {
+        let digest: ContentDigest = ContentDigest::of(HELLO).to_string().parse().unwrap();
+        assert_eq!(digest, ContentDigest::of(HELLO));
+        assert!(digest.matches(HELLO));
+        assert!(!digest.matches(b"{}"));
+        assert_eq!(digest.sha512(), Some(BodyDigest::of(HELLO)));
+    }
+
+    #[test]
+    fn either_algorithm_alone_is_enough() {
+        let sha256: ContentDigest = "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:"
+            .parse()
+            .unwrap();
+        assert!(sha256.matches(HELLO));
+        assert_eq!(sha256.sha512(), None);
+
+        let sha512 = ContentDigest::from(BodyDigest::of(HELLO));
+        assert_eq!(
+            sha512.algorithms().collect::<Vec<_>>(),
+            [DigestAlgorithm::Sha512]
+        );
+        assert!(sha512.matches(HELLO));
+    }
+
+    #[test]
+    fn skips_unknown_algorithms() {
+        let value = format!("md5=:AAAA:, {}", ContentDigest::of(HELLO));
+        let digest: ContentDigest = value.parse().unwrap();
+        assert_eq!(digest, ContentDigest::of(HELLO));
+    }
+
+    #[test]
+    fn names_unsupported_algorithms() {
+        let err = "md5=:AAAA:, unixsum=:AAAA:"
+            .parse::<ContentDigest>()
+            .unwrap_err();
+        assert_eq!(err, Error::UnsupportedDigest("md5, unixsum".into()));
+        assert_eq!(
+            err.to_string(),
+            "Content-Digest has no supported algorithm (found md5, unixsum); use sha-512 or sha-256"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        for value in ["", "sha-256=:AAAA:", "sha-512=abc", "sha-512"] {
+            assert!(
+                matches!(
+                    value.parse::<ContentDigest>(),
+                    Err(Error::InvalidEncoding { .. })
+                ),
+                "{value}"
+            );
+        }
+    }
+}
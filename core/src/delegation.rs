@@ -0,0 +1,480 @@
+//! Delegation certificates: the admin key vouching for a secondary key, such
+//! as a CI bot's, within a limited scope.
+//!
+//! A [`Delegation`] names the delegate public key, the HTTP methods and URI
+//! path prefixes it may use and when it expires. The admin key signs its
+//! canonical serialization into a [`Certificate`], and a delegate may in
+//! turn sign a narrower one for a further key. The delegate sends the whole
+//! [`DelegationChain`], admin-issued certificate first, in the
+//! [`DELEGATION`] header next to a request signed with its own key.
+//!
+//! A request is in scope only if every certificate of the chain allows it,
+//! so a delegate can never hand out more than it was given.
+
+use std::fmt;
+use std::str::FromStr;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use ed25519_dalek::Signer;
+
+use crate::encoding::encode_public_key;
+use crate::error::{Error, Result};
+use crate::payload::is_token;
+use crate::{Signature, SigningKey, VerifyingKey};
+
+/// Tag that opens every canonical delegation.
+pub const DELEGATION_VERSION: &str = "hp-admin-crypto/delegation/v1";
+
+/// Header carrying the delegation chain of a request signed by a delegate:
+/// comma-separated certificates, admin-issued first.
+pub const DELEGATION: &str = "x-hpos-admin-delegation";
+
+/// Longest chain of certificates accepted.
+pub const MAX_CHAIN_LEN: usize = 4;
+
+/// What a delegate key may do, and until when.
+///
+/// Fields are validated on construction, so every `Delegation` has exactly
+/// one canonical serialization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delegation {
+    delegate: VerifyingKey,
+    methods: Vec<String>,
+    path_prefixes: Vec<String>,
+    expires: u64,
+}
+
+impl Delegation {
+    /// Allows `delegate` to make calls with one of `methods` to a path
+    /// starting with one of `path_prefixes`, until `expires` (seconds since
+    /// the Unix epoch).
+    ///
+    /// Methods are upper-cased. A prefix matches whole path segments: `/a/b`
+    /// allows `/a/b` and `/a/b/c` but not `/a/bc`, and `/` allows every path.
+    pub fn new(
+        delegate: &VerifyingKey,
+        methods: &[&str],
+        path_prefixes: &[&str],
+        expires: u64,
+    ) -> Result<Self> {
+        if methods.is_empty() || path_prefixes.is_empty() {
+            return Err(Error::InvalidDelegation(
+                "at least one method and one path prefix are required".into(),
+            ));
+        }
+        let methods = methods
+            .iter()
+            .map(|method| match is_token(method) {
+                true => Ok(method.to_ascii_uppercase()),
+                false => Err(Error::InvalidMethod((*method).to_owned())),
+            })
+            .collect::<Result<_>>()?;
+        let path_prefixes = path_prefixes
+            .iter()
+            .map(|prefix| match is_path_prefix(prefix) {
+                true => Ok((*prefix).to_owned()),
+                false => Err(Error::InvalidDelegation(format!(
+                    "invalid path prefix {prefix:?}"
+                ))),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Delegation {
+            delegate: *delegate,
+            methods,
+            path_prefixes,
+            expires,
+        })
+    }
+
+    pub fn delegate(&self) -> &VerifyingKey {
+        &self.delegate
+    }
+
+    pub fn methods(&self) -> &[String] {
+        &self.methods
+    }
+
+    pub fn path_prefixes(&self) -> &[String] {
+        &self.path_prefixes
+    }
+
+    /// Expiry time in seconds since the Unix epoch.
+    pub fn expires(&self) -> u64 {
+        self.expires
+    }
+
+    /// Whether a call with `method` to `uri` is in scope. `uri` may be a
+    /// path with optional query, as the canonical payload signs it, or an
+    /// absolute URI, as RFC 9421 signs it.
+    ///
+    /// Paths with dot segments or percent-encoded dots and slashes are never
+    /// in scope, since the upstream may resolve them to a path outside it.
+    pub fn allows(&self, method: &str, uri: &str) -> bool {
+        let Some(path) = path_of(uri) else {
+            return false;
+        };
+        self.methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+            && self
+                .path_prefixes
+                .iter()
+                .any(|prefix| prefix_matches(prefix, path))
+    }
+
+    /// The byte-exact canonical serialization that gets signed.
+    ///
+    /// It is the version tag followed by the base64 delegate key, the
+    /// space-separated methods, the space-separated path prefixes and the
+    /// decimal expiry time, each terminated by a single `\n`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        format!(
+            "{DELEGATION_VERSION}\n{}\n{}\n{}\n{}\n",
+            encode_public_key(&self.delegate),
+            self.methods.join(" "),
+            self.path_prefixes.join(" "),
+            self.expires
+        )
+        .into_bytes()
+    }
+
+    /// Parses a canonical serialization back, rejecting anything that would
+    /// not serialize to the same bytes.
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let invalid = |reason: &str| Error::encoding("delegation", reason);
+        let text = std::str::from_utf8(bytes).map_err(|_| invalid("not UTF-8"))?;
+        let fields: Vec<&str> = text
+            .strip_suffix('\n')
+            .ok_or_else(|| invalid("missing final line feed"))?
+            .split('\n')
+            .collect();
+        let [version, delegate, methods, path_prefixes, expires] = fields[..] else {
+            return Err(invalid("expected five lines"));
+        };
+        if version != DELEGATION_VERSION {
+            return Err(invalid("unknown version"));
+        }
+        let expires = expires
+            .parse()
+            .map_err(|_| invalid("expiry is not a decimal number"))?;
+        let delegation = Delegation::new(
+            &crate::decode_public_key(delegate)?,
+            &methods.split(' ').collect::<Vec<_>>(),
+            &path_prefixes.split(' ').collect::<Vec<_>>(),
+            expires,
+        )?;
+        if delegation.to_bytes() != bytes {
+            return Err(invalid("not in canonical form"));
+        }
+        Ok(delegation)
+    }
+
+    /// Signs the delegation with `issuer`, the admin key or the key of a
+    /// delegate passing on part of its own scope.
+    pub fn sign(self, issuer: &SigningKey) -> Certificate {
+        let signature = issuer.sign(&self.to_bytes());
+        Certificate {
+            delegation: self,
+            signature,
+        }
+    }
+}
+
+/// A delegation and its issuer's signature.
+///
+/// As text, the base64 canonical delegation followed by the 64-byte
+/// signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    delegation: Delegation,
+    signature: Signature,
+}
+
+impl Certificate {
+    pub fn delegation(&self) -> &Delegation {
+        &self.delegation
+    }
+
+    pub fn verify(&self, issuer: &VerifyingKey) -> Result<()> {
+        issuer
+            .verify_strict(&self.delegation.to_bytes(), &self.signature)
+            .map_err(|_| Error::BadSignature)
+    }
+}
+
+impl fmt::Display for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = self.delegation.to_bytes();
+        bytes.extend_from_slice(&self.signature.to_bytes());
+        f.write_str(&BASE64_STANDARD.encode(bytes))
+    }
+}
+
+impl FromStr for Certificate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut bytes = BASE64_STANDARD
+            .decode(s.trim())
+            .map_err(|e| Error::encoding("delegation", e))?;
+        if bytes.len() < Signature::BYTE_SIZE {
+            return Err(Error::encoding("delegation", "too short"));
+        }
+        let signature = bytes.split_off(bytes.len() - Signature::BYTE_SIZE);
+        Ok(Certificate {
+            delegation: Delegation::from_bytes(&bytes)?,
+            signature: Signature::from_slice(&signature)
+                .map_err(|e| Error::encoding("delegation", e))?,
+        })
+    }
+}
+
+/// Certificates leading from the admin key to the key that signed a
+/// request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelegationChain(Vec<Certificate>);
+
+impl DelegationChain {
+    /// A chain of certificates, admin-issued first, each issued by the
+    /// delegate of the one before.
+    pub fn new(certificates: Vec<Certificate>) -> Result<Self> {
+        if certificates.is_empty() || certificates.len() > MAX_CHAIN_LEN {
+            return Err(Error::InvalidDelegation(format!(
+                "chain must hold 1 to {MAX_CHAIN_LEN} certificates"
+            )));
+        }
+        Ok(DelegationChain(certificates))
+    }
+
+    /// Extends the chain with a certificate issued by its delegate.
+    pub fn push(mut self, certificate: Certificate) -> Result<Self> {
+        self.0.push(certificate);
+        Self::new(self.0)
+    }
+
+    pub fn certificates(&self) -> &[Certificate] {
+        &self.0
+    }
+
+    /// The key the chain finally vouches for.
+    pub fn delegate(&self) -> &VerifyingKey {
+        self.0
+            .last()
+            .expect("chain is never empty")
+            .delegation
+            .delegate()
+    }
+
+    /// Checks every signature back to `admin_key`. Expiry and scope are
+    /// left to the caller, through [`expires`](Self::expires) and
+    /// [`allows`](Self::allows).
+    pub fn verify(&self, admin_key: &VerifyingKey) -> Result<()> {
+        let mut issuer = admin_key;
+        for certificate in &self.0 {
+            certificate.verify(issuer)?;
+            issuer = certificate.delegation.delegate();
+        }
+        Ok(())
+    }
+
+    /// The earliest expiry time of the chain.
+    pub fn expires(&self) -> u64 {
+        self.0
+            .iter()
+            .map(|certificate| certificate.delegation.expires)
+            .min()
+            .expect("chain is never empty")
+    }
+
+    /// Whether every certificate allows a call with `method` to `uri`.
+    pub fn allows(&self, method: &str, uri: &str) -> bool {
+        self.0
+            .iter()
+            .all(|certificate| certificate.delegation.allows(method, uri))
+    }
+}
+
+impl fmt::Display for DelegationChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, certificate) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{certificate}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DelegationChain {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let certificates = s.split(',').map(str::parse).collect::<Result<_>>()?;
+        Self::new(certificates)
+    }
+}
+
+fn is_path_prefix(prefix: &str) -> bool {
+    prefix.starts_with('/')
+        && prefix.bytes().all(|b| b.is_ascii_graphic())
+        && !prefix.contains(['?', '#'])
+}
+
+/// The path of an origin-form or absolute URI, unless it could resolve
+/// somewhere else: paths with dot segments, backslashes or percent-encoded
+/// dots and slashes give `None`.
+pub fn path_of(uri: &str) -> Option<&str> {
+    let uri = match uri.split_once("://") {
+        Some((_, rest)) => &rest[rest.find('/')?..],
+        None => uri,
+    };
+    let path = uri.split(['?', '#']).next().unwrap_or_default();
+    let lower = path.to_ascii_lowercase();
+    let dot_segment = path
+        .split('/')
+        .any(|segment| segment == "." || segment == "..");
+    let encoded = lower.contains("%2e") || lower.contains("%2f") || lower.contains("%5c");
+    (path.starts_with('/') && !dot_segment && !encoded && !path.contains('\\')).then_some(path)
+}
+
+fn prefix_matches(prefix: &str, path: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || prefix.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admin() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn bot() -> SigningKey {
+        SigningKey::from_bytes(&[8; 32])
+    }
+
+    fn status_only(expires: u64) -> Delegation {
+        Delegation::new(
+            &bot().verifying_key(),
+            &["get", "HEAD"],
+            &["/api/v1/status"],
+            expires,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn canonical_bytes() {
+        assert_eq!(
+            String::from_utf8(status_only(1_700_000_000).to_bytes()).unwrap(),
+            format!(
+                "hp-admin-crypto/delegation/v1\n{}\nGET HEAD\n/api/v1/status\n1700000000\n",
+                encode_public_key(&bot().verifying_key())
+            )
+        );
+    }
+
+    #[test]
+    fn scope() {
+        let delegation = status_only(0);
+        assert!(delegation.allows("GET", "/api/v1/status"));
+        assert!(delegation.allows("head", "/api/v1/status/disk?verbose"));
+        assert!(delegation.allows("GET", "https://hpos.example/api/v1/status"));
+        assert!(!delegation.allows("PUT", "/api/v1/status"));
+        assert!(!delegation.allows("GET", "/api/v1/statusx"));
+        assert!(!delegation.allows("GET", "/api/v1/config"));
+        assert!(!delegation.allows("GET", "/api/v1/status/../config"));
+        assert!(!delegation.allows("GET", "/api/v1/status/%2e%2e/config"));
+        assert!(!delegation.allows("GET", "/api/v1/status%2F..%2Fconfig"));
+        assert!(!delegation.allows("GET", "https://hpos.example"));
+
+        let everything = Delegation::new(&bot().verifying_key(), &["GET"], &["/"], 0).unwrap();
+        assert!(everything.allows("GET", "/anything/at/all"));
+    }
+
+    #[test]
+    fn rejects_invalid_scopes() {
+        let key = bot().verifying_key();
+        assert!(Delegation::new(&key, &[], &["/"], 0).is_err());
+        assert!(Delegation::new(&key, &["GET"], &[], 0).is_err());
+        assert!(Delegation::new(&key, &["GET POST"], &["/"], 0).is_err());
+        for prefix in ["api", "/a b", "/a?b", ""] {
+            assert!(
+                Delegation::new(&key, &["GET"], &[prefix], 0).is_err(),
+                "{prefix}"
+            );
+        }
+    }
+
+    #[test]
+    fn certificate_round_trips_and_verifies() {
+        let certificate = status_only(1_700_000_000).sign(&admin());
+        let parsed: Certificate = certificate.to_string().parse().unwrap();
+        assert_eq!(parsed, certificate);
+        parsed.verify(&admin().verifying_key()).unwrap();
+        assert_eq!(
+            parsed.verify(&bot().verifying_key()),
+            Err(Error::BadSignature)
+        );
+    }
+
+    #[test]
+    fn chain_verifies_back_to_the_admin_key() {
+        let deploy = SigningKey::from_bytes(&[9; 32]);
+        let chain = DelegationChain::new(vec![status_only(2_000).sign(&admin())])
+            .unwrap()
+            .push(
+                Delegation::new(
+                    &deploy.verifying_key(),
+                    &["GET", "PUT"],
+                    &["/api/v1/status/disk"],
+                    1_000,
+                )
+                .unwrap()
+                .sign(&bot()),
+            )
+            .unwrap();
+
+        let parsed: DelegationChain = chain.to_string().parse().unwrap();
+        assert_eq!(parsed, chain);
+        parsed.verify(&admin().verifying_key()).unwrap();
+        assert_eq!(parsed.delegate(), &deploy.verifying_key());
+        assert_eq!(parsed.expires(), 1_000);
+
+        // Scope is the intersection of every certificate.
+        assert!(parsed.allows("GET", "/api/v1/status/disk"));
+        assert!(!parsed.allows("PUT", "/api/v1/status/disk"));
+        assert!(!parsed.allows("GET", "/api/v1/status"));
+
+        // A chain that does not start at the admin key does not verify.
+        let orphan = DelegationChain::new(vec![chain.certificates()[1].clone()]).unwrap();
+        assert_eq!(
+            orphan.verify(&admin().verifying_key()),
+            Err(Error::BadSignature)
+        );
+    }
+
+    #[test]
+    fn rejects_overlong_chains() {
+        let certificate = status_only(0).sign(&admin());
+        let value = vec![certificate.to_string(); MAX_CHAIN_LEN + 1].join(", ");
+        assert!(matches!(
+            value.parse::<DelegationChain>(),
+            Err(Error::InvalidDelegation(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_non_canonical_certificates() {
+        let mut bytes = status_only(0).to_bytes();
+        bytes.insert(bytes.len() - 2, b'0');
+        let mut forged = bytes.clone();
+        forged.extend_from_slice(&admin().sign(&bytes).to_bytes());
+        assert!(BASE64_STANDARD
+            .encode(forged)
+            .parse::<Certificate>()
+            .is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+use std::str::FromStr;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use sha2::{Digest, Sha512};
+
+use crate::error::{Error, Result};
+
+/// SHA-512 digest of an HTTP request body.
+///
+/// The textual form is standard padded base64, which is how the digest
+/// appears both in the canonical payload and on the wire.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BodyDigest([u8; 64]);
+
+impl BodyDigest {
+    pub fn of(body: &[u8]) -> Self {
+        BodyDigest(Sha512::digest(body).into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 64] {
+        &self.0
+    }
+}
+
+impl From<[u8; 64]> for BodyDigest {
+    fn from(bytes: [u8; 64]) -> Self {
+        BodyDigest(bytes)
+    }
+}
+
+impl fmt::Display for BodyDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&BASE64_STANDARD.encode(self.0))
+    }
+}
+
+impl fmt::Debug for BodyDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BodyDigest({self})")
+    }
+}
+
+impl FromStr for BodyDigest {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = BASE64_STANDARD
+            .decode(s)
+            .map_err(|e| Error::encoding("body digest", e))?;
+        let bytes = <[u8; 64]>::try_from(bytes).map_err(|b| {
+            Error::encoding("body digest", format!("{} bytes, expected 64", b.len()))
+        })?;
+        Ok(BodyDigest(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_body_digest() {
+        assert_eq!(
+            BodyDigest::of(b"").to_string(),
+            "z4PhNX7vuL3xVChQ1m2AB9Yg5AULVxXcg/SpIdNs6c5H0NE8XYXysP+DGNKHfuwvY7kxvUdBeoGlODJ6+SfaPg=="
+        );
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let digest = BodyDigest::of(b"{\"admin\":true}");
+        assert_eq!(digest.to_string().parse::<BodyDigest>().unwrap(), digest);
+    }
+
+    #[test]
+    fn rejects_wrong_length() {
+        assert!(matches!(
+            "AAAA".parse::<BodyDigest>(),
+            Err(Error::InvalidEncoding { .. })
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+//! Text encodings for keys and signatures as they appear in headers and in
+//! the HPOS config.
+
+use base64::prelude::{Engine, BASE64_STANDARD, BASE64_STANDARD_NO_PAD, BASE64_URL_SAFE_NO_PAD};
+use ed25519_dalek::{Signature, VerifyingKey};
+
+use crate::error::{Error, Result};
+
+/// Encodes a public key as standard padded base64.
+pub fn encode_public_key(key: &VerifyingKey) -> String {
+    BASE64_STANDARD.encode(key.as_bytes())
+}
+
+/// Decodes a base64 Ed25519 public key.
+///
+/// Keys are written by several generations of HPOS tooling, so padded,
+/// unpadded and URL-safe base64 are all accepted.
+pub fn decode_public_key(s: &str) -> Result<VerifyingKey> {
+    let s = s.trim();
+    let bytes = BASE64_STANDARD
+        .decode(s)
+        .or_else(|_| BASE64_STANDARD_NO_PAD.decode(s))
+        .or_else(|_| BASE64_URL_SAFE_NO_PAD.decode(s.trim_end_matches('=')))
+        .map_err(|e| Error::encoding("public key", e))?;
+    let bytes = <[u8; 32]>::try_from(bytes)
+        .map_err(|b| Error::encoding("public key", format!("{} bytes, expected 32", b.len())))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| Error::encoding("public key", e))
+}
+
+/// Encodes a signature as standard padded base64.
+pub fn encode_signature(signature: &Signature) -> String {
+    BASE64_STANDARD.encode(signature.to_bytes())
+}
+
+/// Decodes a standard padded base64 signature.
+pub fn decode_signature(s: &str) -> Result<Signature> {
+    let bytes = BASE64_STANDARD
+        .decode(s.trim())
+        .map_err(|e| Error::encoding("signature", e))?;
+    Signature::from_slice(&bytes).map_err(|e| Error::encoding("signature", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn public_key_accepts_all_base64_flavours() {
+        let key = SigningKey::from_bytes(&[1; 32]).verifying_key();
+        let bytes = key.as_bytes();
+        for encoded in [
+            BASE64_STANDARD.encode(bytes),
+            BASE64_STANDARD_NO_PAD.encode(bytes),
+            BASE64_URL_SAFE_NO_PAD.encode(bytes),
+        ] {
+            assert_eq!(decode_public_key(&encoded).unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn public_key_rejects_wrong_length() {
+        assert!(decode_public_key("AAAA").is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong while building, encoding or verifying a
+/// signed payload.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Error {
+    #[error("invalid HTTP method {0:?}")]
+    InvalidMethod(String),
+    #[error("invalid request URI {0:?}")]
+    InvalidUri(String),
+    #[error("invalid timestamp {0:?}")]
+    InvalidTimestamp(String),
+    #[error("invalid nonce {0:?}")]
+    InvalidNonce(String),
+    #[error("invalid {what} encoding: {reason}")]
+    InvalidEncoding { what: &'static str, reason: String },
+    #[error("cannot sign or verify message component {0}")]
+    UnsupportedComponent(String),
+    #[error("request has no {0} component")]
+    MissingComponent(String),
+    #[error("unsupported signature algorithm {0:?}")]
+    UnsupportedAlgorithm(String),
+    #[error("Content-Digest has no supported algorithm (found {0}); use sha-512 or sha-256")]
+    UnsupportedDigest(String),
+    #[error("invalid delegation: {0}")]
+    InvalidDelegation(String),
+    #[error("invalid key rotation: {0}")]
+    InvalidRotation(String),
+    #[error("invalid KDF descriptor {descriptor:?}: {reason}")]
+    InvalidKdf {
+        descriptor: String,
+        reason: &'static str,
+    },
+    #[error("signature does not match payload")]
+    BadSignature,
+}
+
+impl Error {
+    pub(crate) fn encoding(what: &'static str, reason: impl ToString) -> Self {
+        Error::InvalidEncoding {
+            what,
+            reason: reason.to_string(),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! HTTP headers that carry a signed payload from the client to the server.
+//!
+//! Header names are lower-case, which is how the `http` crate and HTTP/2
+//! spell them; HTTP/1 peers compare them case-insensitively.
+
+use crate::content_digest::ContentDigest;
+use crate::digest::BodyDigest;
+use crate::encoding::{decode_signature, encode_signature};
+use crate::error::{Error, Result};
+use crate::payload::Payload;
+use crate::{Signature, SigningKey};
+
+/// Base64 Ed25519 signature over the canonical payload.
+pub const SIGNATURE: &str = "x-hpos-admin-signature";
+/// Signing time, in decimal seconds since the Unix epoch.
+pub const TIMESTAMP: &str = "x-hpos-admin-timestamp";
+/// Base64 SHA-512 digest of the request body.
+///
+/// Superseded by [`CONTENT_DIGEST`]; still sent for servers that predate it.
+pub const BODY_DIGEST: &str = "x-hpos-admin-body-digest";
+pub use crate::content_digest::CONTENT_DIGEST;
+/// Server-issued challenge nonce, present only when one was signed.
+pub const NONCE: &str = "x-hpos-admin-nonce";
+
+/// Header values produced by the client for one signed request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureHeaders {
+    pub signature: String,
+    pub timestamp: String,
+    pub body_digest: Option<String>,
+    pub content_digest: Option<String>,
+    pub nonce: Option<String>,
+}
+
+impl SignatureHeaders {
+    /// Signs `payload` and renders the headers that describe it.
+    ///
+    /// The payload only knows its body by SHA-512 digest, so that is all
+    /// `Content-Digest` carries; use [`with_content_digest`] to add the
+    /// other algorithms when the body is at hand.
+    ///
+    /// [`with_content_digest`]: Self::with_content_digest
+    pub fn sign(key: &SigningKey, payload: &Payload) -> Self {
+        SignatureHeaders {
+            signature: encode_signature(&crate::sign(key, payload)),
+            timestamp: payload.timestamp().to_string(),
+            body_digest: Some(payload.body_digest().to_string()),
+            content_digest: Some(ContentDigest::from(*payload.body_digest()).to_string()),
+            nonce: payload.nonce().map(str::to_owned),
+        }
+    }
+
+    /// Replaces `Content-Digest` with `digest`, which must be of the signed
+    /// body.
+    pub fn with_content_digest(mut self, digest: &ContentDigest) -> Self {
+        self.content_digest = Some(digest.to_string());
+        self
+    }
+
+    /// Rebuilds the signed payload from the headers and the method and URI
+    /// of the original request.
+    ///
+    /// The signed digest comes from `X-Hpos-Admin-Body-Digest` or, failing
+    /// that, from the SHA-512 entry of `Content-Digest`; when both are sent
+    /// they must agree.
+    pub fn payload(&self, method: &str, uri: &str) -> Result<Payload> {
+        let timestamp = parse_timestamp(&self.timestamp)?;
+        let body_digest = self.signed_digest()?;
+        let payload = Payload::with_digest(method, uri, body_digest, timestamp)?;
+        match &self.nonce {
+            Some(nonce) => payload.with_nonce(nonce),
+            None => Ok(payload),
+        }
+    }
+
+    fn signed_digest(&self) -> Result<BodyDigest> {
+        let content_digest = match &self.content_digest {
+            Some(value) => Some(value.parse::<ContentDigest>()?.sha512()),
+            None => None,
+        };
+        match (&self.body_digest, content_digest) {
+            (Some(body_digest), content_digest) => {
+                let body_digest: BodyDigest = body_digest.trim().parse()?;
+                match content_digest {
+                    Some(Some(sha512)) if sha512 != body_digest => Err(Error::encoding(
+                        "Content-Digest",
+                        format!("sha-512 disagrees with {BODY_DIGEST}"),
+                    )),
+                    _ => Ok(body_digest),
+                }
+            }
+            (None, Some(Some(sha512))) => Ok(sha512),
+            (None, Some(None)) => Err(Error::encoding(
+                "Content-Digest",
+                "X-Hpos-Admin signatures need a sha-512 digest",
+            )),
+            (None, None) => Err(Error::encoding(
+                "body digest",
+                format!("neither {CONTENT_DIGEST} nor {BODY_DIGEST} was sent"),
+            )),
+        }
+    }
+
+    pub fn signature(&self) -> Result<Signature> {
+        decode_signature(&self.signature)
+    }
+
+    /// `(name, value)` pairs, ready to be attached to a request.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            (SIGNATURE, Some(self.signature.as_str())),
+            (TIMESTAMP, Some(self.timestamp.as_str())),
+            (BODY_DIGEST, self.body_digest.as_deref()),
+            (CONTENT_DIGEST, self.content_digest.as_deref()),
+            (NONCE, self.nonce.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+    }
+}
+
+/// Plain decimal only: no sign, no whitespace, no leading `+`.
+fn parse_timestamp(s: &str) -> Result<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::InvalidTimestamp(s.to_owned()));
+    }
+    s.parse().map_err(|_| Error::InvalidTimestamp(s.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_round_trip() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let payload = Payload::new("PUT", "/api/v1/config", b"{\"a\":1}", 1_600_000_000).unwrap();
+        let headers = SignatureHeaders::sign(&key, &payload);
+
+        let rebuilt = headers.payload("put", "/api/v1/config").unwrap();
+        assert_eq!(rebuilt, payload);
+        crate::verify(
+            &key.verifying_key(),
+            &rebuilt,
+            &headers.signature().unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn nonce_header_only_when_signed() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let payload = Payload::new("GET", "/api/v1/status", b"", 1_600_000_000).unwrap();
+        let plain = SignatureHeaders::sign(&key, &payload);
+        assert!(plain.iter().all(|(name, _)| name != NONCE));
+
+        let challenged = SignatureHeaders::sign(&key, &payload.with_nonce("abc").unwrap());
+        assert!(challenged
+            .iter()
+            .any(|(name, value)| (name, value) == (NONCE, "abc")));
+        assert_ne!(challenged.signature, plain.signature);
+        assert_eq!(
+            challenged.payload("GET", "/api/v1/status").unwrap().nonce(),
+            Some("abc")
+        );
+    }
+
+    #[test]
+    fn either_digest_header_will_do() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let payload = Payload::new("PUT", "/api/v1/config", b"{}", 1_600_000_000).unwrap();
+        let headers =
+            SignatureHeaders::sign(&key, &payload).with_content_digest(&ContentDigest::of(b"{}"));
+
+        let content_only = SignatureHeaders {
+            body_digest: None,
+            ..headers.clone()
+        };
+        assert_eq!(
+            content_only.payload("PUT", "/api/v1/config"),
+            Ok(payload.clone())
+        );
+
+        let legacy_only = SignatureHeaders {
+            content_digest: None,
+            ..headers.clone()
+        };
+        assert_eq!(legacy_only.payload("PUT", "/api/v1/config"), Ok(payload));
+
+        let neither = SignatureHeaders {
+            body_digest: None,
+            content_digest: None,
+            ..headers.clone()
+        };
+        assert!(neither.payload("PUT", "/api/v1/config").is_err());
+
+        let disagreeing = SignatureHeaders {
+            content_digest: Some(ContentDigest::of(b"{ }").to_string()),
+            ..headers.clone()
+        };
+        assert!(disagreeing.payload("PUT", "/api/v1/config").is_err());
+
+        let sha256_only = SignatureHeaders {
+            body_digest: None,
+            content_digest: Some(
+                ContentDigest::of(b"{}")
+                    .to_string()
+                    .split(", ")
+                    .nth(1)
+                    .unwrap()
+                    .to_owned(),
+            ),
+            ..headers
+        };
+        assert!(sha256_only.payload("PUT", "/api/v1/config").is_err());
+    }
+
+    #[test]
+    fn rejects_non_decimal_timestamp() {
+        for timestamp in ["", "+1", "-1", " 1", "0x10", "99999999999999999999"] {
+            assert_eq!(
+                parse_timestamp(timestamp),
+                Err(Error::InvalidTimestamp(timestamp.to_owned()))
+            );
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Descriptors of how a host's admin key is derived from the password.
+//!
+//! The derivation itself, Argon2id over the admin's email and password, is
+//! done by the signing client; a host records only which version of it and
+//! which costs its admin key was made with, as a [`KdfDescriptor`] such as
+//! `hp-admin-crypto/kdf/v1:m=131072,t=3,p=1`. Hosts set up before
+//! descriptors existed have none and use [`KdfDescriptor::LEGACY`]; new
+//! hosts should use [`KdfDescriptor::RECOMMENDED`]. The server checks the
+//! descriptor in the HPOS config so that a host cannot record one that no
+//! client would accept.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+/// Argon2id cost parameters.
+///
+/// These are part of the derivation: changing any of them changes every
+/// admin key, so each set is fixed forever under its own name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory cost in KiB.
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// 64 MiB, 2 passes, 1 lane: slow enough to hurt offline guessing, fast
+    /// enough to run in a browser tab.
+    pub const V1: KdfParams = KdfParams {
+        memory_kib: 64 * 1024,
+        iterations: 2,
+        parallelism: 1,
+    };
+
+    /// 128 MiB, 3 passes, 1 lane: three times the work of [`V1`](Self::V1),
+    /// for new hosts.
+    pub const V2: KdfParams = KdfParams {
+        memory_kib: 128 * 1024,
+        iterations: 3,
+        parallelism: 1,
+    };
+
+    /// The most a descriptor may ask for, so that a host cannot make the
+    /// browser allocate gigabytes or spin for minutes.
+    const MAX: KdfParams = KdfParams {
+        memory_kib: 1024 * 1024,
+        iterations: 16,
+        parallelism: 16,
+    };
+}
+
+/// Version tag of descriptors for the construction above.
+pub const KDF_VERSION: &str = "hp-admin-crypto/kdf/v1";
+
+/// How a host's admin key is derived, as recorded with the host.
+///
+/// The text form is [`KDF_VERSION`], a colon, then the memory cost in KiB,
+/// the iterations and the parallelism:
+/// `hp-admin-crypto/kdf/v1:m=65536,t=2,p=1`. Costs below
+/// [`KdfParams::V1`] or above a sanity limit are refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfDescriptor {
+    params: KdfParams,
+}
+
+impl KdfDescriptor {
+    /// What hosts without a descriptor use.
+    pub const LEGACY: KdfDescriptor = KdfDescriptor {
+        params: KdfParams::V1,
+    };
+
+    /// What new hosts should use.
+    pub const RECOMMENDED: KdfDescriptor = KdfDescriptor {
+        params: KdfParams::V2,
+    };
+
+    pub fn new(params: KdfParams) -> Result<Self> {
+        let (min, max) = (KdfParams::V1, KdfParams::MAX);
+        let reason = if params.memory_kib < min.memory_kib
+            || params.iterations < min.iterations
+            || params.parallelism < min.parallelism
+        {
+            "costs below 64 MiB, 2 iterations or 1 lane"
+        } else if params.memory_kib > max.memory_kib
+            || params.iterations > max.iterations
+            || params.parallelism > max.parallelism
+        {
+            "costs above 1 GiB, 16 iterations or 16 lanes"
+        } else {
+            return Ok(KdfDescriptor { params });
+        };
+        Err(Error::InvalidKdf {
+            descriptor: KdfDescriptor { params }.to_string(),
+            reason,
+        })
+    }
+
+    pub fn params(&self) -> KdfParams {
+        self.params
+    }
+}
+
+impl Default for KdfDescriptor {
+    fn default() -> Self {
+        KdfDescriptor::LEGACY
+    }
+}
+
+impl fmt::Display for KdfDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let KdfParams {
+            memory_kib,
+            iterations,
+            parallelism,
+        } = self.params;
+        write!(
+            f,
+            "{KDF_VERSION}:m={memory_kib},t={iterations},p={parallelism}"
+        )
+    }
+}
+
+impl FromStr for KdfDescriptor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let invalid = |reason| Error::InvalidKdf {
+            descriptor: s.to_owned(),
+            reason,
+        };
+        let (version, costs) = s.split_once(':').unwrap_or((s, ""));
+        if version != KDF_VERSION {
+            return Err(invalid(if version.starts_with("hp-admin-crypto/kdf/") {
+                "unsupported version; this software is too old"
+            } else {
+                "expected hp-admin-crypto/kdf/v1:m=<KiB>,t=<iterations>,p=<lanes>"
+            }));
+        }
+        let mut values = costs
+            .split(',')
+            .zip(["m=", "t=", "p="])
+            .map(|(cost, name)| {
+                cost.strip_prefix(name)
+                    .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+                    .and_then(|n| n.parse().ok())
+            });
+        let mut next = || values.next().flatten();
+        let params = match (next(), next(), next(), costs.split(',').count()) {
+            (Some(memory_kib), Some(iterations), Some(parallelism), 3) => KdfParams {
+                memory_kib,
+                iterations,
+                parallelism,
+            },
+            _ => return Err(invalid("expected m=<KiB>,t=<iterations>,p=<lanes>")),
+        };
+        KdfDescriptor::new(params).map_err(|e| match e {
+            Error::InvalidKdf { reason, .. } => invalid(reason),
+            e => e,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptors_round_trip() {
+        assert_eq!(
+            KdfDescriptor::LEGACY.to_string(),
+            "hp-admin-crypto/kdf/v1:m=65536,t=2,p=1"
+        );
+        for descriptor in [KdfDescriptor::LEGACY, KdfDescriptor::RECOMMENDED] {
+            assert_eq!(descriptor.to_string().parse(), Ok(descriptor));
+        }
+        assert_eq!(
+            " hp-admin-crypto/kdf/v1:m=262144,t=4,p=2\n"
+                .parse::<KdfDescriptor>()
+                .unwrap()
+                .params(),
+            KdfParams {
+                memory_kib: 262144,
+                iterations: 4,
+                parallelism: 2
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_bad_descriptors() {
+        let reason = |s: &str| match s.parse::<KdfDescriptor>() {
+            Err(Error::InvalidKdf { reason, .. }) => reason,
+            other => panic!("{s:?} parsed as {other:?}"),
+        };
+        assert_eq!(
+            reason("hp-admin-crypto/kdf/v2:m=65536,t=2,p=1"),
+            "unsupported version; this software is too old"
+        );
+        assert_eq!(
+            reason("hp-admin-crypto/kdf/v1:m=32768,t=2,p=1"),
+            "costs below 64 MiB, 2 iterations or 1 lane"
+        );
+        assert_eq!(
+            reason("hp-admin-crypto/kdf/v1:m=4194304,t=2,p=1"),
+            "costs above 1 GiB, 16 iterations or 16 lanes"
+        );
+        for malformed in [
+            "",
+            "argon2id$v=19$m=65536,t=2,p=1",
+            "hp-admin-crypto/kdf/v1",
+            "hp-admin-crypto/kdf/v1:t=2,m=65536,p=1",
+            "hp-admin-crypto/kdf/v1:m=65536,t=2",
+            "hp-admin-crypto/kdf/v1:m=65536,t=2,p=1,x=0",
+            "hp-admin-crypto/kdf/v1:m=+65536,t=2,p=1",
+        ] {
+            assert!(reason(malformed).starts_with("expected"), "{malformed:?}");
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Canonical signed-payload format shared by the HP Admin signing client and
+//! the HPOS verification server.
+//!
+//! Every admin API call made from HP Admin is described by a [`Payload`]: the
+//! HTTP method, the request URI, a SHA-512 digest of the request body and the
+//! time at which the call was signed. The payload is serialized to a
+//! byte-exact canonical form with [`Payload::to_bytes`], and that form is what
+//! the admin Ed25519 key signs. Both halves of the system depend on this crate,
+//! so the client and the server can never disagree about what was signed.
+//!
+//! The same calls can instead be signed with standard RFC 9421 HTTP Message
+//! Signatures; see [`rfc9421`]. The admin key can also delegate a limited
+//! scope to a secondary key; see [`delegation`], and endorse the key that
+//! replaces it when the admin password changes; see [`rotation`]. How the
+//! admin key is derived from the password is recorded with the host as a
+//! [`KdfDescriptor`]; see [`kdf`].
+
+pub mod content_digest;
+pub mod delegation;
+mod digest;
+mod encoding;
+mod error;
+pub mod headers;
+pub mod kdf;
+mod payload;
+pub mod rfc9421;
+pub mod rotation;
+mod sf;
+
+pub use content_digest::ContentDigest;
+pub use delegation::{Delegation, DelegationChain};
+pub use digest::BodyDigest;
+pub use encoding::{decode_public_key, decode_signature, encode_public_key, encode_signature};
+pub use error::{Error, Result};
+pub use headers::SignatureHeaders;
+pub use kdf::KdfDescriptor;
+pub use payload::{Payload, MAX_NONCE_LEN, PAYLOAD_VERSION};
+pub use rfc9421::MessageSignatureHeaders;
+pub use rotation::{Endorsement, KeyRotation};
+
+pub use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+
+use ed25519_dalek::Signer;
+
+/// Signs the canonical serialization of `payload` with the admin key.
+pub fn sign(key: &SigningKey, payload: &Payload) -> Signature {
+    key.sign(&payload.to_bytes())
+}
+
+/// Verifies that `signature` was made by `key` over the canonical
+/// serialization of `payload`.
+pub fn verify(key: &VerifyingKey, payload: &Payload, signature: &Signature) -> Result<()> {
+    key.verify_strict(&payload.to_bytes(), signature)
+        .map_err(|_| Error::BadSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    #[test]
+    fn sign_then_verify() {
+        let payload = Payload::new("post", "/api/v1/config", b"{}", 1_570_000_000).unwrap();
+        let signature = sign(&key(), &payload);
+        verify(&key().verifying_key(), &payload, &signature).unwrap();
+    }
+
+    #[test]
+    fn verify_rejects_any_field_change() {
+        let payload = Payload::new("POST", "/api/v1/config", b"{}", 1_570_000_000).unwrap();
+        let signature = sign(&key(), &payload);
+        let tampered = [
+            Payload::new("PUT", "/api/v1/config", b"{}", 1_570_000_000),
+            Payload::new("POST", "/api/v1/config?x", b"{}", 1_570_000_000),
+            Payload::new("POST", "/api/v1/config", b"{ }", 1_570_000_000),
+            Payload::new("POST", "/api/v1/config", b"{}", 1_570_000_001),
+        ];
+        for other in tampered {
+            assert_eq!(
+                verify(&key().verifying_key(), &other.unwrap(), &signature),
+                Err(Error::BadSignature)
+            );
+        }
+    }
+
+    #[test]
+    fn verify_rejects_other_key() {
+        let payload = Payload::new("GET", "/api/v1/status", b"", 1_570_000_000).unwrap();
+        let signature = sign(&key(), &payload);
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        assert_eq!(
+            verify(&other, &payload, &signature),
+            Err(Error::BadSignature)
+        );
+    }
+}
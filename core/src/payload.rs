@@ -0,0 +1,194 @@
+use crate::digest::BodyDigest;
+use crate::error::{Error, Result};
+
+/// Tag that opens every canonical payload. Bumped whenever the canonical
+/// serialization changes, so old signatures can never verify under a new
+/// interpretation of the same bytes.
+pub const PAYLOAD_VERSION: &str = "hp-admin-crypto/v1";
+
+/// Longest nonce a payload may carry.
+pub const MAX_NONCE_LEN: usize = 128;
+
+/// The description of an admin API call that the admin key signs.
+///
+/// Fields are validated on construction, so every `Payload` has exactly one
+/// canonical serialization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    method: String,
+    uri: String,
+    body_digest: BodyDigest,
+    timestamp: u64,
+    nonce: Option<String>,
+}
+
+impl Payload {
+    /// Builds the payload for a request, hashing `body` with SHA-512.
+    ///
+    /// `timestamp` is the signing time in seconds since the Unix epoch.
+    pub fn new(method: &str, uri: &str, body: &[u8], timestamp: u64) -> Result<Self> {
+        Self::with_digest(method, uri, BodyDigest::of(body), timestamp)
+    }
+
+    /// Builds the payload for a request whose body is known only by digest,
+    /// which is the situation of a server behind nginx `auth_request`.
+    pub fn with_digest(
+        method: &str,
+        uri: &str,
+        body_digest: BodyDigest,
+        timestamp: u64,
+    ) -> Result<Self> {
+        Ok(Payload {
+            method: canonical_method(method)?,
+            uri: canonical_uri(uri)?,
+            body_digest,
+            timestamp,
+            nonce: None,
+        })
+    }
+
+    /// Adds a server-issued challenge nonce to the payload.
+    ///
+    /// Nonces are opaque to the client; they only have to be non-empty,
+    /// visible ASCII of at most [`MAX_NONCE_LEN`] bytes.
+    pub fn with_nonce(mut self, nonce: &str) -> Result<Self> {
+        self.nonce = Some(check_nonce(nonce)?.to_owned());
+        Ok(self)
+    }
+
+    /// Upper-cased HTTP method.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Request URI exactly as it was sent: path plus optional query string.
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    pub fn body_digest(&self) -> &BodyDigest {
+        &self.body_digest
+    }
+
+    /// Signing time in seconds since the Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn nonce(&self) -> Option<&str> {
+        self.nonce.as_deref()
+    }
+
+    /// The byte-exact canonical serialization that gets signed.
+    ///
+    /// It is the version tag followed by the method, URI, base64 body digest,
+    /// decimal timestamp and, only if there is one, the nonce, each terminated
+    /// by a single `\n`. None of the fields may contain a line feed, so the
+    /// encoding is unambiguous.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!(
+            "{PAYLOAD_VERSION}\n{}\n{}\n{}\n{}\n",
+            self.method, self.uri, self.body_digest, self.timestamp
+        );
+        if let Some(nonce) = &self.nonce {
+            bytes.push_str(nonce);
+            bytes.push('\n');
+        }
+        bytes.into_bytes()
+    }
+}
+
+/// Methods are RFC 9110 tokens; they are upper-cased so that `get` and `GET`
+/// sign identically.
+fn canonical_method(method: &str) -> Result<String> {
+    if !is_token(method) {
+        return Err(Error::InvalidMethod(method.to_owned()));
+    }
+    Ok(method.to_ascii_uppercase())
+}
+
+/// Whether `s` is an RFC 9110 token, which is what HTTP methods are.
+pub(crate) fn is_token(s: &str) -> bool {
+    let is_tchar = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    !s.is_empty() && s.chars().all(is_tchar)
+}
+
+/// URIs are signed verbatim; they only have to be non-empty, visible ASCII.
+fn canonical_uri(uri: &str) -> Result<String> {
+    if uri.is_empty() || !uri.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(Error::InvalidUri(uri.to_owned()));
+    }
+    Ok(uri.to_owned())
+}
+
+pub(crate) fn check_nonce(nonce: &str) -> Result<&str> {
+    if nonce.is_empty()
+        || nonce.len() > MAX_NONCE_LEN
+        || !nonce.bytes().all(|b| b.is_ascii_graphic())
+    {
+        return Err(Error::InvalidNonce(nonce.to_owned()));
+    }
+    Ok(nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_bytes() {
+        let payload = Payload::new("get", "/api/v1/status?verbose=1", b"", 1_570_000_000).unwrap();
+        assert_eq!(
+            String::from_utf8(payload.to_bytes()).unwrap(),
+            "hp-admin-crypto/v1\n\
+             GET\n\
+             /api/v1/status?verbose=1\n\
+             z4PhNX7vuL3xVChQ1m2AB9Yg5AULVxXcg/SpIdNs6c5H0NE8XYXysP+DGNKHfuwvY7kxvUdBeoGlODJ6+SfaPg==\n\
+             1570000000\n"
+        );
+    }
+
+    #[test]
+    fn nonce_is_an_extra_line() {
+        let payload = Payload::new("GET", "/api/v1/status", b"", 1_570_000_000).unwrap();
+        let with_nonce = payload.clone().with_nonce("n0nce").unwrap();
+        let mut expected = payload.to_bytes();
+        expected.extend_from_slice(b"n0nce\n");
+        assert_eq!(with_nonce.to_bytes(), expected);
+    }
+
+    #[test]
+    fn rejects_bad_nonce() {
+        let payload = Payload::new("GET", "/", b"", 0).unwrap();
+        for nonce in [
+            "".to_owned(),
+            "a b".to_owned(),
+            "x".repeat(MAX_NONCE_LEN + 1),
+        ] {
+            assert_eq!(
+                payload.clone().with_nonce(&nonce),
+                Err(Error::InvalidNonce(nonce))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_method() {
+        for method in ["", "GE T", "GET\n", "GÉT"] {
+            assert_eq!(
+                Payload::new(method, "/", b"", 0),
+                Err(Error::InvalidMethod(method.to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_uri() {
+        for uri in ["", "/a b", "/a\nGET", "/é"] {
+            assert_eq!(
+                Payload::new("GET", uri, b"", 0),
+                Err(Error::InvalidUri(uri.to_owned()))
+            );
+        }
+    }
+}
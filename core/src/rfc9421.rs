@@ -0,0 +1,616 @@
+//! RFC 9421 HTTP Message Signatures, the standard alternative to the
+//! `X-Hpos-Admin-*` headers of [`headers`](crate::headers).
+//!
+//! HP Admin signs the `@method`, `@target-uri` and `content-digest`
+//! components of a request with the `ed25519` algorithm and a `created`
+//! time, and sends the result in `Signature-Input` and `Signature` under the
+//! label [`LABEL`]. Verification accepts signatures made by other tools too,
+//! as long as every component they cover can be computed from the request.
+
+use std::fmt;
+
+use ed25519_dalek::Signer;
+
+use crate::content_digest::ContentDigest;
+use crate::encoding::encode_public_key;
+use crate::error::{Error, Result};
+use crate::payload::{check_nonce, is_token};
+use crate::sf::{self, BareItem, InnerList, Item, Member};
+use crate::{Signature, SigningKey, VerifyingKey};
+
+/// Dictionary of signature parameters, keyed by label.
+pub const SIGNATURE_INPUT: &str = "signature-input";
+/// Dictionary of signatures, keyed by label.
+pub const SIGNATURE: &str = "signature";
+pub use crate::content_digest::CONTENT_DIGEST;
+
+/// The only signature algorithm we sign or verify with.
+pub const ALGORITHM: &str = "ed25519";
+/// Label HP Admin gives its signature.
+pub const LABEL: &str = "hpos";
+/// Components HP Admin signs, in order.
+pub const COVERED_COMPONENTS: [&str; 3] = ["@method", "@target-uri", CONTENT_DIGEST];
+
+/// The parts of a request that signatures can cover.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    method: String,
+    target_uri: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Request {
+    /// `target_uri` is the absolute URI the request was sent to, scheme and
+    /// authority included; `method` is signed exactly as sent.
+    pub fn new(method: &str, target_uri: &str) -> Result<Self> {
+        if !is_token(method) {
+            return Err(Error::InvalidMethod(method.to_owned()));
+        }
+        if !target_uri.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(Error::InvalidUri(target_uri.to_owned()));
+        }
+        split_target_uri(target_uri)?;
+        Ok(Request {
+            method: method.to_owned(),
+            target_uri: target_uri.to_owned(),
+            fields: Vec::new(),
+        })
+    }
+
+    /// Adds a header field. Names compare case-insensitively, and a field
+    /// added more than once is covered as the comma-joined list of values.
+    pub fn with_field(mut self, name: &str, value: &str) -> Self {
+        self.fields
+            .push((name.to_ascii_lowercase(), value.trim().to_owned()));
+        self
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn target_uri(&self) -> &str {
+        &self.target_uri
+    }
+
+    /// The value of the component named `id` in the signature base.
+    pub fn component(&self, id: &str) -> Result<String> {
+        // Checked in `new`.
+        let (scheme, authority, path, query) = split_target_uri(&self.target_uri)?;
+        let value = match id {
+            "@method" => self.method.clone(),
+            "@target-uri" => self.target_uri.clone(),
+            "@scheme" => scheme.to_ascii_lowercase(),
+            "@authority" => authority.to_ascii_lowercase(),
+            "@path" => path.to_owned(),
+            "@query" => format!("?{}", query.unwrap_or_default()),
+            "@request-target" => match query {
+                Some(query) => format!("{path}?{query}"),
+                None => path.to_owned(),
+            },
+            _ if id.starts_with('@') => return Err(Error::UnsupportedComponent(id.to_owned())),
+            _ if id.bytes().any(|b| b.is_ascii_uppercase()) => {
+                return Err(Error::UnsupportedComponent(id.to_owned()))
+            }
+            _ => {
+                let values: Vec<&str> = self
+                    .fields
+                    .iter()
+                    .filter(|(name, _)| name == id)
+                    .map(|(_, value)| value.as_str())
+                    .collect();
+                if values.is_empty() {
+                    return Err(Error::MissingComponent(id.to_owned()));
+                }
+                values.join(", ")
+            }
+        };
+        if value.contains(['\r', '\n']) {
+            return Err(Error::UnsupportedComponent(id.to_owned()));
+        }
+        Ok(value)
+    }
+}
+
+/// Splits an absolute URI into scheme, authority, path and query.
+fn split_target_uri(uri: &str) -> Result<(&str, &str, &str, Option<&str>)> {
+    let invalid = || Error::InvalidUri(uri.to_owned());
+    let (scheme, rest) = uri.split_once("://").ok_or_else(invalid)?;
+    if scheme.is_empty() || uri.contains('#') {
+        return Err(invalid());
+    }
+    let (authority, path_and_query) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+    if authority.is_empty() {
+        return Err(invalid());
+    }
+    let (path, query) = match path_and_query.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path_and_query, None),
+    };
+    Ok((
+        scheme,
+        authority,
+        if path.is_empty() { "/" } else { path },
+        query,
+    ))
+}
+
+/// The covered components and parameters of one signature: one member of
+/// `Signature-Input`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureParams(InnerList);
+
+impl SignatureParams {
+    /// Covers [`COVERED_COMPONENTS`], signed at `created` seconds since the
+    /// Unix epoch.
+    pub fn new(created: u64) -> Self {
+        Self::covering(&COVERED_COMPONENTS, created)
+    }
+
+    /// Covers the components named by `ids`, signed at `created`.
+    pub fn covering(ids: &[&str], created: u64) -> Self {
+        SignatureParams(InnerList {
+            items: ids
+                .iter()
+                .map(|id| Item {
+                    bare: BareItem::String((*id).to_owned()),
+                    params: Vec::new(),
+                })
+                .collect(),
+            params: vec![("created".to_owned(), BareItem::Integer(created as i64))],
+        })
+    }
+
+    /// Adds a server-issued challenge nonce.
+    pub fn with_nonce(mut self, nonce: &str) -> Result<Self> {
+        let nonce = check_nonce(nonce)?;
+        self.0
+            .params
+            .push(("nonce".to_owned(), BareItem::String(nonce.to_owned())));
+        Ok(self)
+    }
+
+    /// Names the signing key and algorithm.
+    pub fn with_key(mut self, key: &VerifyingKey) -> Self {
+        self.0.params.extend([
+            ("keyid".to_owned(), BareItem::String(encode_public_key(key))),
+            ("alg".to_owned(), BareItem::String(ALGORITHM.to_owned())),
+        ]);
+        self
+    }
+
+    fn parse(list: InnerList) -> Result<Self> {
+        let invalid = |reason: String| Error::encoding("signature parameters", reason);
+        for item in &list.items {
+            match &item.bare {
+                BareItem::String(id) if item.params.is_empty() => {
+                    if list
+                        .items
+                        .iter()
+                        .filter(|other| other.bare == item.bare)
+                        .count()
+                        > 1
+                    {
+                        return Err(invalid(format!("component {id} is covered twice")));
+                    }
+                }
+                _ => return Err(Error::UnsupportedComponent(item.to_string())),
+            }
+        }
+        for (key, value) in &list.params {
+            let valid = match key.as_str() {
+                "created" | "expires" => matches!(value, BareItem::Integer(n) if *n >= 0),
+                "nonce" => {
+                    matches!(value, BareItem::String(nonce) if check_nonce(nonce).is_ok())
+                }
+                "keyid" | "alg" | "tag" => matches!(value, BareItem::String(_)),
+                _ => true,
+            };
+            if !valid {
+                return Err(invalid(format!("bad {key} parameter {value}")));
+            }
+        }
+        Ok(SignatureParams(list))
+    }
+
+    /// Identifiers of the covered components, in order.
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.0.items.iter().filter_map(|item| match &item.bare {
+            BareItem::String(id) => Some(id.as_str()),
+            _ => None,
+        })
+    }
+
+    fn integer(&self, key: &str) -> Option<u64> {
+        match sf::get(&self.0.params, key) {
+            Some(BareItem::Integer(n)) => u64::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+
+    fn string(&self, key: &str) -> Option<&str> {
+        match sf::get(&self.0.params, key) {
+            Some(BareItem::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Signing time in seconds since the Unix epoch.
+    pub fn created(&self) -> Option<u64> {
+        self.integer("created")
+    }
+
+    /// Time after which the signer wants the signature refused.
+    pub fn expires(&self) -> Option<u64> {
+        self.integer("expires")
+    }
+
+    pub fn nonce(&self) -> Option<&str> {
+        self.string("nonce")
+    }
+
+    pub fn keyid(&self) -> Option<&str> {
+        self.string("keyid")
+    }
+
+    pub fn alg(&self) -> Option<&str> {
+        self.string("alg")
+    }
+}
+
+/// The serialization that `Signature-Input` carries and the signature base
+/// ends with.
+impl fmt::Display for SignatureParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The exact text that gets signed: one `"<id>": <value>` line per covered
+/// component, then the `"@signature-params"` line, with no final newline.
+pub fn signature_base(request: &Request, params: &SignatureParams) -> Result<String> {
+    let mut base = String::new();
+    for id in params.components() {
+        base.push_str(&format!("\"{id}\": {}\n", request.component(id)?));
+    }
+    base.push_str(&format!("\"@signature-params\": {params}"));
+    Ok(base)
+}
+
+/// One labelled signature, as carried by `Signature-Input` and `Signature`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSignature {
+    label: String,
+    params: SignatureParams,
+    signature: Signature,
+}
+
+impl MessageSignature {
+    /// Signs `request` under [`LABEL`].
+    pub fn sign(key: &SigningKey, request: &Request, params: SignatureParams) -> Result<Self> {
+        let base = signature_base(request, &params)?;
+        Ok(MessageSignature {
+            label: LABEL.to_owned(),
+            signature: key.sign(base.as_bytes()),
+            params,
+        })
+    }
+
+    /// Picks the signature to check out of the two header values: the one
+    /// labelled [`LABEL`], or else the only one there is.
+    pub fn from_headers(signature_input: &str, signature: &str) -> Result<Self> {
+        let inputs = sf::parse_dictionary(signature_input)
+            .map_err(|e| Error::encoding("Signature-Input", e))?;
+        let signatures =
+            sf::parse_dictionary(signature).map_err(|e| Error::encoding("Signature", e))?;
+
+        let label = match inputs.as_slice() {
+            _ if sf::get(&inputs, LABEL).is_some() => LABEL.to_owned(),
+            [(label, _)] => label.clone(),
+            [] => return Err(Error::encoding("Signature-Input", "no signatures")),
+            _ => {
+                return Err(Error::encoding(
+                    "Signature-Input",
+                    format!("several signatures and none labelled {LABEL:?}"),
+                ))
+            }
+        };
+        let params = match sf::get(&inputs, &label) {
+            Some(Member::InnerList(list)) => SignatureParams::parse(list.clone())?,
+            _ => {
+                return Err(Error::encoding(
+                    "Signature-Input",
+                    format!("{label} is not an inner list"),
+                ))
+            }
+        };
+        let signature = match sf::get(&signatures, &label) {
+            Some(Member::Item(Item {
+                bare: BareItem::ByteSeq(bytes),
+                ..
+            })) => Signature::from_slice(bytes)
+                .map_err(|_| Error::encoding("Signature", format!("{} bytes", bytes.len())))?,
+            _ => {
+                return Err(Error::encoding(
+                    "Signature",
+                    format!("no byte sequence labelled {label}"),
+                ))
+            }
+        };
+        Ok(MessageSignature {
+            label,
+            params,
+            signature,
+        })
+    }
+
+    /// Checks the signature over `request` with `key`.
+    pub fn verify(&self, key: &VerifyingKey, request: &Request) -> Result<()> {
+        match self.params.alg() {
+            None | Some(ALGORITHM) => {}
+            Some(alg) => return Err(Error::UnsupportedAlgorithm(alg.to_owned())),
+        }
+        let base = signature_base(request, &self.params)?;
+        key.verify_strict(base.as_bytes(), &self.signature)
+            .map_err(|_| Error::BadSignature)
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn params(&self) -> &SignatureParams {
+        &self.params
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// Value for the `Signature-Input` header.
+    pub fn signature_input_header(&self) -> String {
+        format!("{}={}", self.label, self.params)
+    }
+
+    /// Value for the `Signature` header.
+    pub fn signature_header(&self) -> String {
+        let bytes = BareItem::ByteSeq(self.signature.to_bytes().to_vec());
+        format!("{}={bytes}", self.label)
+    }
+}
+
+/// Header values produced by the client for one request signed with
+/// RFC 9421.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSignatureHeaders {
+    pub content_digest: String,
+    pub signature_input: String,
+    pub signature: String,
+}
+
+impl MessageSignatureHeaders {
+    /// Signs a request the way HP Admin does: [`COVERED_COMPONENTS`] at
+    /// `created`, naming the key, with a challenge nonce if one is given.
+    pub fn sign(
+        key: &SigningKey,
+        method: &str,
+        target_uri: &str,
+        body: &[u8],
+        created: u64,
+        nonce: Option<&str>,
+    ) -> Result<Self> {
+        let content_digest = ContentDigest::of(body).to_string();
+        let request = Request::new(method, target_uri)?.with_field(CONTENT_DIGEST, &content_digest);
+        let mut params = SignatureParams::new(created);
+        if let Some(nonce) = nonce {
+            params = params.with_nonce(nonce)?;
+        }
+        let signed = MessageSignature::sign(key, &request, params.with_key(&key.verifying_key()))?;
+        Ok(MessageSignatureHeaders {
+            content_digest,
+            signature_input: signed.signature_input_header(),
+            signature: signed.signature_header(),
+        })
+    }
+
+    /// `(name, value)` pairs, ready to be attached to a request.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            (CONTENT_DIGEST, self.content_digest.as_str()),
+            (SIGNATURE_INPUT, self.signature_input.as_str()),
+            (SIGNATURE, self.signature.as_str()),
+        ]
+        .into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::prelude::{Engine, BASE64_STANDARD};
+
+    use super::*;
+
+    /// `test-key-ed25519` from RFC 9421 appendix B.1.4, without its PKCS#8
+    /// and SPKI wrappers.
+    fn rfc_key() -> SigningKey {
+        let pkcs8 = BASE64_STANDARD
+            .decode("MC4CAQAwBQYDK2VwBCIEIJ+DYvh6SEqVTm50DFtMDoQikTmiCqirVv9mWG9qfSnF")
+            .unwrap();
+        SigningKey::from_bytes(pkcs8[16..].try_into().unwrap())
+    }
+
+    fn rfc_request() -> Request {
+        Request::new("POST", "https://example.com/foo?param=Value&Pet=dog")
+            .unwrap()
+            .with_field("Host", "example.com")
+            .with_field("Date", "Tue, 20 Apr 2021 02:07:55 GMT")
+            .with_field("Content-Type", "application/json")
+            .with_field(
+                "Content-Digest",
+                "sha-512=:WZDPaVn/7XgHaAy8pmojAkGWoRx2UFChF41A2svX+TaPm+AbwAgBWnrIiYllu7BNNyealdVLvRwEmTHWXvJwew==:",
+            )
+            .with_field("Content-Length", "18")
+    }
+
+    #[test]
+    fn rfc9421_ed25519_example() {
+        let spki = BASE64_STANDARD
+            .decode("MCowBQYDK2VwAyEAJrQLj5P/89iXES9+vFgrIy29clF9CC/oPPsw3c5D0bs=")
+            .unwrap();
+        assert_eq!(rfc_key().verifying_key().as_bytes()[..], spki[12..]);
+
+        let signed = MessageSignature::from_headers(
+            r#"sig-b26=("date" "@method" "@path" "@authority" "content-type" "content-length");created=1618884473;keyid="test-key-ed25519""#,
+            "sig-b26=:wqcAqbmYJ2ji2glfAMaRy4gruYYnx2nEFN2HN6jrnDnQCK1u02Gb04v9EDgwUPiu4A0w6vuQv5lIp5WPpBKRCw==:",
+        )
+        .unwrap();
+        assert_eq!(
+            signature_base(&rfc_request(), signed.params()).unwrap(),
+            "\"date\": Tue, 20 Apr 2021 02:07:55 GMT\n\
+             \"@method\": POST\n\
+             \"@path\": /foo\n\
+             \"@authority\": example.com\n\
+             \"content-type\": application/json\n\
+             \"content-length\": 18\n\
+             \"@signature-params\": (\"date\" \"@method\" \"@path\" \"@authority\" \"content-type\" \"content-length\");created=1618884473;keyid=\"test-key-ed25519\""
+        );
+        signed
+            .verify(&rfc_key().verifying_key(), &rfc_request())
+            .unwrap();
+    }
+
+    #[test]
+    fn headers_round_trip() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let target = "https://hpos.example/api/v1/config?force=1";
+        let headers =
+            MessageSignatureHeaders::sign(&key, "PUT", target, b"{}", 1_600_000_000, Some("n"))
+                .unwrap();
+        assert!(headers
+            .signature_input
+            .starts_with(r#"hpos=("@method" "@target-uri" "content-digest");created=1600000000;nonce="n";keyid=""#));
+
+        let signed =
+            MessageSignature::from_headers(&headers.signature_input, &headers.signature).unwrap();
+        assert_eq!(signed.params().created(), Some(1_600_000_000));
+        assert_eq!(signed.params().nonce(), Some("n"));
+        assert_eq!(signed.params().alg(), Some(ALGORITHM));
+
+        let request = |method, target, body: &[u8]| {
+            Request::new(method, target)
+                .unwrap()
+                .with_field(CONTENT_DIGEST, &ContentDigest::of(body).to_string())
+        };
+        signed
+            .verify(&key.verifying_key(), &request("PUT", target, b"{}"))
+            .unwrap();
+        for tampered in [
+            request("POST", target, b"{}"),
+            request("PUT", "https://hpos.example/api/v1/config", b"{}"),
+            request("PUT", "https://other.example/api/v1/config?force=1", b"{}"),
+            request("PUT", target, b"{ }"),
+        ] {
+            assert_eq!(
+                signed.verify(&key.verifying_key(), &tampered),
+                Err(Error::BadSignature)
+            );
+        }
+    }
+
+    #[test]
+    fn picks_the_hpos_label() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let headers = MessageSignatureHeaders::sign(
+            &key,
+            "GET",
+            "https://hpos.example/",
+            b"",
+            1_600_000_000,
+            None,
+        )
+        .unwrap();
+        let inputs = format!(
+            r#"other=("@method");created=1, {}"#,
+            headers.signature_input
+        );
+        let signatures = format!("other=:AAAA:, {}", headers.signature);
+        let signed = MessageSignature::from_headers(&inputs, &signatures).unwrap();
+        assert_eq!(signed.label(), LABEL);
+
+        let ambiguous = MessageSignature::from_headers(
+            r#"a=("@method");created=1, b=("@method");created=1"#,
+            "a=:AAAA:, b=:AAAA:",
+        );
+        assert!(matches!(ambiguous, Err(Error::InvalidEncoding { .. })));
+    }
+
+    #[test]
+    fn component_values() {
+        let request = Request::new("get", "HTTPS://Hpos.Example:8443?x=1")
+            .unwrap()
+            .with_field("X-Multi", "a ")
+            .with_field("x-multi", " b");
+        let value = |id| request.component(id);
+        assert_eq!(value("@method").unwrap(), "get");
+        assert_eq!(value("@scheme").unwrap(), "https");
+        assert_eq!(value("@authority").unwrap(), "hpos.example:8443");
+        assert_eq!(value("@path").unwrap(), "/");
+        assert_eq!(value("@query").unwrap(), "?x=1");
+        assert_eq!(value("@request-target").unwrap(), "/?x=1");
+        assert_eq!(value("x-multi").unwrap(), "a, b");
+        assert_eq!(
+            value("x-missing"),
+            Err(Error::MissingComponent("x-missing".into()))
+        );
+        assert_eq!(
+            value("@status"),
+            Err(Error::UnsupportedComponent("@status".into()))
+        );
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_check() {
+        let parse = |input: &str| MessageSignature::from_headers(input, "sig=:AAAA:");
+        assert!(matches!(
+            parse(r#"sig=("content-digest";sf);created=1"#),
+            Err(Error::UnsupportedComponent(_))
+        ));
+        assert!(matches!(
+            parse(r#"sig=("@method" "@method");created=1"#),
+            Err(Error::InvalidEncoding { .. })
+        ));
+        assert!(matches!(
+            parse(r#"sig=("@method");created=-1"#),
+            Err(Error::InvalidEncoding { .. })
+        ));
+
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let request = Request::new("GET", "https://hpos.example/")
+            .unwrap()
+            .with_field(CONTENT_DIGEST, &ContentDigest::of(b"").to_string());
+        let signed = MessageSignature::sign(&key, &request, SignatureParams::new(1)).unwrap();
+        let input = signed
+            .signature_input_header()
+            .replace("created=1", r#"created=1;alg="rsa-pss-sha512""#);
+        let other_alg = MessageSignature::from_headers(&input, &signed.signature_header()).unwrap();
+        assert_eq!(
+            other_alg.verify(&key.verifying_key(), &request),
+            Err(Error::UnsupportedAlgorithm("rsa-pss-sha512".into()))
+        );
+    }
+
+    #[test]
+    fn rejects_relative_target_uri() {
+        for uri in [
+            "/api/v1/status",
+            "https:///x",
+            "https://a/b#c",
+            "https://a/ b",
+        ] {
+            assert_eq!(
+                Request::new("GET", uri),
+                Err(Error::InvalidUri(uri.to_owned()))
+            );
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Endorsements: the admin key vouching for the key that replaces it.
+//!
+//! The admin key is derived from the admin's password, so changing the
+//! password changes the key. Before switching, the client signs a
+//! [`KeyRotation`] naming the old and new public keys with the old key, and
+//! the verification server, holding the resulting [`Endorsement`], starts
+//! accepting the new key in place of the old one.
+
+use std::fmt;
+use std::str::FromStr;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use ed25519_dalek::Signer;
+
+use crate::encoding::encode_public_key;
+use crate::error::{Error, Result};
+use crate::{Signature, SigningKey, VerifyingKey};
+
+/// Tag that opens every canonical key rotation.
+pub const ROTATION_VERSION: &str = "hp-admin-crypto/rotation/v1";
+
+/// The old admin key handing over to a new one at some time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRotation {
+    old: VerifyingKey,
+    new: VerifyingKey,
+    timestamp: u64,
+}
+
+impl KeyRotation {
+    /// Hands over from `old` to `new` at `timestamp` (seconds since the
+    /// Unix epoch).
+    pub fn new(old: &VerifyingKey, new: &VerifyingKey, timestamp: u64) -> Result<Self> {
+        if old == new {
+            return Err(Error::InvalidRotation("the new key is the old key".into()));
+        }
+        Ok(KeyRotation {
+            old: *old,
+            new: *new,
+            timestamp,
+        })
+    }
+
+    pub fn old(&self) -> &VerifyingKey {
+        &self.old
+    }
+
+    pub fn new_key(&self) -> &VerifyingKey {
+        &self.new
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// The byte-exact canonical serialization that gets signed: the version
+    /// tag, the base64 old and new keys and the decimal timestamp, each
+    /// terminated by a single `\n`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        format!(
+            "{ROTATION_VERSION}\n{}\n{}\n{}\n",
+            encode_public_key(&self.old),
+            encode_public_key(&self.new),
+            self.timestamp
+        )
+        .into_bytes()
+    }
+
+    /// Parses a canonical serialization back, rejecting anything that would
+    /// not serialize to the same bytes.
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let invalid = |reason: &str| Error::encoding("key rotation", reason);
+        let text = std::str::from_utf8(bytes).map_err(|_| invalid("not UTF-8"))?;
+        let fields: Vec<&str> = text
+            .strip_suffix('\n')
+            .ok_or_else(|| invalid("missing final line feed"))?
+            .split('\n')
+            .collect();
+        let [version, old, new, timestamp] = fields[..] else {
+            return Err(invalid("expected four lines"));
+        };
+        if version != ROTATION_VERSION {
+            return Err(invalid("unknown version"));
+        }
+        let timestamp = timestamp
+            .parse()
+            .map_err(|_| invalid("timestamp is not a decimal number"))?;
+        let rotation = KeyRotation::new(
+            &crate::decode_public_key(old)?,
+            &crate::decode_public_key(new)?,
+            timestamp,
+        )?;
+        if rotation.to_bytes() != bytes {
+            return Err(invalid("not in canonical form"));
+        }
+        Ok(rotation)
+    }
+
+    /// Signs the rotation with the old key.
+    pub fn sign(self, old: &SigningKey) -> Result<Endorsement> {
+        if old.verifying_key() != self.old {
+            return Err(Error::InvalidRotation(
+                "only the old key can endorse its successor".into(),
+            ));
+        }
+        let signature = old.sign(&self.to_bytes());
+        Ok(Endorsement {
+            rotation: self,
+            signature,
+        })
+    }
+}
+
+/// A key rotation and the old key's signature over it.
+///
+/// As text, the base64 canonical rotation followed by the 64-byte
+/// signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endorsement {
+    rotation: KeyRotation,
+    signature: Signature,
+}
+
+impl Endorsement {
+    pub fn rotation(&self) -> &KeyRotation {
+        &self.rotation
+    }
+
+    /// Checks that the old key signed the rotation.
+    pub fn verify(&self) -> Result<()> {
+        self.rotation
+            .old
+            .verify_strict(&self.rotation.to_bytes(), &self.signature)
+            .map_err(|_| Error::BadSignature)
+    }
+}
+
+impl fmt::Display for Endorsement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = self.rotation.to_bytes();
+        bytes.extend_from_slice(&self.signature.to_bytes());
+        f.write_str(&BASE64_STANDARD.encode(bytes))
+    }
+}
+
+impl FromStr for Endorsement {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut bytes = BASE64_STANDARD
+            .decode(s.trim())
+            .map_err(|e| Error::encoding("endorsement", e))?;
+        if bytes.len() < Signature::BYTE_SIZE {
+            return Err(Error::encoding("endorsement", "too short"));
+        }
+        let signature = bytes.split_off(bytes.len() - Signature::BYTE_SIZE);
+        Ok(Endorsement {
+            rotation: KeyRotation::from_bytes(&bytes)?,
+            signature: Signature::from_slice(&signature)
+                .map_err(|e| Error::encoding("endorsement", e))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn old() -> SigningKey {
+        SigningKey::from_bytes(&[42; 32])
+    }
+
+    fn new() -> SigningKey {
+        SigningKey::from_bytes(&[43; 32])
+    }
+
+    fn rotation() -> KeyRotation {
+        KeyRotation::new(
+            &old().verifying_key(),
+            &new().verifying_key(),
+            1_600_000_000,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn canonical_bytes() {
+        assert_eq!(
+            String::from_utf8(rotation().to_bytes()).unwrap(),
+            format!(
+                "hp-admin-crypto/rotation/v1\n{}\n{}\n1600000000\n",
+                encode_public_key(&old().verifying_key()),
+                encode_public_key(&new().verifying_key())
+            )
+        );
+    }
+
+    #[test]
+    fn endorsement_round_trips_and_verifies() {
+        let endorsement = rotation().sign(&old()).unwrap();
+        let parsed: Endorsement = endorsement.to_string().parse().unwrap();
+        assert_eq!(parsed, endorsement);
+        parsed.verify().unwrap();
+        assert_eq!(parsed.rotation().new_key(), &new().verifying_key());
+    }
+
+    #[test]
+    fn only_the_old_key_endorses() {
+        assert!(matches!(
+            rotation().sign(&new()),
+            Err(Error::InvalidRotation(_))
+        ));
+        assert!(matches!(
+            KeyRotation::new(&old().verifying_key(), &old().verifying_key(), 0),
+            Err(Error::InvalidRotation(_))
+        ));
+
+        // Signed by the new key but claiming to be from the old one.
+        let forged = Endorsement {
+            rotation: rotation(),
+            signature: new().sign(&rotation().to_bytes()),
+        };
+        assert_eq!(forged.verify(), Err(Error::BadSignature));
+    }
+
+    #[test]
+    fn rejects_non_canonical_endorsements() {
+        let mut bytes = rotation().to_bytes();
+        bytes.pop();
+        assert!(KeyRotation::from_bytes(&bytes).is_err());
+        let padded = String::from_utf8(rotation().to_bytes())
+            .unwrap()
+            .replace("1600000000", "01600000000");
+        assert!(KeyRotation::from_bytes(padded.as_bytes()).is_err());
+        assert!("not base64!".parse::<Endorsement>().is_err());
+    }
+}
@@ -0,0 +1,424 @@
+//! The subset of RFC 8941 Structured Field Values that HTTP message
+//! signatures are written in: dictionaries whose members are items or inner
+//! lists, with parameters.
+//!
+//! Serialization is canonical, so a parsed value re-serializes to exactly
+//! what a strict sender put on the wire.
+
+use std::fmt::{self, Write};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BareItem {
+    Integer(i64),
+    /// Fixed point, in thousandths.
+    Decimal(i64),
+    String(String),
+    Token(String),
+    ByteSeq(Vec<u8>),
+    Boolean(bool),
+}
+
+/// Parameters in wire order; keys are unique.
+pub(crate) type Parameters = Vec<(String, BareItem)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Item {
+    pub bare: BareItem,
+    pub params: Parameters,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct InnerList {
+    pub items: Vec<Item>,
+    pub params: Parameters,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Member {
+    Item(Item),
+    InnerList(InnerList),
+}
+
+/// Dictionary members in wire order; keys are unique.
+pub(crate) type Dictionary = Vec<(String, Member)>;
+
+/// Looks up `key` in parameters or a dictionary.
+pub(crate) fn get<'a, T>(entries: &'a [(String, T)], key: &str) -> Option<&'a T> {
+    entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+/// Parses a dictionary field value, such as `Signature-Input`.
+pub(crate) fn parse_dictionary(input: &str) -> Result<Dictionary, String> {
+    let mut parser = Parser {
+        input: input.as_bytes(),
+        at: 0,
+    };
+    let mut dictionary = Dictionary::new();
+    parser.skip_spaces();
+    while !parser.done() {
+        let key = parser.key()?;
+        let member = if parser.eat(b'=') {
+            parser.item_or_inner_list()?
+        } else {
+            Member::Item(Item {
+                bare: BareItem::Boolean(true),
+                params: parser.parameters()?,
+            })
+        };
+        insert(&mut dictionary, key, member);
+
+        parser.skip_whitespace();
+        if parser.done() {
+            break;
+        }
+        if !parser.eat(b',') {
+            return Err(parser.unexpected("`,` between dictionary members"));
+        }
+        parser.skip_whitespace();
+        if parser.done() {
+            return Err("trailing `,` in dictionary".into());
+        }
+    }
+    Ok(dictionary)
+}
+
+fn insert<T>(entries: &mut Vec<(String, T)>, key: String, value: T) {
+    match entries.iter_mut().find(|(k, _)| *k == key) {
+        Some(entry) => entry.1 = value,
+        None => entries.push((key, value)),
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn done(&self) -> bool {
+        self.at == self.input.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.at).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let matched = self.peek() == Some(byte);
+        if matched {
+            self.at += 1;
+        }
+        matched
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.eat(b' ') {}
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.eat(b' ') || self.eat(b'\t') {}
+    }
+
+    fn unexpected(&self, expected: &str) -> String {
+        match self.peek() {
+            Some(byte) => format!(
+                "expected {expected} at offset {}, found {:?}",
+                self.at, byte as char
+            ),
+            None => format!("expected {expected}, found end of input"),
+        }
+    }
+
+    fn take_while(&mut self, accept: impl Fn(u8) -> bool) -> &str {
+        let start = self.at;
+        while self.peek().is_some_and(&accept) {
+            self.at += 1;
+        }
+        // Only ever called with ASCII predicates.
+        std::str::from_utf8(&self.input[start..self.at]).unwrap_or_default()
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        if !self
+            .peek()
+            .is_some_and(|b| b.is_ascii_lowercase() || b == b'*')
+        {
+            return Err(self.unexpected("a key"));
+        }
+        Ok(self
+            .take_while(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"_-.*".contains(&b))
+            .to_owned())
+    }
+
+    fn item_or_inner_list(&mut self) -> Result<Member, String> {
+        if self.peek() == Some(b'(') {
+            self.inner_list().map(Member::InnerList)
+        } else {
+            self.item().map(Member::Item)
+        }
+    }
+
+    fn inner_list(&mut self) -> Result<InnerList, String> {
+        self.eat(b'(');
+        let mut items = Vec::new();
+        loop {
+            self.skip_spaces();
+            if self.eat(b')') {
+                return Ok(InnerList {
+                    items,
+                    params: self.parameters()?,
+                });
+            }
+            items.push(self.item()?);
+            if !matches!(self.peek(), Some(b' ' | b')')) {
+                return Err(self.unexpected("` ` or `)` in inner list"));
+            }
+        }
+    }
+
+    fn item(&mut self) -> Result<Item, String> {
+        Ok(Item {
+            bare: self.bare_item()?,
+            params: self.parameters()?,
+        })
+    }
+
+    fn parameters(&mut self) -> Result<Parameters, String> {
+        let mut params = Parameters::new();
+        while self.eat(b';') {
+            self.skip_spaces();
+            let key = self.key()?;
+            let value = if self.eat(b'=') {
+                self.bare_item()?
+            } else {
+                BareItem::Boolean(true)
+            };
+            insert(&mut params, key, value);
+        }
+        Ok(params)
+    }
+
+    fn bare_item(&mut self) -> Result<BareItem, String> {
+        match self.peek() {
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b'"') => self.string(),
+            Some(b':') => self.byte_sequence(),
+            Some(b'?') => self.boolean(),
+            Some(b) if b.is_ascii_alphabetic() || b == b'*' => Ok(self.token()),
+            _ => Err(self.unexpected("an item")),
+        }
+    }
+
+    fn number(&mut self) -> Result<BareItem, String> {
+        let negative = self.eat(b'-');
+        let integer = self.take_while(|b| b.is_ascii_digit()).to_owned();
+        if integer.is_empty() {
+            return Err(self.unexpected("a digit"));
+        }
+        let sign = if negative { -1 } else { 1 };
+        if !self.eat(b'.') {
+            if integer.len() > 15 {
+                return Err(format!("integer {integer} is too long"));
+            }
+            return Ok(BareItem::Integer(sign * integer.parse::<i64>().unwrap()));
+        }
+        let fraction = self.take_while(|b| b.is_ascii_digit()).to_owned();
+        if integer.len() > 12 || fraction.is_empty() || fraction.len() > 3 {
+            return Err(format!("bad decimal {integer}.{fraction}"));
+        }
+        let thousandths = format!("{fraction:0<3}").parse::<i64>().unwrap();
+        Ok(BareItem::Decimal(
+            sign * (integer.parse::<i64>().unwrap() * 1000 + thousandths),
+        ))
+    }
+
+    fn string(&mut self) -> Result<BareItem, String> {
+        self.eat(b'"');
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.at += 1;
+                    return Ok(BareItem::String(value));
+                }
+                Some(b'\\') => {
+                    self.at += 1;
+                    match self.peek() {
+                        Some(b @ (b'"' | b'\\')) => value.push(b as char),
+                        _ => return Err(self.unexpected("`\"` or `\\` after `\\`")),
+                    }
+                }
+                Some(b @ 0x20..=0x7e) => value.push(b as char),
+                _ => return Err(self.unexpected("a string character")),
+            }
+            self.at += 1;
+        }
+    }
+
+    fn token(&mut self) -> BareItem {
+        let is_tchar = |b: u8| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~:/".contains(&b);
+        BareItem::Token(self.take_while(is_tchar).to_owned())
+    }
+
+    fn byte_sequence(&mut self) -> Result<BareItem, String> {
+        self.eat(b':');
+        let encoded = self
+            .take_while(|b| b.is_ascii_alphanumeric() || b"+/=".contains(&b))
+            .to_owned();
+        if !self.eat(b':') {
+            return Err(self.unexpected("`:` closing a byte sequence"));
+        }
+        BASE64_STANDARD
+            .decode(&encoded)
+            .map(BareItem::ByteSeq)
+            .map_err(|e| format!("bad byte sequence: {e}"))
+    }
+
+    fn boolean(&mut self) -> Result<BareItem, String> {
+        self.eat(b'?');
+        match self.peek() {
+            Some(b @ (b'0' | b'1')) => {
+                self.at += 1;
+                Ok(BareItem::Boolean(b == b'1'))
+            }
+            _ => Err(self.unexpected("`0` or `1` after `?`")),
+        }
+    }
+}
+
+impl fmt::Display for BareItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BareItem::Integer(n) => write!(f, "{n}"),
+            BareItem::Decimal(n) => {
+                let sign = if *n < 0 { "-" } else { "" };
+                let fraction = format!("{:03}", n.unsigned_abs() % 1000);
+                let fraction = fraction.trim_end_matches('0');
+                let fraction = if fraction.is_empty() { "0" } else { fraction };
+                write!(f, "{sign}{}.{fraction}", n.unsigned_abs() / 1000)
+            }
+            BareItem::String(s) => {
+                f.write_char('"')?;
+                for c in s.chars() {
+                    if c == '"' || c == '\\' {
+                        f.write_char('\\')?;
+                    }
+                    f.write_char(c)?;
+                }
+                f.write_char('"')
+            }
+            BareItem::Token(t) => f.write_str(t),
+            BareItem::ByteSeq(bytes) => write!(f, ":{}:", BASE64_STANDARD.encode(bytes)),
+            BareItem::Boolean(b) => write!(f, "?{}", u8::from(*b)),
+        }
+    }
+}
+
+struct DisplayParameters<'a>(&'a Parameters);
+
+impl fmt::Display for DisplayParameters<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in self.0 {
+            match value {
+                BareItem::Boolean(true) => write!(f, ";{key}")?,
+                value => write!(f, ";{key}={value}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.bare, DisplayParameters(&self.params))
+    }
+}
+
+impl fmt::Display for InnerList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('(')?;
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                f.write_char(' ')?;
+            }
+            write!(f, "{item}")?;
+        }
+        write!(f, "){}", DisplayParameters(&self.params))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_signature_input() {
+        let input =
+            r#"sig1=("@method" "content-digest";sf);created=1618884473;keyid="k",  flag, n=-1.50"#;
+        let dictionary = parse_dictionary(input).unwrap();
+        let keys: Vec<_> = dictionary.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["sig1", "flag", "n"]);
+
+        let Some(Member::InnerList(list)) = get(&dictionary, "sig1") else {
+            panic!("sig1 is not an inner list");
+        };
+        assert_eq!(
+            list.to_string(),
+            r#"("@method" "content-digest";sf);created=1618884473;keyid="k""#
+        );
+        assert_eq!(
+            get(&dictionary, "n"),
+            Some(&Member::Item(Item {
+                bare: BareItem::Decimal(-1500),
+                params: vec![],
+            }))
+        );
+        assert_eq!(BareItem::Decimal(-1500).to_string(), "-1.5");
+    }
+
+    #[test]
+    fn parses_byte_sequences_and_strings() {
+        let dictionary = parse_dictionary(r#"a=:AQID:, b="x\"y\\z", c=?0"#).unwrap();
+        let bare = |key| match get(&dictionary, key) {
+            Some(Member::Item(item)) => item.bare.clone(),
+            other => panic!("{other:?}"),
+        };
+        assert_eq!(bare("a"), BareItem::ByteSeq(vec![1, 2, 3]));
+        assert_eq!(bare("b"), BareItem::String("x\"y\\z".into()));
+        assert_eq!(bare("b").to_string(), r#""x\"y\\z""#);
+        assert_eq!(bare("c"), BareItem::Boolean(false));
+    }
+
+    #[test]
+    fn later_duplicates_win() {
+        let dictionary = parse_dictionary("a=1, b=2, a=3").unwrap();
+        assert_eq!(dictionary.len(), 2);
+        assert_eq!(dictionary[0].0, "a");
+        assert!(matches!(
+            &dictionary[0].1,
+            Member::Item(Item {
+                bare: BareItem::Integer(3),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_dictionaries() {
+        for input in [
+            "a=1,",
+            "A=1",
+            "a=(1 2",
+            "a=(1,2)",
+            "a=:not base64!:",
+            "a=\"unterminated",
+            "a=?2",
+            "a=1 b=2",
+            "a=1234567890123456",
+        ] {
+            assert!(parse_dictionary(input).is_err(), "{input}");
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! The keys allowed to administer a host, each labelled with the id of its
+//! admin.
+//!
+//! The HPOS config names one admin, `settings.admin`, whose id is its email.
+//! Hosts that a team co-manages list further admins, either in the config's
+//! optional `settings.admins` or in a separate JSON file of the same shape:
+//!
+//! ```json
+//! [{"id": "alice@example.com", "public_key": "<base64 Ed25519 key>"}]
+//! ```
+//!
+//! Every accepted call is attributed to one admin, and its id passed to the
+//! upstream in the [`ADMIN_ID`] response header.
+//!
+//! An admin whose key changes, because their password did, keeps the key it
+//! replaced for a grace period (see [`AdminKeys::rotate`]).
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use hp_admin_crypto::{Endorsement, VerifyingKey};
+
+/// Response header naming the admin an accepted call comes from, for nginx
+/// to pass on to the upstream.
+pub const ADMIN_ID: &str = "x-hpos-admin-id";
+
+#[derive(Debug, Error)]
+pub enum AdminsError {
+    #[error("cannot read admin keys {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("admin keys {path} are not valid: {source}")]
+    Malformed {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("admin {id:?} has an invalid public key: {source}")]
+    InvalidKey {
+        id: String,
+        source: hp_admin_crypto::Error,
+    },
+    #[error("admin id {0:?} must be non-empty and free of control characters")]
+    InvalidId(String),
+    #[error("admin id {0:?} is listed twice")]
+    DuplicateId(String),
+    #[error("admins {0:?} and {1:?} have the same public key")]
+    DuplicateKey(String, String),
+    #[error("endorsement does not verify: {0}")]
+    BadEndorsement(hp_admin_crypto::Error),
+    #[error("endorsement is from key {0}, which is no admin's current key")]
+    UnknownOldKey(String),
+}
+
+/// An admin as listed in the HPOS config or an admin keys file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdminEntry {
+    pub id: String,
+    pub public_key: String,
+}
+
+/// One admin's public key and id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Admin {
+    id: String,
+    key: VerifyingKey,
+    retiring: Option<RetiringKey>,
+}
+
+/// A key that has been replaced but is still accepted until `retires`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetiringKey {
+    pub key: VerifyingKey,
+    pub retires: u64,
+}
+
+impl Admin {
+    /// Labels `key` with `id`, which must fit in a header value.
+    pub fn new(id: &str, key: VerifyingKey) -> Result<Self, AdminsError> {
+        if id.trim().is_empty() || id.chars().any(char::is_control) {
+            return Err(AdminsError::InvalidId(id.to_owned()));
+        }
+        Ok(Admin {
+            id: id.to_owned(),
+            key,
+            retiring: None,
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The current key.
+    pub fn key(&self) -> &VerifyingKey {
+        &self.key
+    }
+
+    /// The key the current one replaced, if it has not retired yet.
+    pub fn retiring(&self) -> Option<&RetiringKey> {
+        self.retiring.as_ref()
+    }
+
+    /// The keys accepted for this admin at `now`: the current key and,
+    /// during its grace period, the one it replaced.
+    pub fn keys(&self, now: u64) -> impl Iterator<Item = &VerifyingKey> {
+        let retiring = self
+            .retiring
+            .as_ref()
+            .filter(|retiring| now < retiring.retires);
+        std::iter::once(&self.key).chain(retiring.map(|retiring| &retiring.key))
+    }
+}
+
+impl TryFrom<&AdminEntry> for Admin {
+    type Error = AdminsError;
+
+    fn try_from(entry: &AdminEntry) -> Result<Self, AdminsError> {
+        let key = hp_admin_crypto::decode_public_key(&entry.public_key).map_err(|source| {
+            AdminsError::InvalidKey {
+                id: entry.id.clone(),
+                source,
+            }
+        })?;
+        Admin::new(&entry.id, key)
+    }
+}
+
+/// The admins of a host: never empty, with distinct ids and keys. The first
+/// is the one named by `settings.admin` in the HPOS config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminKeys(Vec<Admin>);
+
+impl AdminKeys {
+    pub fn new(primary: Admin) -> Self {
+        AdminKeys(vec![primary])
+    }
+
+    /// Adds a further admin.
+    pub fn with_admin(mut self, admin: Admin) -> Result<Self, AdminsError> {
+        for existing in &self.0 {
+            if existing.id == admin.id {
+                return Err(AdminsError::DuplicateId(admin.id));
+            }
+            if existing.key == admin.key {
+                return Err(AdminsError::DuplicateKey(existing.id.clone(), admin.id));
+            }
+        }
+        self.0.push(admin);
+        Ok(self)
+    }
+
+    /// Adds the admins listed in a config or admin keys file.
+    pub fn with_entries(self, entries: &[AdminEntry]) -> Result<Self, AdminsError> {
+        entries
+            .iter()
+            .try_fold(self, |admins, entry| admins.with_admin(entry.try_into()?))
+    }
+
+    /// Adds the admins listed in the JSON file at `path`.
+    pub fn with_file(self, path: &Path) -> Result<Self, AdminsError> {
+        let bytes = std::fs::read(path).map_err(|source| AdminsError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        let entries: Vec<AdminEntry> =
+            serde_json::from_slice(&bytes).map_err(|source| AdminsError::Malformed {
+                path: path.to_owned(),
+                source,
+            })?;
+        self.with_entries(&entries)
+    }
+
+    /// Replaces the key that `endorsement` comes from with the key it
+    /// endorses, keeping the old key until `retires`, and returns the admin
+    /// it belongs to. A key that was still retiring from an earlier rotation
+    /// is dropped.
+    pub fn rotate(
+        &mut self,
+        endorsement: &Endorsement,
+        retires: u64,
+    ) -> Result<&Admin, AdminsError> {
+        endorsement.verify().map_err(AdminsError::BadEndorsement)?;
+        let rotation = endorsement.rotation();
+        let index = self
+            .0
+            .iter()
+            .position(|admin| admin.key == *rotation.old())
+            .ok_or_else(|| {
+                AdminsError::UnknownOldKey(hp_admin_crypto::encode_public_key(rotation.old()))
+            })?;
+        if let Some(other) = self.0.iter().find(|admin| admin.key == *rotation.new_key()) {
+            return Err(AdminsError::DuplicateKey(
+                other.id.clone(),
+                self.0[index].id.clone(),
+            ));
+        }
+        let admin = &mut self.0[index];
+        admin.retiring = Some(RetiringKey {
+            key: admin.key,
+            retires,
+        });
+        admin.key = *rotation.new_key();
+        Ok(admin)
+    }
+
+    pub fn primary(&self) -> &Admin {
+        &self.0[0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Admin> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Always false; there is at least the primary admin.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hp_admin_crypto::SigningKey;
+
+    use super::*;
+
+    fn key(byte: u8) -> VerifyingKey {
+        SigningKey::from_bytes(&[byte; 32]).verifying_key()
+    }
+
+    fn entry(id: &str, byte: u8) -> AdminEntry {
+        AdminEntry {
+            id: id.to_owned(),
+            public_key: hp_admin_crypto::encode_public_key(&key(byte)),
+        }
+    }
+
+    #[test]
+    fn adds_listed_admins_after_the_primary() {
+        let admins = AdminKeys::new(Admin::new("owner@example.com", key(1)).unwrap())
+            .with_entries(&[entry("alice", 2), entry("bob", 3)])
+            .unwrap();
+        assert_eq!(admins.primary().id(), "owner@example.com");
+        let ids: Vec<&str> = admins.iter().map(Admin::id).collect();
+        assert_eq!(ids, ["owner@example.com", "alice", "bob"]);
+        assert_eq!(admins.iter().nth(2).unwrap().key(), &key(3));
+    }
+
+    #[test]
+    fn rejects_duplicates_and_bad_ids() {
+        let admins = || AdminKeys::new(Admin::new("owner", key(1)).unwrap());
+        assert!(matches!(
+            admins().with_entries(&[entry("owner", 2)]),
+            Err(AdminsError::DuplicateId(_))
+        ));
+        assert!(matches!(
+            admins().with_entries(&[entry("alice", 1)]),
+            Err(AdminsError::DuplicateKey(..))
+        ));
+        for id in ["", " ", "bad\nid"] {
+            assert!(matches!(
+                admins().with_entries(&[entry(id, 2)]),
+                Err(AdminsError::InvalidId(_))
+            ));
+        }
+        let mut bad_key = entry("alice", 2);
+        bad_key.public_key = "not a key".into();
+        assert!(matches!(
+            admins().with_entries(&[bad_key]),
+            Err(AdminsError::InvalidKey { .. })
+        ));
+    }
+
+    #[test]
+    fn reads_admin_keys_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("admins.json");
+        let admins = || AdminKeys::new(Admin::new("owner", key(1)).unwrap());
+
+        std::fs::write(
+            &path,
+            format!(
+                r#"[{{"id": "alice", "public_key": "{}"}}]"#,
+                hp_admin_crypto::encode_public_key(&key(2))
+            ),
+        )
+        .unwrap();
+        assert_eq!(admins().with_file(&path).unwrap().len(), 2);
+
+        std::fs::write(&path, "{}").unwrap();
+        assert!(matches!(
+            admins().with_file(&path),
+            Err(AdminsError::Malformed { .. })
+        ));
+    }
+
+    #[test]
+    fn rotation_keeps_the_old_key_until_it_retires() {
+        let mut admins = AdminKeys::new(Admin::new("owner", key(1)).unwrap())
+            .with_entries(&[entry("alice", 2)])
+            .unwrap();
+        let endorse = |old: u8, new: u8| {
+            hp_admin_crypto::KeyRotation::new(&key(old), &key(new), 100)
+                .unwrap()
+                .sign(&SigningKey::from_bytes(&[old; 32]))
+                .unwrap()
+        };
+
+        assert_eq!(admins.rotate(&endorse(2, 4), 200).unwrap().id(), "alice");
+        let alice = admins.iter().nth(1).unwrap();
+        assert_eq!(alice.key(), &key(4));
+        assert_eq!(alice.keys(199).collect::<Vec<_>>(), [&key(4), &key(2)]);
+        assert_eq!(alice.keys(200).collect::<Vec<_>>(), [&key(4)]);
+
+        // Only current keys hand over, and not to another admin's key.
+        assert!(matches!(
+            admins.rotate(&endorse(2, 5), 200),
+            Err(AdminsError::UnknownOldKey(_))
+        ));
+        assert!(matches!(
+            admins.rotate(&endorse(4, 1), 200),
+            Err(AdminsError::DuplicateKey(..))
+        ));
+    }
+}
@@ -0,0 +1,317 @@
+//! A tamper-evident record of every decision the server makes.
+//!
+//! Each decision is appended to a JSON-lines file as one [`Entry`]: when it
+//! was made, by which admin, about which call, and why. Every entry carries
+//! the SHA-256 of the line before it, and a sequence number, so deleting,
+//! reordering or editing lines breaks the chain that [`verify`] checks.
+//! Truncating the end of the log cannot be detected this way; ship the log
+//! off the host to guard against that.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use hp_admin_crypto::headers;
+use hp_admin_crypto::rfc9421::CONTENT_DIGEST;
+
+use crate::verify::{Rejection, ORIGINAL_METHOD, ORIGINAL_URI};
+
+/// The `prev` of the first entry of a log.
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("audit log {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("audit log {path} is broken at line {line}: {reason}")]
+    Broken {
+        path: PathBuf,
+        line: u64,
+        reason: String,
+    },
+}
+
+/// What became of a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Allowed,
+    Rejected,
+}
+
+/// A decision about one call, as it goes in the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    /// The endpoint that decided: `auth`, `login`, `logout`,
+    /// `revoke-sessions` or `rotate`.
+    pub action: String,
+    /// Id of the admin the call came from, once that is known.
+    pub admin: Option<String>,
+    pub method: Option<String>,
+    pub uri: Option<String>,
+    /// The body digest the call was signed with, as sent.
+    pub body_digest: Option<String>,
+    pub decision: Decision,
+    /// Why the call was rejected.
+    pub reason: Option<String>,
+}
+
+impl Event {
+    /// An allowed call, described by the headers nginx forwarded with it.
+    pub fn allowed(action: &str, headers: &HeaderMap, admin: Option<String>) -> Self {
+        let mut event = Event::from_headers(action, headers, Decision::Allowed);
+        event.admin = admin;
+        event
+    }
+
+    /// A rejected call, described by the headers nginx forwarded with it.
+    pub fn rejected(
+        action: &str,
+        headers: &HeaderMap,
+        admin: Option<String>,
+        rejection: &Rejection,
+    ) -> Self {
+        let mut event = Event::from_headers(action, headers, Decision::Rejected);
+        event.admin = admin;
+        event.reason = Some(rejection.to_string());
+        event
+    }
+
+    fn from_headers(action: &str, headers: &HeaderMap, decision: Decision) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        };
+        Event {
+            action: action.to_owned(),
+            admin: None,
+            method: header(ORIGINAL_METHOD),
+            uri: header(ORIGINAL_URI),
+            body_digest: header(CONTENT_DIGEST).or_else(|| header(headers::BODY_DIGEST)),
+            decision,
+            reason: None,
+        }
+    }
+}
+
+/// One line of the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Position in the log, from 1.
+    pub seq: u64,
+    /// When the decision was made, in seconds since the Unix epoch.
+    pub time: u64,
+    #[serde(flatten)]
+    pub event: Event,
+    /// Hex SHA-256 of the previous line, or [`GENESIS`].
+    pub prev: String,
+}
+
+/// Appends entries to a log file, continuing its chain.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    tail: Mutex<Tail>,
+}
+
+/// The open file and where its chain has got to.
+#[derive(Debug)]
+struct Tail {
+    file: File,
+    seq: u64,
+    prev: String,
+}
+
+impl AuditLog {
+    /// Opens the log at `path` for appending, creating it if need be. A log
+    /// whose last line was cut short, say by a crash, is refused rather than
+    /// appended to.
+    pub fn open(path: &Path) -> Result<Self, AuditError> {
+        let io = |source| AuditError::Io {
+            path: path.to_owned(),
+            source,
+        };
+        let (mut seq, mut prev) = (0, GENESIS.to_owned());
+        match File::open(path) {
+            Ok(file) => {
+                let mut reader = BufReader::new(file);
+                let mut line = Vec::new();
+                while reader.read_until(b'\n', &mut line).map_err(io)? > 0 {
+                    seq += 1;
+                    if line.pop() != Some(b'\n') {
+                        return Err(AuditError::Broken {
+                            path: path.to_owned(),
+                            line: seq,
+                            reason: "incomplete last line".into(),
+                        });
+                    }
+                    prev = hash(&line);
+                    line.clear();
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(io(e)),
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(io)?;
+        Ok(AuditLog {
+            path: path.to_owned(),
+            tail: Mutex::new(Tail { file, seq, prev }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `event`, decided at `time`, and returns once it is on disk.
+    pub fn record(&self, event: Event, time: u64) -> Result<Entry, AuditError> {
+        let io = |source| AuditError::Io {
+            path: self.path.clone(),
+            source,
+        };
+        let mut tail = self.tail.lock().unwrap();
+        let entry = Entry {
+            seq: tail.seq + 1,
+            time,
+            event,
+            prev: tail.prev.clone(),
+        };
+        let line = serde_json::to_vec(&entry)
+            .map_err(std::io::Error::from)
+            .map_err(io)?;
+        let mut buf = line.clone();
+        buf.push(b'\n');
+        tail.file.write_all(&buf).map_err(io)?;
+        tail.file.sync_data().map_err(io)?;
+        tail.seq = entry.seq;
+        tail.prev = hash(&line);
+        Ok(entry)
+    }
+}
+
+/// Checks the chain of the log at `path` and returns how many entries it
+/// holds.
+pub fn verify(path: &Path) -> Result<u64, AuditError> {
+    let file = File::open(path).map_err(|source| AuditError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+    let broken = |line, reason: String| AuditError::Broken {
+        path: path.to_owned(),
+        line,
+        reason,
+    };
+    let mut prev = GENESIS.to_owned();
+    let mut count = 0;
+    for line in BufReader::new(file).split(b'\n') {
+        let line = line.map_err(|source| AuditError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        count += 1;
+        let entry: Entry =
+            serde_json::from_slice(&line).map_err(|e| broken(count, e.to_string()))?;
+        if entry.seq != count {
+            return Err(broken(
+                count,
+                format!("sequence number {} where {count} was expected", entry.seq),
+            ));
+        }
+        if entry.prev != prev {
+            return Err(broken(
+                count,
+                "does not follow from the line before it".into(),
+            ));
+        }
+        prev = hash(&line);
+    }
+    Ok(count)
+}
+
+fn hash(line: &[u8]) -> String {
+    hex::encode(Sha256::digest(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(admin: &str) -> Event {
+        let mut headers = HeaderMap::new();
+        headers.insert(ORIGINAL_METHOD, "PUT".parse().unwrap());
+        headers.insert(ORIGINAL_URI, "/api/v1/config".parse().unwrap());
+        headers.insert(headers::BODY_DIGEST, "digest".parse().unwrap());
+        Event::allowed("auth", &headers, Some(admin.to_owned()))
+    }
+
+    fn log_of(dir: &Path, admins: &[&str]) -> PathBuf {
+        let path = dir.join("audit.jsonl");
+        let log = AuditLog::open(&path).unwrap();
+        for (time, admin) in admins.iter().enumerate() {
+            log.record(event(admin), time as u64).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn chains_entries_across_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = log_of(dir.path(), &["alice", "bob"]);
+        let entry = AuditLog::open(&path)
+            .unwrap()
+            .record(
+                Event::rejected("login", &HeaderMap::new(), None, &Rejection::BadSignature),
+                3,
+            )
+            .unwrap();
+        assert_eq!(entry.seq, 3);
+        assert_eq!(entry.event.decision, Decision::Rejected);
+        assert_eq!(verify(&path).unwrap(), 3);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let first: Entry = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(first.prev, GENESIS);
+        assert_eq!(first.event.method.as_deref(), Some("PUT"));
+        assert_eq!(first.event.body_digest.as_deref(), Some("digest"));
+    }
+
+    #[test]
+    fn detects_edited_and_deleted_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = log_of(dir.path(), &["alice", "bob", "carol"]);
+        let text = std::fs::read_to_string(&path).unwrap();
+
+        std::fs::write(&path, text.replacen("bob", "eve", 1)).unwrap();
+        assert!(matches!(
+            verify(&path),
+            Err(AuditError::Broken { line: 3, .. })
+        ));
+
+        let lines: Vec<&str> = text.lines().collect();
+        std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        assert!(matches!(
+            verify(&path),
+            Err(AuditError::Broken { line: 2, .. })
+        ));
+
+        std::fs::write(&path, &text[..text.len() - 10]).unwrap();
+        assert!(matches!(
+            AuditLog::open(&path),
+            Err(AuditError::Broken { line: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,186 @@
+//! Replays the verification server's checks against a captured request and
+//! explains which one fails.
+//!
+//! ```sh
+//! hp-admin-verify --hpos-config /run/hpos-config.json --request captured.http
+//! hp-admin-verify --public-key <base64> --method PUT --uri /api/v1/config \
+//!     -H 'X-Hpos-Admin-Signature: …' -H 'X-Hpos-Admin-Timestamp: …' \
+//!     -H 'X-Hpos-Admin-Body-Digest: …' --body-file config.json
+//! ```
+
+use std::error::Error;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Args, Parser};
+
+use hp_admin_crypto::KdfDescriptor;
+use hp_admin_crypto_server::admins::Admin;
+use hp_admin_crypto_server::clock::{Clock, SystemClock};
+use hp_admin_crypto_server::diagnose::{diagnose, CapturedRequest};
+use hp_admin_crypto_server::rotation::{self, RotationRecord};
+use hp_admin_crypto_server::verify::{
+    DEFAULT_MAX_SKEW, ORIGINAL_HOST, ORIGINAL_METHOD, ORIGINAL_SCHEME, ORIGINAL_URI,
+};
+use hp_admin_crypto_server::{AdminKeys, HposConfig};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Explain why the HP Admin verification server rejects a request"
+)]
+struct Cli {
+    #[command(flatten)]
+    key: KeySource,
+
+    /// JSON file listing further admins, as given to the server.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_ADMIN_KEYS")]
+    admin_keys: Option<PathBuf>,
+
+    /// Admin key rotations, as recorded by the server; by default the
+    /// record next to --hpos-config, if any.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_ROTATION_RECORD")]
+    rotation_record: Option<PathBuf>,
+
+    /// Raw HTTP request (request line, headers, blank line, body); `-` reads
+    /// standard input.
+    #[arg(long)]
+    request: Option<PathBuf>,
+
+    /// Method of the original request; overrides the capture.
+    #[arg(long)]
+    method: Option<String>,
+
+    /// URI of the original request; overrides the capture.
+    #[arg(long)]
+    uri: Option<String>,
+
+    /// Scheme of the original request, for RFC 9421 signatures; `https`
+    /// unless the capture says otherwise.
+    #[arg(long)]
+    scheme: Option<String>,
+
+    /// Host of the original request, for RFC 9421 signatures; overrides the
+    /// capture.
+    #[arg(long)]
+    host: Option<String>,
+
+    /// Request header, `Name: value`; may be repeated.
+    #[arg(long, short = 'H')]
+    header: Vec<String>,
+
+    /// Request body; overrides the body of the capture.
+    #[arg(long)]
+    body_file: Option<PathBuf>,
+
+    /// Check the timestamp as of this time (seconds since the Unix epoch)
+    /// instead of now, e.g. the time the request was captured.
+    #[arg(long)]
+    now: Option<u64>,
+
+    /// Seconds a signed timestamp may differ from the server clock.
+    #[arg(long, default_value_t = DEFAULT_MAX_SKEW.as_secs())]
+    max_clock_skew: u64,
+}
+
+#[derive(Debug, Args)]
+#[group(required = true, multiple = false)]
+struct KeySource {
+    /// HPOS config file holding the admin public key.
+    #[arg(long, env = "HPOS_CONFIG_PATH")]
+    hpos_config: Option<PathBuf>,
+
+    /// Base64 admin public key.
+    #[arg(long)]
+    public_key: Option<String>,
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("hp-admin-verify: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<bool, Box<dyn Error>> {
+    let mut admins = admins(&cli.key)?;
+    if let Some(path) = &cli.admin_keys {
+        admins = admins.with_file(path)?;
+    }
+    let record = match (&cli.rotation_record, &cli.key.hpos_config) {
+        (Some(path), _) => Some(path.clone()),
+        (None, Some(config)) => Some(rotation::default_path(config)),
+        (None, None) => None,
+    };
+    if let Some(path) = record {
+        RotationRecord::open(&path)?.apply(&mut admins);
+    }
+
+    let mut request = match &cli.request {
+        Some(path) => CapturedRequest::parse(&read(path)?)?,
+        None => CapturedRequest::default(),
+    };
+    for header in &cli.header {
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| format!("header {header:?} is not `Name: value`"))?;
+        request.set_header(name.trim(), value.trim())?;
+    }
+    if let Some(method) = &cli.method {
+        request.set_header(ORIGINAL_METHOD, method)?;
+    }
+    if let Some(uri) = &cli.uri {
+        request.set_header(ORIGINAL_URI, uri)?;
+    }
+    if let Some(host) = &cli.host {
+        request.set_header(ORIGINAL_HOST, host)?;
+    }
+    match &cli.scheme {
+        Some(scheme) => request.set_header(ORIGINAL_SCHEME, scheme)?,
+        None if !request.headers.contains_key(ORIGINAL_SCHEME) => {
+            request.set_header(ORIGINAL_SCHEME, "https")?
+        }
+        None => {}
+    }
+    if let Some(path) = &cli.body_file {
+        request.body = Some(read(path)?);
+    }
+
+    let now = cli.now.unwrap_or_else(|| SystemClock.now());
+    let report = diagnose(&request, &admins, now, cli.max_clock_skew);
+    println!("{report}");
+    Ok(report.passed())
+}
+
+fn admins(source: &KeySource) -> Result<AdminKeys, Box<dyn Error>> {
+    if let Some(path) = &source.hpos_config {
+        let config = HposConfig::load(path)?;
+        // A client deriving the key with other costs signs with another key.
+        match &config.settings().admin.kdf {
+            Some(_) => println!("admin key derivation: {}", config.kdf()?),
+            None => println!(
+                "admin key derivation: {} (no settings.admin.kdf)",
+                KdfDescriptor::LEGACY
+            ),
+        }
+        println!();
+        return Ok(config.admins()?);
+    }
+    let key = source.public_key.as_deref().unwrap_or_default();
+    let admin = Admin::new("admin", hp_admin_crypto::decode_public_key(key)?)?;
+    Ok(AdminKeys::new(admin))
+}
+
+fn read(path: &PathBuf) -> Result<Vec<u8>, Box<dyn Error>> {
+    if path.as_os_str() == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)?;
+        return Ok(bytes);
+    }
+    std::fs::read(path).map_err(|e| format!("cannot read {}: {e}", path.display()).into())
+}
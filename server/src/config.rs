@@ -0,0 +1,199 @@
+//! Loading the admin public keys from `hpos-config.json`.
+//!
+//! HPOS has shipped several schema versions of the config file, each an
+//! object with a single version key (`{"v2": {...}}`). Every version keeps
+//! the admin under `settings.admin`, plus any further admins under
+//! `settings.admins` (see [`crate::admins`]), which is all the server
+//! needs; the other fields are listed only to document the schemas.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use hp_admin_crypto::{KdfDescriptor, VerifyingKey};
+
+use crate::admins::{self, AdminEntry, AdminKeys, AdminsError};
+
+/// Config schema versions this server understands.
+pub const SUPPORTED_VERSIONS: &[&str] = &["v1", "v2", "v3"];
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("cannot read HPOS config {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("HPOS config {path} is not valid: {reason}")]
+    Malformed { path: PathBuf, reason: String },
+    #[error(
+        "HPOS config {path} has unsupported version {version:?}, expected one of {SUPPORTED_VERSIONS:?}"
+    )]
+    UnsupportedVersion { path: PathBuf, version: String },
+    #[error("HPOS config {path} has an invalid admin public key: {source}")]
+    InvalidAdminKey {
+        path: PathBuf,
+        source: hp_admin_crypto::Error,
+    },
+    #[error("HPOS config {path} has an invalid admin KDF descriptor: {source}")]
+    InvalidKdf {
+        path: PathBuf,
+        source: hp_admin_crypto::Error,
+    },
+    #[error("HPOS config {path} has an invalid admin: {source}")]
+    InvalidAdmins { path: PathBuf, source: AdminsError },
+}
+
+/// An HPOS config of any supported version. Only `settings` has to be
+/// there: a config missing the other fields, which the server does not use,
+/// still loads, rather than locking the admin out.
+#[derive(Debug, Clone, Deserialize)]
+pub enum HposConfig {
+    #[serde(rename = "v1")]
+    V1 {
+        #[serde(default)]
+        seed: Option<String>,
+        settings: Settings,
+    },
+    #[serde(rename = "v2")]
+    V2 {
+        #[serde(default)]
+        device_bundle: Option<String>,
+        #[serde(default)]
+        device_derivation_path: Option<String>,
+        #[serde(default)]
+        registration_code: Option<String>,
+        settings: Settings,
+    },
+    #[serde(rename = "v3")]
+    V3 {
+        #[serde(default)]
+        device_bundle: Option<String>,
+        #[serde(default)]
+        device_derivation_path: Option<String>,
+        #[serde(default)]
+        registration_code: Option<String>,
+        #[serde(default)]
+        holoport_id: Option<String>,
+        settings: Settings,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Settings {
+    pub admin: Admin,
+    /// Admins besides `admin`, for hosts that a team co-manages.
+    #[serde(default)]
+    pub admins: Vec<AdminEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Admin {
+    pub email: String,
+    pub public_key: String,
+    /// How `public_key` was derived from the password, for clients; absent
+    /// on hosts set up before KDF descriptors. See [`HposConfig::kdf`].
+    #[serde(default)]
+    pub kdf: Option<String>,
+}
+
+impl HposConfig {
+    /// Reads and validates the config at `path`.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        Self::parse(path, &text)
+    }
+
+    /// Parses config text; `path` is only used in error messages.
+    pub fn parse(path: &Path, text: &str) -> Result<Self, ConfigError> {
+        let malformed = |reason: String| ConfigError::Malformed {
+            path: path.to_owned(),
+            reason,
+        };
+
+        // Check the version tag by hand so an unknown version gets its own
+        // error instead of serde's "unknown variant".
+        let value: serde_json::Value =
+            serde_json::from_str(text).map_err(|e| malformed(e.to_string()))?;
+        let version = match value.as_object() {
+            Some(object) if object.len() == 1 => object.keys().next().unwrap().clone(),
+            _ => {
+                return Err(malformed(
+                    "expected an object with a single version key".into(),
+                ))
+            }
+        };
+        if !SUPPORTED_VERSIONS.contains(&version.as_str()) {
+            return Err(ConfigError::UnsupportedVersion {
+                path: path.to_owned(),
+                version,
+            });
+        }
+
+        let config: HposConfig =
+            serde_json::from_value(value).map_err(|e| malformed(e.to_string()))?;
+        config
+            .admin_key()
+            .map_err(|source| ConfigError::InvalidAdminKey {
+                path: path.to_owned(),
+                source,
+            })?;
+        config.kdf().map_err(|source| ConfigError::InvalidKdf {
+            path: path.to_owned(),
+            source,
+        })?;
+        config
+            .admins()
+            .map_err(|source| ConfigError::InvalidAdmins {
+                path: path.to_owned(),
+                source,
+            })?;
+        Ok(config)
+    }
+
+    pub fn version(&self) -> &'static str {
+        match self {
+            HposConfig::V1 { .. } => "v1",
+            HposConfig::V2 { .. } => "v2",
+            HposConfig::V3 { .. } => "v3",
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        match self {
+            HposConfig::V1 { settings, .. }
+            | HposConfig::V2 { settings, .. }
+            | HposConfig::V3 { settings, .. } => settings,
+        }
+    }
+
+    /// The public key of `settings.admin`.
+    pub fn admin_key(&self) -> Result<VerifyingKey, hp_admin_crypto::Error> {
+        hp_admin_crypto::decode_public_key(&self.settings().admin.public_key)
+    }
+
+    /// How the key of `settings.admin` was derived from the password:
+    /// `settings.admin.kdf`, or [`KdfDescriptor::LEGACY`] if there is none.
+    pub fn kdf(&self) -> Result<KdfDescriptor, hp_admin_crypto::Error> {
+        match &self.settings().admin.kdf {
+            Some(kdf) => kdf.parse(),
+            None => Ok(KdfDescriptor::LEGACY),
+        }
+    }
+
+    /// Every admin allowed to administer this host, `settings.admin` first
+    /// under its email.
+    pub fn admins(&self) -> Result<AdminKeys, AdminsError> {
+        let settings = self.settings();
+        let key = self.admin_key().map_err(|source| AdminsError::InvalidKey {
+            id: settings.admin.email.clone(),
+            source,
+        })?;
+        AdminKeys::new(admins::Admin::new(&settings.admin.email, key)?)
+            .with_entries(&settings.admins)
+    }
+}
@@ -0,0 +1,737 @@
+//! Step-by-step replay of the server's checks against a captured request,
+//! for working out why a call was rejected.
+
+use std::fmt;
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+
+use hp_admin_crypto::delegation::DelegationChain;
+use hp_admin_crypto::{rfc9421, BodyDigest, ContentDigest, Payload, VerifyingKey};
+
+use crate::admins::{Admin, AdminKeys};
+use crate::verify::{
+    check_body, check_delegation, check_timestamp, delegation, identify, Claims, Scheme,
+    SignedMessage, SignedRequest, ORIGINAL_HOST, ORIGINAL_METHOD, ORIGINAL_URI,
+};
+
+/// A request as captured from the wire or from nginx logs.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedRequest {
+    pub headers: HeaderMap,
+    /// The request body, if it was captured.
+    pub body: Option<Vec<u8>>,
+}
+
+impl CapturedRequest {
+    /// Parses a raw HTTP/1 request: request line, headers, blank line, body.
+    ///
+    /// The request line and `Host` supply the original method, URI and host
+    /// unless the capture is of a subrequest that already carries
+    /// `X-Original-Method`, `X-Original-URI` and `X-Original-Host`.
+    pub fn parse(raw: &[u8]) -> Result<Self, String> {
+        let (head, body) = split_head(raw);
+        let head = std::str::from_utf8(head).map_err(|_| "request head is not UTF-8")?;
+        let mut lines = head.lines();
+
+        let request_line = lines.next().unwrap_or_default();
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(format!("bad request line {request_line:?}"));
+        };
+
+        let mut captured = CapturedRequest {
+            headers: HeaderMap::new(),
+            body: (!body.is_empty()).then(|| body.to_vec()),
+        };
+        for line in lines {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("bad header line {line:?}"))?;
+            captured.append_header(name.trim(), value.trim())?;
+        }
+        if !captured.headers.contains_key(ORIGINAL_METHOD) {
+            captured.append_header(ORIGINAL_METHOD, method)?;
+        }
+        if !captured.headers.contains_key(ORIGINAL_URI) {
+            captured.append_header(ORIGINAL_URI, target)?;
+        }
+        if let Some(host) = captured.headers.get("host").cloned() {
+            if !captured.headers.contains_key(ORIGINAL_HOST) {
+                captured.headers.append(ORIGINAL_HOST, host);
+            }
+        }
+        Ok(captured)
+    }
+
+    pub fn append_header(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let name = HeaderName::try_from(name).map_err(|e| format!("header {name:?}: {e}"))?;
+        let value = HeaderValue::try_from(value).map_err(|e| format!("header {name}: {e}"))?;
+        self.headers.append(name, value);
+        Ok(())
+    }
+
+    /// Replaces every value of `name` with `value`.
+    pub fn set_header(&mut self, name: &str, value: &str) -> Result<(), String> {
+        self.headers.remove(name);
+        self.append_header(name, value)
+    }
+}
+
+fn split_head(raw: &[u8]) -> (&[u8], &[u8]) {
+    for separator in [&b"\r\n\r\n"[..], b"\n\n"] {
+        if let Some(at) = raw.windows(separator.len()).position(|w| w == separator) {
+            return (&raw[..at], &raw[at + separator.len()..]);
+        }
+    }
+    (raw, &[])
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    /// Not checked, either because it does not apply or cannot be done
+    /// offline.
+    Skipped,
+}
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub name: &'static str,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+
+/// Result of every check, in the order the server runs them.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub steps: Vec<Step>,
+    /// The scheme the request was signed with, once known.
+    pub scheme: Option<Scheme>,
+    /// The bytes the client should have signed, once known: the canonical
+    /// payload or the RFC 9421 signature base.
+    pub canonical: Option<Vec<u8>>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| step.outcome != Outcome::Failed)
+    }
+
+    /// The first failing check, which is the one the server rejects on.
+    pub fn first_failure(&self) -> Option<&Step> {
+        self.steps
+            .iter()
+            .find(|step| step.outcome == Outcome::Failed)
+    }
+
+    fn push(&mut self, name: &'static str, outcome: Outcome, detail: impl Into<String>) {
+        self.steps.push(Step {
+            name,
+            outcome,
+            detail: detail.into(),
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(canonical) = &self.canonical {
+            let what = match self.scheme {
+                Some(Scheme::Rfc9421) => "signature base",
+                _ => "canonical payload",
+            };
+            writeln!(f, "{what} ({} bytes):", canonical.len())?;
+            for line in String::from_utf8_lossy(canonical).split_inclusive('\n') {
+                writeln!(f, "    {}", line.escape_debug())?;
+            }
+            writeln!(f)?;
+        }
+        for step in &self.steps {
+            let tag = match step.outcome {
+                Outcome::Passed => " ok ",
+                Outcome::Failed => "FAIL",
+                Outcome::Skipped => "skip",
+            };
+            writeln!(f, "[{tag}] {}: {}", step.name, step.detail)?;
+        }
+        match self.first_failure() {
+            Some(step) => write!(f, "\nrejected at: {}", step.name),
+            None => write!(f, "\naccepted"),
+        }
+    }
+}
+
+/// Runs the server's checks one at a time against `request`, as of `now`.
+///
+/// The checks are run against the key of the admin the request comes from,
+/// or of the first admin if no admin key verifies it. Nonces and replays
+/// depend on server state and are reported as skipped.
+pub fn diagnose(request: &CapturedRequest, admins: &AdminKeys, now: u64, max_skew: u64) -> Report {
+    let mut report = Report::default();
+    let delegation = match delegation(&request.headers) {
+        Ok(delegation) => delegation,
+        Err(e) => {
+            report.push("delegation", Outcome::Failed, e.to_string());
+            return report;
+        }
+    };
+    let admin_key = match signed_by(request, admins, now, delegation.as_ref()) {
+        Some((admin, key)) => {
+            let retiring = match admin.retiring() {
+                Some(retiring) if retiring.key == *key => {
+                    format!(", retiring at {}", retiring.retires)
+                }
+                _ => String::new(),
+            };
+            report.push(
+                "admin",
+                Outcome::Passed,
+                format!(
+                    "{}, key {}{retiring}",
+                    admin.id(),
+                    hp_admin_crypto::encode_public_key(key)
+                ),
+            );
+            key
+        }
+        None => admins.primary().key(),
+    };
+    let signer = match &delegation {
+        None => admin_key,
+        Some(chain) => {
+            let count = chain.certificates().len();
+            if chain.verify(admin_key).is_err() {
+                report.push(
+                    "delegation",
+                    Outcome::Failed,
+                    format!(
+                        "{count} certificate(s) do not lead back to {}",
+                        hp_admin_crypto::encode_public_key(admin_key)
+                    ),
+                );
+                return report;
+            }
+            report.push(
+                "delegation",
+                Outcome::Passed,
+                format!(
+                    "{count} certificate(s) from the admin key to {}",
+                    hp_admin_crypto::encode_public_key(chain.delegate())
+                ),
+            );
+            chain.delegate()
+        }
+    };
+
+    let claims = if request.headers.contains_key(rfc9421::SIGNATURE_INPUT) {
+        report.scheme = Some(Scheme::Rfc9421);
+        diagnose_message(request, signer, &mut report)
+    } else {
+        report.scheme = Some(Scheme::Legacy);
+        diagnose_legacy(request, signer, &mut report)
+    };
+    let Some(mut claims) = claims else {
+        return report;
+    };
+
+    if let Some(chain) = delegation {
+        let scope = format!("until {}", chain.expires());
+        claims.delegation = Some(chain);
+        match check_delegation(&claims, now) {
+            Ok(()) => report.push(
+                "delegated scope",
+                Outcome::Passed,
+                format!("{} {} allowed {scope}", claims.method, claims.uri),
+            ),
+            Err(e) => report.push("delegated scope", Outcome::Failed, e.to_string()),
+        }
+    }
+
+    if claims.nonce.is_some() {
+        report.push(
+            "timestamp",
+            Outcome::Skipped,
+            "request signs a nonce, so the timestamp window does not apply",
+        );
+    } else {
+        let skew = claims.timestamp as i128 - now as i128;
+        match check_timestamp(&claims, now, max_skew) {
+            Ok(()) => report.push(
+                "timestamp",
+                Outcome::Passed,
+                format!("signed {skew:+}s from now, within ±{max_skew}s"),
+            ),
+            Err(_) => report.push(
+                "timestamp",
+                Outcome::Failed,
+                format!(
+                    "signed at {}, {skew:+}s from now ({now}), outside ±{max_skew}s",
+                    claims.timestamp
+                ),
+            ),
+        }
+    }
+
+    match &request.body {
+        None => report.push("body digest", Outcome::Skipped, "no body captured"),
+        Some(body) => match check_body(&claims, body) {
+            Ok(()) => report.push(
+                "body digest",
+                Outcome::Passed,
+                format!("{} body bytes match the signed digest", body.len()),
+            ),
+            Err(_) => report.push(
+                "body digest",
+                Outcome::Failed,
+                format!(
+                    "{} body bytes hash to {}, signed digest is {}",
+                    body.len(),
+                    ContentDigest::of(body),
+                    claims.content_digest
+                ),
+            ),
+        },
+    }
+
+    match &claims.nonce {
+        Some(nonce) => report.push(
+            "nonce",
+            Outcome::Skipped,
+            format!("{nonce:?} can only be checked against the server's nonce store"),
+        ),
+        None => report.push(
+            "replay",
+            Outcome::Skipped,
+            "can only be checked against the server's replay cache",
+        ),
+    }
+
+    report
+}
+
+/// The admin, and which of their keys, that verifies the request's
+/// signature or delegation, if any does.
+fn signed_by<'a>(
+    request: &CapturedRequest,
+    admins: &'a AdminKeys,
+    now: u64,
+    delegation: Option<&DelegationChain>,
+) -> Option<(&'a Admin, &'a VerifyingKey)> {
+    if request.headers.contains_key(rfc9421::SIGNATURE_INPUT) {
+        let signed = SignedMessage::from_headers(&request.headers).ok()?;
+        identify(admins, now, delegation, |key| signed.verify(key)).ok()
+    } else {
+        let signed = SignedRequest::from_headers(&request.headers).ok()?;
+        identify(admins, now, delegation, |key| signed.verify(key)).ok()
+    }
+}
+
+/// The checks up to and including the signature, for the `X-Hpos-Admin-*`
+/// headers. Returns the claims if the later checks can still be run.
+fn diagnose_legacy(
+    request: &CapturedRequest,
+    key: &VerifyingKey,
+    report: &mut Report,
+) -> Option<Claims> {
+    let signed = match SignedRequest::from_headers(&request.headers) {
+        Ok(signed) => signed,
+        Err(e) => {
+            report.push("headers", Outcome::Failed, e.to_string());
+            return None;
+        }
+    };
+    report.push(
+        "headers",
+        Outcome::Passed,
+        format!("{} {}", signed.method, signed.uri),
+    );
+
+    let payload = match signed.payload() {
+        Ok(payload) => payload,
+        Err(e) => {
+            report.push("payload", Outcome::Failed, e.to_string());
+            return None;
+        }
+    };
+    report.canonical = Some(payload.to_bytes());
+    report.push("payload", Outcome::Passed, "canonical payload rebuilt");
+
+    let claims = match signed.claims() {
+        Ok(claims) => claims,
+        Err(e) => {
+            report.push("signature", Outcome::Failed, e.to_string());
+            return None;
+        }
+    };
+    match hp_admin_crypto::verify(key, &payload, &claims.signature) {
+        Ok(()) => report.push(
+            "signature",
+            Outcome::Passed,
+            format!(
+                "verifies against {}",
+                hp_admin_crypto::encode_public_key(key)
+            ),
+        ),
+        Err(_) => {
+            let mut detail = format!(
+                "does not verify against {}",
+                hp_admin_crypto::encode_public_key(key)
+            );
+            if let Some(hint) =
+                signature_hint(&payload, request.body.as_deref(), key, &claims.signature)
+            {
+                detail.push_str("; ");
+                detail.push_str(&hint);
+            }
+            report.push("signature", Outcome::Failed, detail);
+        }
+    }
+    Some(claims)
+}
+
+/// The checks up to and including the signature, for RFC 9421 message
+/// signatures. Returns the claims if the later checks can still be run.
+fn diagnose_message(
+    request: &CapturedRequest,
+    key: &VerifyingKey,
+    report: &mut Report,
+) -> Option<Claims> {
+    let signed = match SignedMessage::from_headers(&request.headers) {
+        Ok(signed) => signed,
+        Err(e) => {
+            report.push("headers", Outcome::Failed, e.to_string());
+            return None;
+        }
+    };
+    report.push(
+        "headers",
+        Outcome::Passed,
+        format!(
+            "{} {}, signature {:?}",
+            signed.request.method(),
+            signed.request.target_uri(),
+            signed.signature.label()
+        ),
+    );
+
+    match rfc9421::signature_base(&signed.request, signed.signature.params()) {
+        Ok(base) => {
+            report.canonical = Some(base.into_bytes());
+            report.push("signature base", Outcome::Passed, "signature base rebuilt");
+        }
+        Err(e) => {
+            report.push("signature base", Outcome::Failed, e.to_string());
+            return None;
+        }
+    }
+
+    if let Err(e) = signed.check_params(key) {
+        report.push("signature parameters", Outcome::Failed, e.to_string());
+        return None;
+    }
+    report.push(
+        "signature parameters",
+        Outcome::Passed,
+        signed.signature.params().to_string(),
+    );
+
+    let claims = match signed.claims() {
+        Ok(claims) => claims,
+        Err(e) => {
+            report.push("content digest", Outcome::Failed, e.to_string());
+            return None;
+        }
+    };
+    match signed.signature.verify(key, &signed.request) {
+        Ok(()) => report.push(
+            "signature",
+            Outcome::Passed,
+            format!(
+                "verifies against {}",
+                hp_admin_crypto::encode_public_key(key)
+            ),
+        ),
+        Err(hp_admin_crypto::Error::BadSignature) => report.push(
+            "signature",
+            Outcome::Failed,
+            format!(
+                "does not verify against {}",
+                hp_admin_crypto::encode_public_key(key)
+            ),
+        ),
+        Err(e) => report.push("signature", Outcome::Failed, e.to_string()),
+    }
+    Some(claims)
+}
+
+/// Looks for a nearby payload that the signature does cover, to point at
+/// the field the client and server disagree on.
+fn signature_hint(
+    payload: &Payload,
+    body: Option<&[u8]>,
+    key: &VerifyingKey,
+    signature: &hp_admin_crypto::Signature,
+) -> Option<String> {
+    let rebuild = |uri: &str, digest: BodyDigest| {
+        let rebuilt =
+            Payload::with_digest(payload.method(), uri, digest, payload.timestamp()).ok()?;
+        match payload.nonce() {
+            Some(nonce) => rebuilt.with_nonce(nonce).ok(),
+            None => Some(rebuilt),
+        }
+    };
+    let verifies = |candidate: Option<Payload>| {
+        candidate
+            .is_some_and(|candidate| hp_admin_crypto::verify(key, &candidate, signature).is_ok())
+    };
+
+    if let Some(body) = body {
+        if verifies(rebuild(payload.uri(), BodyDigest::of(body))) {
+            return Some(
+                "it does verify over the captured body, so the body digest header is wrong".into(),
+            );
+        }
+    }
+    if let Some((path, _)) = payload.uri().split_once('?') {
+        if verifies(rebuild(path, *payload.body_digest())) {
+            return Some(format!(
+                "it does verify for {path:?}, so the client signed the URI without its query string"
+            ));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hp_admin_crypto::{headers, MessageSignatureHeaders, SignatureHeaders, SigningKey};
+
+    const NOW: u64 = 1_600_000_000;
+
+    fn admin() -> SigningKey {
+        SigningKey::from_bytes(&[42; 32])
+    }
+
+    fn admins() -> AdminKeys {
+        AdminKeys::new(Admin::new("admin", admin().verifying_key()).unwrap())
+    }
+
+    fn capture(method: &str, uri: &str, signed: &SignatureHeaders, body: &[u8]) -> Vec<u8> {
+        let mut raw = format!("{method} {uri} HTTP/1.1\r\nHost: hpos\r\n");
+        for (name, value) in signed.iter() {
+            raw.push_str(&format!("{name}: {value}\r\n"));
+        }
+        raw.push_str("\r\n");
+        let mut raw = raw.into_bytes();
+        raw.extend_from_slice(body);
+        raw
+    }
+
+    fn sign(uri: &str, body: &[u8], timestamp: u64) -> SignatureHeaders {
+        let payload = Payload::new("PUT", uri, body, timestamp).unwrap();
+        SignatureHeaders::sign(&admin(), &payload)
+    }
+
+    fn failure(report: &Report) -> &'static str {
+        report
+            .first_failure()
+            .map(|step| step.name)
+            .unwrap_or("none")
+    }
+
+    #[test]
+    fn accepted_request() {
+        let raw = capture(
+            "PUT",
+            "/api/v1/config",
+            &sign("/api/v1/config", b"{}", NOW),
+            b"{}",
+        );
+        let report = diagnose(&CapturedRequest::parse(&raw).unwrap(), &admins(), NOW, 60);
+        assert!(report.passed(), "{report}");
+        assert!(report.to_string().contains("hp-admin-crypto/v1\\n"));
+    }
+
+    #[test]
+    fn names_the_admin_that_signed() {
+        let alice = SigningKey::from_bytes(&[7; 32]);
+        let admins = admins()
+            .with_admin(Admin::new("alice", alice.verifying_key()).unwrap())
+            .unwrap();
+        let payload = Payload::new("GET", "/api/v1/status", b"", NOW).unwrap();
+        let raw = capture(
+            "GET",
+            "/api/v1/status",
+            &SignatureHeaders::sign(&alice, &payload),
+            b"",
+        );
+        let report = diagnose(&CapturedRequest::parse(&raw).unwrap(), &admins, NOW, 60);
+        assert!(report.passed(), "{report}");
+        assert!(report.to_string().contains("admin: alice"));
+
+        // Nobody's key verifies a forgery, so it is checked against the first
+        // admin's.
+        let forged = capture(
+            "GET",
+            "/api/v1/status",
+            &SignatureHeaders::sign(&SigningKey::from_bytes(&[9; 32]), &payload),
+            b"",
+        );
+        let report = diagnose(&CapturedRequest::parse(&forged).unwrap(), &admins, NOW, 60);
+        assert_eq!(failure(&report), "signature");
+        assert!(!report.to_string().contains("admin:"));
+    }
+
+    #[test]
+    fn names_the_failing_check() {
+        let admins = admins();
+        let check = |raw: Vec<u8>, now| {
+            failure(&diagnose(
+                &CapturedRequest::parse(&raw).unwrap(),
+                &admins,
+                now,
+                60,
+            ))
+        };
+
+        let signed = sign("/api/v1/config", b"{}", NOW);
+        assert_eq!(
+            check(capture("PUT", "/api/v1/config", &signed, b"{}"), NOW + 61),
+            "timestamp"
+        );
+        assert_eq!(
+            check(capture("PUT", "/api/v1/config", &signed, b"{ }"), NOW),
+            "body digest"
+        );
+        assert_eq!(
+            check(capture("POST", "/api/v1/config", &signed, b"{}"), NOW),
+            "signature"
+        );
+
+        let mut missing = signed.clone();
+        missing.body_digest = Some("??".into());
+        assert_eq!(
+            check(capture("PUT", "/api/v1/config", &missing, b""), NOW),
+            "payload"
+        );
+
+        let raw = b"PUT /api/v1/config HTTP/1.1\r\n\r\n".to_vec();
+        assert_eq!(check(raw, NOW), "headers");
+    }
+
+    #[test]
+    fn hints_at_a_wrong_body_digest_header() {
+        let mut signed = sign("/api/v1/config", b"{}", NOW);
+        signed.body_digest = Some(BodyDigest::of(b"other").to_string());
+        signed.content_digest = None;
+        let raw = capture("PUT", "/api/v1/config", &signed, b"{}");
+        let report = diagnose(&CapturedRequest::parse(&raw).unwrap(), &admins(), NOW, 60);
+        assert!(
+            report.to_string().contains("body digest header is wrong"),
+            "{report}"
+        );
+    }
+
+    #[test]
+    fn hints_at_a_dropped_query_string() {
+        let signed = sign("/api/v1/config", b"", NOW);
+        let raw = capture("PUT", "/api/v1/config?force=1", &signed, b"");
+        let report = diagnose(&CapturedRequest::parse(&raw).unwrap(), &admins(), NOW, 60);
+        assert!(
+            report.to_string().contains("without its query string"),
+            "{report}"
+        );
+    }
+
+    #[test]
+    fn subrequest_capture_keeps_original_method_and_uri() {
+        let signed = sign("/api/v1/config", b"", NOW);
+        let raw = format!(
+            "GET /hp-admin-auth HTTP/1.0\nX-Original-Method: PUT\nX-Original-URI: /api/v1/config\n{}: {}\n{}: {}\n{}: {}\n\n",
+            headers::SIGNATURE,
+            signed.signature,
+            headers::TIMESTAMP,
+            signed.timestamp,
+            headers::CONTENT_DIGEST,
+            signed.content_digest.unwrap()
+        )
+        .into_bytes();
+        let captured = CapturedRequest::parse(&raw).unwrap();
+        assert!(captured.body.is_none());
+        let report = diagnose(&captured, &admins(), NOW, 60);
+        assert!(report.passed(), "{report}");
+    }
+
+    #[test]
+    fn message_signatures() {
+        let signed = MessageSignatureHeaders::sign(
+            &admin(),
+            "PUT",
+            "https://hpos.example/api/v1/config",
+            b"{}",
+            NOW,
+            None,
+        )
+        .unwrap();
+        let capture = |host: &str, body: &[u8]| {
+            let mut raw = format!(
+                "PUT /api/v1/config HTTP/1.1\r\nHost: {host}\r\nX-Original-Scheme: https\r\n"
+            );
+            for (name, value) in signed.iter() {
+                raw.push_str(&format!("{name}: {value}\r\n"));
+            }
+            let mut raw = format!("{raw}\r\n").into_bytes();
+            raw.extend_from_slice(body);
+            let request = CapturedRequest::parse(&raw).unwrap();
+            diagnose(&request, &admins(), NOW, 60)
+        };
+
+        let report = capture("hpos.example", b"{}");
+        assert!(report.passed(), "{report}");
+        assert!(report.to_string().contains("signature base ("), "{report}");
+        let base = String::from_utf8(report.canonical.unwrap()).unwrap();
+        assert!(base.contains("\"@target-uri\": https://hpos.example/api/v1/config\n"));
+        assert_eq!(failure(&capture("other.example", b"{}")), "signature");
+        assert_eq!(failure(&capture("hpos.example", b"{ }")), "body digest");
+    }
+
+    #[test]
+    fn delegated_requests() {
+        use hp_admin_crypto::delegation::{Delegation, DelegationChain, DELEGATION};
+
+        let bot = SigningKey::from_bytes(&[8; 32]);
+        let chain = |issuer: &SigningKey| {
+            let delegation = Delegation::new(
+                &bot.verifying_key(),
+                &["GET"],
+                &["/api/v1/status"],
+                NOW + 3600,
+            )
+            .unwrap();
+            DelegationChain::new(vec![delegation.sign(issuer)]).unwrap()
+        };
+        let check = |method: &str, chain: &DelegationChain, now| {
+            let payload = Payload::new(method, "/api/v1/status", b"", NOW).unwrap();
+            let signed = SignatureHeaders::sign(&bot, &payload);
+            let mut raw = capture(method, "/api/v1/status", &signed, b"");
+            let head = format!("{DELEGATION}: {chain}\r\n\r\n");
+            raw.truncate(raw.len() - 2);
+            raw.extend_from_slice(head.as_bytes());
+            let report = diagnose(&CapturedRequest::parse(&raw).unwrap(), &admins(), now, 60);
+            failure(&report)
+        };
+
+        assert_eq!(check("GET", &chain(&admin()), NOW), "none");
+        assert_eq!(check("PUT", &chain(&admin()), NOW), "delegated scope");
+        assert_eq!(check("GET", &chain(&admin()), NOW + 61), "timestamp");
+        assert_eq!(
+            check("GET", &chain(&admin()), NOW + 3601),
+            "delegated scope"
+        );
+        assert_eq!(check("GET", &chain(&bot), NOW), "delegation");
+    }
+}
@@ -0,0 +1,53 @@
+//! Server half of HP Admin authentication.
+//!
+//! nginx sends an `auth_request` subrequest here, over TCP or a Unix socket
+//! (see [`listen`]), for every call to an HPOS admin endpoint. The
+//! subrequest carries the original method and URI in `X-Original-Method`
+//! and `X-Original-URI` plus the signature headers
+//! defined by [`hp_admin_crypto::headers`], or instead RFC 9421
+//! `Signature-Input` and `Signature` headers (see
+//! [`hp_admin_crypto::rfc9421`]); we answer 200 if the signature
+//! verifies against the HPOS admin public key, 401 if it does not and 400 if
+//! the subrequest is malformed. The admin public key comes from the HPOS
+//! config file (see [`config`]), which may list further admins, each
+//! labelled with an id that is passed on to the upstream (see [`admins`]),
+//! and is reloaded whenever it changes (see [`reload`]). A signature is only
+//! accepted within a configurable window around its signed timestamp, and
+//! only once. Hosts
+//! with unreliable clocks can instead require each request to sign a
+//! single-use nonce issued by `POST /nonce` (see [`nonce`]). A client may also
+//! sign a single call to `POST /login` and send the short-lived session token
+//! it gets back instead of signing every call (see [`session`]), until it
+//! logs out or every session is revoked (see [`revocation`]). An optional
+//! policy then limits which admins may make which calls (see [`policy`]).
+//! An admin whose password changes hands their key over to the new one with
+//! a signed `POST /rotate`, and the old key stays valid for a grace period
+//! (see [`rotation`]). Every decision can be appended to a hash-chained
+//! audit log (see [`audit`]) and is counted in Prometheus metrics served on a
+//! separate address (see [`metrics`]). Clients that keep failing to verify
+//! are answered 429 for a while (see [`ratelimit`]). Under systemd the server
+//! reports readiness and status and feeds the watchdog (see [`systemd`]).
+
+pub mod admins;
+pub mod audit;
+pub mod clock;
+pub mod config;
+pub mod diagnose;
+pub mod listen;
+pub mod metrics;
+pub mod nonce;
+pub mod policy;
+pub mod ratelimit;
+pub mod reload;
+pub mod replay;
+pub mod revocation;
+pub mod rotation;
+pub mod server;
+pub mod session;
+pub mod systemd;
+pub mod verify;
+
+pub use admins::AdminKeys;
+pub use config::HposConfig;
+pub use server::router;
+pub use verify::{Rejection, Verifier};
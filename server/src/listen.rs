@@ -0,0 +1,302 @@
+//! Where the server listens: a TCP address, a Unix socket, or a socket that
+//! systemd opened for us.
+//!
+//! On HPOS nginx runs on the same host, so a Unix socket that only nginx's
+//! user may connect to leaves no TCP port open at all. With systemd socket
+//! activation (see `sd_listen_fds(3)`) the `.socket` unit creates the
+//! socket, with its own ownership and mode, and starts the server on the
+//! first connection.
+
+use std::fs::Permissions;
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use thiserror::Error;
+use tokio::net::{TcpListener, UnixListener};
+
+/// The first file descriptor systemd passes.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+#[derive(Debug, Error)]
+pub enum ListenError {
+    #[error("cannot listen on {addr}: {source}")]
+    Tcp { addr: SocketAddr, source: io::Error },
+    #[error("cannot listen on {path}: {source}")]
+    Unix { path: PathBuf, source: io::Error },
+    #[error("{0} exists and is not a socket")]
+    NotASocket(PathBuf),
+    #[error("invalid owner {0:?}: expected user, user:group or :group")]
+    InvalidOwner(String),
+    #[error("no user {0:?}")]
+    UnknownUser(String),
+    #[error("no group {0:?}")]
+    UnknownGroup(String),
+    #[error("socket activation: {0}")]
+    Activation(String),
+}
+
+/// Who owns a Unix socket, by name or number; either half may be left as
+/// it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Owner {
+    pub user: Option<String>,
+    pub group: Option<String>,
+}
+
+impl FromStr for Owner {
+    type Err = ListenError;
+
+    fn from_str(s: &str) -> Result<Self, ListenError> {
+        let (user, group) = match s.split_once(':') {
+            Some((user, group)) => (user, Some(group)),
+            None => (s, None),
+        };
+        let part = |name: &str| (!name.is_empty()).then(|| name.to_owned());
+        let owner = Owner {
+            user: part(user),
+            group: group.and_then(part),
+        };
+        if owner.user.is_none() && owner.group.is_none() || group == Some("") {
+            return Err(ListenError::InvalidOwner(s.to_owned()));
+        }
+        Ok(owner)
+    }
+}
+
+impl Owner {
+    /// The numeric ids, looked up in `/etc/passwd` and `/etc/group` unless
+    /// they are numbers already.
+    fn ids(&self) -> Result<(Option<u32>, Option<u32>), ListenError> {
+        let user = match &self.user {
+            None => None,
+            Some(user) => Some(
+                lookup("/etc/passwd", user)
+                    .ok_or_else(|| ListenError::UnknownUser(user.clone()))?,
+            ),
+        };
+        let group = match &self.group {
+            None => None,
+            Some(group) => Some(
+                lookup("/etc/group", group)
+                    .ok_or_else(|| ListenError::UnknownGroup(group.clone()))?,
+            ),
+        };
+        Ok((user, group))
+    }
+}
+
+/// The id of `name` in a passwd(5) or group(5) style file, whose third
+/// field is the id; a number is its own id.
+fn lookup(file: &str, name: &str) -> Option<u32> {
+    if let Ok(id) = name.parse() {
+        return Some(id);
+    }
+    std::fs::read_to_string(file)
+        .ok()?
+        .lines()
+        .find_map(|line| {
+            let mut fields = line.split(':');
+            (fields.next() == Some(name))
+                .then(|| fields.nth(1)?.parse().ok())
+                .flatten()
+        })
+}
+
+/// Sets the process umask until dropped.
+struct Umask(libc::mode_t);
+
+impl Umask {
+    fn set(mask: libc::mode_t) -> Self {
+        // SAFETY: umask(2) cannot fail and touches no memory.
+        Umask(unsafe { libc::umask(mask) })
+    }
+}
+
+impl Drop for Umask {
+    fn drop(&mut self) {
+        // SAFETY: as above.
+        unsafe { libc::umask(self.0) };
+    }
+}
+
+/// A bound listener of either kind.
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    pub async fn tcp(addr: SocketAddr) -> Result<Self, ListenError> {
+        TcpListener::bind(addr)
+            .await
+            .map(Listener::Tcp)
+            .map_err(|source| ListenError::Tcp { addr, source })
+    }
+
+    /// Binds a Unix socket at `path`, replacing a stale socket left there,
+    /// and gives it `mode` and `owner`.
+    pub fn unix(path: &Path, mode: u32, owner: Option<&Owner>) -> Result<Self, ListenError> {
+        let unix = |source| ListenError::Unix {
+            path: path.to_owned(),
+            source,
+        };
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => {
+                std::fs::remove_file(path).map_err(unix)?
+            }
+            Ok(_) => return Err(ListenError::NotASocket(path.to_owned())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(unix(e)),
+        }
+        let (uid, gid) = owner.map(Owner::ids).transpose()?.unwrap_or_default();
+        // Created with no permissions at all, so that nobody can connect
+        // before the socket has its mode and owner. The umask is the whole
+        // process's, but nothing else creates files while the server starts.
+        let listener = {
+            let _umask = Umask::set(0o777);
+            UnixListener::bind(path).map_err(unix)?
+        };
+        std::fs::set_permissions(path, Permissions::from_mode(mode)).map_err(unix)?;
+        if uid.is_some() || gid.is_some() {
+            std::os::unix::fs::chown(path, uid, gid).map_err(unix)?;
+        }
+        Ok(Listener::Unix(listener))
+    }
+
+    /// The socket systemd passed us, if it started us through a socket unit.
+    /// This takes ownership of the descriptor, so call it at most once.
+    pub fn activated() -> Result<Option<Self>, ListenError> {
+        let Ok(fds) = std::env::var("LISTEN_FDS") else {
+            return Ok(None);
+        };
+        let pid = std::env::var("LISTEN_PID").ok();
+        if pid.and_then(|pid| pid.parse().ok()) != Some(std::process::id()) {
+            return Ok(None);
+        }
+        if fds != "1" {
+            return Err(ListenError::Activation(format!(
+                "expected one socket, got LISTEN_FDS={fds}"
+            )));
+        }
+        // Taking ownership of a descriptor that is not open, or not a
+        // socket, would have us close something we do not own.
+        let inherited = format!("/proc/self/fd/{SD_LISTEN_FDS_START}");
+        match std::fs::read_link(&inherited) {
+            Ok(target) if target.to_string_lossy().starts_with("socket:") => {}
+            _ => {
+                return Err(ListenError::Activation(format!(
+                    "descriptor {SD_LISTEN_FDS_START} is not an inherited socket"
+                )))
+            }
+        }
+        // SAFETY: with LISTEN_PID naming us, systemd has handed this process
+        // the open socket, and nothing else in it refers to the descriptor
+        // yet.
+        let fd = unsafe { OwnedFd::from_raw_fd(SD_LISTEN_FDS_START) };
+        Listener::from_fd(fd).map(Some)
+    }
+
+    /// Wraps a listening socket of either kind.
+    pub fn from_fd(fd: OwnedFd) -> Result<Self, ListenError> {
+        let activation = |e: io::Error| ListenError::Activation(e.to_string());
+        let unix = std::os::unix::net::UnixListener::from(fd);
+        // Only succeeds for a Unix socket.
+        if unix.local_addr().is_ok() {
+            unix.set_nonblocking(true).map_err(activation)?;
+            return UnixListener::from_std(unix)
+                .map(Listener::Unix)
+                .map_err(activation);
+        }
+        let tcp = std::net::TcpListener::from(OwnedFd::from(unix));
+        tcp.local_addr().map_err(activation)?;
+        tcp.set_nonblocking(true).map_err(activation)?;
+        TcpListener::from_std(tcp)
+            .map(Listener::Tcp)
+            .map_err(activation)
+    }
+
+    /// Where it listens, for logging.
+    pub fn describe(&self) -> String {
+        let addr = match self {
+            Listener::Tcp(listener) => listener.local_addr().map(|addr| addr.to_string()),
+            Listener::Unix(listener) => {
+                listener.local_addr().map(|addr| match addr.as_pathname() {
+                    Some(path) => path.display().to_string(),
+                    None => "an unnamed Unix socket".to_owned(),
+                })
+            }
+        };
+        addr.unwrap_or_else(|e| format!("an unknown address ({e})"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use super::*;
+
+    #[test]
+    fn parses_owners() {
+        let owner = |s: &str| s.parse::<Owner>();
+        assert_eq!(
+            owner("nginx:hpos").unwrap(),
+            Owner {
+                user: Some("nginx".into()),
+                group: Some("hpos".into())
+            }
+        );
+        assert_eq!(owner("nginx").unwrap().group, None);
+        assert_eq!(owner(":0").unwrap().user, None);
+        for invalid in ["", ":", "nginx:"] {
+            assert!(owner(invalid).is_err(), "{invalid:?}");
+        }
+        assert_eq!(owner("root:0").unwrap().ids().unwrap(), (Some(0), Some(0)));
+        assert!(matches!(
+            owner("no-such-user-here").unwrap().ids(),
+            Err(ListenError::UnknownUser(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn binds_unix_sockets_with_their_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hp-admin-crypto.sock");
+        let _umask = Umask::set(0o027);
+        // A stale socket is replaced, anything else is left alone.
+        drop(Listener::unix(&path, 0o600, None).unwrap());
+        let listener = Listener::unix(&path, 0o660, None).unwrap();
+        assert!(matches!(listener, Listener::Unix(_)));
+        assert_eq!(listener.describe(), path.display().to_string());
+        assert_eq!(std::fs::metadata(&path).unwrap().mode() & 0o777, 0o660);
+        // The umask in force before is restored.
+        assert_eq!(Umask::set(0o027).0, 0o027);
+
+        let file = dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        assert!(matches!(
+            Listener::unix(&file, 0o660, None),
+            Err(ListenError::NotASocket(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn wraps_inherited_sockets_of_either_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let unix = std::os::unix::net::UnixListener::bind(dir.path().join("s")).unwrap();
+        assert!(matches!(
+            Listener::from_fd(unix.into()).unwrap(),
+            Listener::Unix(_)
+        ));
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        assert!(matches!(
+            Listener::from_fd(tcp.into()).unwrap(),
+            Listener::Tcp(_)
+        ));
+    }
+}
@@ -0,0 +1,502 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing_subscriber::EnvFilter;
+
+use hp_admin_crypto_server::audit::{self, AuditLog};
+use hp_admin_crypto_server::listen::{Listener, Owner};
+use hp_admin_crypto_server::metrics::{self, Metrics};
+use hp_admin_crypto_server::nonce::{
+    FileNonceStore, MemoryNonceStore, NonceStore, DEFAULT_MAX_OUTSTANDING, DEFAULT_NONCE_TTL,
+};
+use hp_admin_crypto_server::policy::{Policy, PolicyFile, Via};
+use hp_admin_crypto_server::ratelimit::RateLimits;
+use hp_admin_crypto_server::reload::{self, AdminSource, LoadedAdmins};
+use hp_admin_crypto_server::revocation::{
+    FileRevocationStore, MemoryRevocationStore, RevocationStore,
+};
+use hp_admin_crypto_server::rotation::{self, RotationRecord, DEFAULT_ROTATION_GRACE};
+use hp_admin_crypto_server::session::{Sessions, DEFAULT_SESSION_TTL};
+use hp_admin_crypto_server::systemd;
+use hp_admin_crypto_server::verify::{DEFAULT_MAX_SKEW, DEFAULT_REPLAY_CACHE_SIZE};
+use hp_admin_crypto_server::{router, Verifier};
+
+#[derive(Debug, Parser)]
+#[command(version, about, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Address to serve `auth_request` subrequests on.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_LISTEN", default_value = "127.0.0.1:2884")]
+    listen: SocketAddr,
+
+    /// Unix socket to serve `auth_request` subrequests on, instead of
+    /// --listen. A socket passed by systemd socket activation takes
+    /// precedence over both.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_UNIX_SOCKET")]
+    unix_socket: Option<PathBuf>,
+
+    /// Permissions of the Unix socket, in octal.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_UNIX_SOCKET_MODE", default_value = "660", value_parser = parse_mode)]
+    unix_socket_mode: u32,
+
+    /// Owner of the Unix socket, as `user`, `user:group` or `:group`.
+    #[arg(
+        long,
+        env = "HP_ADMIN_CRYPTO_UNIX_SOCKET_OWNER",
+        requires = "unix_socket"
+    )]
+    unix_socket_owner: Option<Owner>,
+
+    /// Address to serve Prometheus metrics on, at `/metrics`; must differ
+    /// from --listen. No metrics are served if unset.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_METRICS_LISTEN")]
+    metrics_listen: Option<SocketAddr>,
+
+    /// HPOS config file holding the admin public key, reloaded when it
+    /// changes and on SIGHUP.
+    #[arg(long, env = "HPOS_CONFIG_PATH", required = true)]
+    hpos_config: Option<PathBuf>,
+
+    /// JSON file listing further admins, as `[{"id": ..., "public_key": ...}]`;
+    /// reloaded along with the HPOS config.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_ADMIN_KEYS")]
+    admin_keys: Option<PathBuf>,
+
+    /// Require the forwarded request body to match the signed body digest.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_VERIFY_BODY")]
+    verify_body: bool,
+
+    /// Seconds a signed timestamp may differ from the server clock.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_MAX_CLOCK_SKEW", default_value_t = DEFAULT_MAX_SKEW.as_secs())]
+    max_clock_skew: u64,
+
+    /// Number of recent signatures remembered to reject replays.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_REPLAY_CACHE_SIZE", default_value_t = DEFAULT_REPLAY_CACHE_SIZE)]
+    replay_cache_size: usize,
+
+    /// Refuse requests that do not sign a nonce from `POST /nonce`.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_REQUIRE_NONCE")]
+    require_nonce: bool,
+
+    /// Refuse requests signed with the X-Hpos-Admin headers and accept only
+    /// RFC 9421 message signatures.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_REQUIRE_RFC9421")]
+    require_rfc9421: bool,
+
+    /// Seconds an issued nonce stays valid.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_NONCE_TTL", default_value_t = DEFAULT_NONCE_TTL)]
+    nonce_ttl: u64,
+
+    /// File that keeps outstanding nonces across restarts; in memory if unset.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_NONCE_STORE")]
+    nonce_store: Option<PathBuf>,
+
+    /// Seconds a session token from `POST /login` stays valid.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_SESSION_TTL", default_value_t = DEFAULT_SESSION_TTL.as_secs())]
+    session_ttl: u64,
+
+    /// File holding the key that signs session tokens, created if missing,
+    /// so that tokens survive a restart; a new key every start if unset.
+    /// Requires --revocation-list, so that revocations survive too.
+    #[arg(
+        long,
+        env = "HP_ADMIN_CRYPTO_SESSION_KEY",
+        requires = "revocation_list"
+    )]
+    session_key: Option<PathBuf>,
+
+    /// File that keeps revoked session tokens across restarts; in memory,
+    /// which only --session-key makes unsafe, if unset.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_REVOCATION_LIST")]
+    revocation_list: Option<PathBuf>,
+
+    /// Policy file limiting which admins may make which calls, reloaded on
+    /// SIGHUP; every verified call is let through if unset.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_POLICY")]
+    policy: Option<PathBuf>,
+
+    /// Accept admin key rotations on `POST /rotate`; off by default, when
+    /// rotations already recorded still apply.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_ENABLE_ROTATION")]
+    enable_rotation: bool,
+
+    /// File recording admin key rotations; `hp-admin-rotations.json` next
+    /// to the HPOS config if unset.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_ROTATION_RECORD")]
+    rotation_record: Option<PathBuf>,
+
+    /// Seconds an admin's old key stays valid after rotating to a new one.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_ROTATION_GRACE", default_value_t = DEFAULT_ROTATION_GRACE.as_secs())]
+    rotation_grace: u64,
+
+    /// JSON-lines file to append a hash-chained entry to for every decision;
+    /// no audit log if unset.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_AUDIT_LOG")]
+    audit_log: Option<PathBuf>,
+
+    /// Failed verifications a client address may make in a row before it is
+    /// locked out.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_CLIENT_FAILURE_BURST", default_value_t = RateLimits::default().client_burst)]
+    client_failure_burst: u32,
+
+    /// Seconds it takes a client address to earn back one failure.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_CLIENT_FAILURE_INTERVAL", default_value_t = RateLimits::default().client_interval)]
+    client_failure_interval: u64,
+
+    /// Failed verifications all clients together may make in a row before
+    /// every call is turned away.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_GLOBAL_FAILURE_BURST", default_value_t = RateLimits::default().global_burst)]
+    global_failure_burst: u32,
+
+    /// Seconds it takes to earn back one failure overall.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_GLOBAL_FAILURE_INTERVAL", default_value_t = RateLimits::default().global_interval)]
+    global_failure_interval: u64,
+
+    /// Seconds a client address is first locked out for; each further
+    /// lockout doubles it.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_LOCKOUT", default_value_t = RateLimits::default().lockout)]
+    lockout: u64,
+
+    /// Most seconds a client address is ever locked out for.
+    #[arg(long, env = "HP_ADMIN_CRYPTO_MAX_LOCKOUT", default_value_t = RateLimits::default().max_lockout)]
+    max_lockout: u64,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Check a policy file before deploying it, and optionally whether it
+    /// allows a call.
+    CheckPolicy(CheckPolicy),
+
+    /// Check that no line of an audit log has been edited, removed or
+    /// reordered.
+    VerifyLog { path: PathBuf },
+}
+
+#[derive(Debug, Args)]
+struct CheckPolicy {
+    path: PathBuf,
+
+    /// Admin id making the call to check.
+    #[arg(long, requires = "method")]
+    admin: Option<String>,
+
+    /// How the call authenticates: signature, delegation or session.
+    #[arg(long, default_value = "signature", requires = "method")]
+    via: Via,
+
+    /// Method of the call to check.
+    #[arg(requires_all = ["admin", "uri"])]
+    method: Option<String>,
+
+    /// URI of the call to check.
+    uri: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::CheckPolicy(args)) => return check_policy(args),
+        Some(Command::VerifyLog { path }) => return verify_log(path),
+        None => {}
+    }
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            tracing::error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    // Before anything else opens a file and might be handed descriptor 3.
+    let activated = Listener::activated()?;
+    if cli.unix_socket.is_none() && cli.metrics_listen == Some(cli.listen) {
+        return Err("--metrics-listen must differ from --listen".into());
+    }
+    let hpos_config = cli.hpos_config.as_deref().expect("required by clap");
+    let record_path = match &cli.rotation_record {
+        Some(path) => path.clone(),
+        None => rotation::default_path(hpos_config),
+    };
+    let rotations = Arc::new(RotationRecord::open(&record_path)?);
+    let source = AdminSource::new(hpos_config, cli.admin_keys.as_deref(), rotations.clone());
+    let loaded = source.load()?;
+    log_admins(&source, &loaded);
+    let nonces: Arc<dyn NonceStore> = match &cli.nonce_store {
+        Some(path) => Arc::new(FileNonceStore::open(path, DEFAULT_MAX_OUTSTANDING)?),
+        None => Arc::new(MemoryNonceStore::default()),
+    };
+    let revocations: Arc<dyn RevocationStore> = match &cli.revocation_list {
+        Some(path) => Arc::new(FileRevocationStore::open(path)?),
+        None => Arc::new(MemoryRevocationStore::default()),
+    };
+    let session_ttl = Duration::from_secs(cli.session_ttl);
+    let sessions = match &cli.session_key {
+        Some(path) => Sessions::open(path, session_ttl)?,
+        None => Sessions::generate(session_ttl)?,
+    }
+    .with_revocation_store(revocations);
+    let metrics = Arc::new(Metrics::default());
+    let mut verifier = Verifier::new(loaded.admins).with_metrics(metrics.clone());
+    let mut policy = None;
+    if let Some(path) = &cli.policy {
+        let file = Arc::new(PolicyFile::open(path)?);
+        tracing::info!(
+            "loaded policy {} with {} rules",
+            path.display(),
+            file.policy().len()
+        );
+        verifier = verifier.with_policy(file.clone());
+        policy = Some(file);
+    }
+    if cli.enable_rotation {
+        verifier = verifier.with_rotation(rotations, Duration::from_secs(cli.rotation_grace));
+        tracing::info!("accepting key rotations into {}", record_path.display());
+    }
+    if let Some(path) = &cli.audit_log {
+        verifier = verifier.with_audit_log(Arc::new(AuditLog::open(path)?));
+        tracing::info!("auditing decisions to {}", path.display());
+    }
+    let verifier = Arc::new(
+        verifier
+            .with_body_check(cli.verify_body)
+            .with_max_skew(Duration::from_secs(cli.max_clock_skew))
+            .with_replay_cache_size(cli.replay_cache_size)
+            .with_nonce_store(nonces, Duration::from_secs(cli.nonce_ttl))
+            .with_nonce_required(cli.require_nonce)
+            .with_rfc9421_required(cli.require_rfc9421)
+            .with_sessions(sessions)
+            .with_rate_limits(RateLimits {
+                client_burst: cli.client_failure_burst,
+                client_interval: cli.client_failure_interval,
+                global_burst: cli.global_failure_burst,
+                global_interval: cli.global_failure_interval,
+                lockout: cli.lockout,
+                max_lockout: cli.max_lockout,
+            }),
+    );
+
+    let source = Arc::new(source);
+    reload::watch(&source.files(), {
+        let (verifier, source) = (verifier.clone(), source.clone());
+        move |path| {
+            tracing::info!("{} changed", path.display());
+            reload_admins(&verifier, &source);
+        }
+    })?;
+    tokio::spawn(reload_on_sighup(verifier.clone(), source, policy));
+
+    if let Some(addr) = cli.metrics_listen {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        tracing::info!("serving metrics on {addr}");
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, metrics::router(metrics)).await {
+                tracing::error!("metrics listener failed: {e}");
+            }
+        });
+    }
+
+    let (listener, socket) = match activated {
+        Some(listener) => {
+            tracing::info!("using the socket passed by systemd");
+            (listener, None)
+        }
+        None => match &cli.unix_socket {
+            Some(path) => (
+                Listener::unix(path, cli.unix_socket_mode, cli.unix_socket_owner.as_ref())?,
+                Some(path),
+            ),
+            None => (Listener::tcp(cli.listen).await?, None),
+        },
+    };
+    tracing::info!("listening on {}", listener.describe());
+    systemd::ready(&systemd::status_line(
+        &verifier.admins(),
+        verifier.metrics(),
+    ));
+    tokio::spawn(notify_systemd(verifier.clone()));
+    let app = router(verifier);
+    let served = match listener {
+        Listener::Tcp(listener) => {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown_signal())
+                .await
+        }
+        Listener::Unix(listener) => {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown_signal())
+                .await
+        }
+    };
+    if let Some(path) = socket {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(served?)
+}
+
+/// Parses a file mode written in octal, such as `660` or `0o660`.
+fn parse_mode(s: &str) -> Result<u32, String> {
+    let digits = s.strip_prefix("0o").unwrap_or(s);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!("{s:?} is not an octal file mode")),
+    }
+}
+
+/// Reloads the admins, and the policy if there is one, whenever we get
+/// SIGHUP, as `systemctl reload` sends.
+async fn reload_on_sighup(
+    verifier: Arc<Verifier>,
+    source: Arc<AdminSource>,
+    policy: Option<Arc<PolicyFile>>,
+) {
+    let mut sighup = signal(SignalKind::hangup()).expect("SIGHUP handler");
+    while sighup.recv().await.is_some() {
+        reload_admins(&verifier, &source);
+        let Some(policy) = &policy else {
+            continue;
+        };
+        match policy.reload() {
+            Ok(reloaded) => {
+                tracing::info!(
+                    "reloaded policy {} with {} rules",
+                    policy.path().display(),
+                    reloaded.len()
+                );
+                verifier.metrics().reloaded(true);
+            }
+            Err(e) => {
+                tracing::error!("keeping the previous policy: {e}");
+                verifier.metrics().reloaded(false);
+            }
+        }
+    }
+}
+
+/// Puts the admins from `source` in force, or keeps the previous ones if
+/// they no longer load.
+fn reload_admins(verifier: &Verifier, source: &AdminSource) {
+    match verifier.reload_admins(source) {
+        Ok(loaded) => {
+            log_admins(source, &loaded);
+            verifier.metrics().reloaded(true);
+            systemd::status(&systemd::status_line(
+                &verifier.admins(),
+                verifier.metrics(),
+            ));
+        }
+        Err(e) => {
+            tracing::error!("keeping the previous admin keys: {e}");
+            verifier.metrics().reloaded(false);
+        }
+    }
+}
+
+fn log_admins(source: &AdminSource, loaded: &LoadedAdmins) {
+    tracing::info!(
+        "loaded {} config {}",
+        loaded.version,
+        source.hpos_config().display()
+    );
+    for (rotation, e) in &loaded.skipped {
+        tracing::warn!(
+            "skipping rotation of admin {} from {}: {e}",
+            rotation.admin,
+            source.rotations().path().display()
+        );
+    }
+    for admin in loaded.admins.iter() {
+        tracing::info!(
+            "admin {}: public key {}",
+            admin.id(),
+            hp_admin_crypto::encode_public_key(admin.key())
+        );
+        if let Some(retiring) = admin.retiring() {
+            tracing::info!(
+                "admin {}: previous key {} retires at {}",
+                admin.id(),
+                hp_admin_crypto::encode_public_key(&retiring.key),
+                retiring.retires
+            );
+        }
+    }
+}
+
+/// Validates a policy file and answers whether it allows the given call.
+fn check_policy(args: &CheckPolicy) -> ExitCode {
+    let policy = match Policy::load(&args.path) {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("{}: {} rules", args.path.display(), policy.len());
+    let (Some(admin), Some(method), Some(uri)) = (&args.admin, &args.method, &args.uri) else {
+        return ExitCode::SUCCESS;
+    };
+    if policy.allows(admin, args.via, method, uri) {
+        println!("{method} {uri} by {admin} via {}: allowed", args.via);
+        ExitCode::SUCCESS
+    } else {
+        println!("{method} {uri} by {admin} via {}: refused", args.via);
+        ExitCode::FAILURE
+    }
+}
+
+/// Checks the hash chain of an audit log.
+fn verify_log(path: &Path) -> ExitCode {
+    match audit::verify(path) {
+        Ok(entries) => {
+            println!("{}: {entries} entries, chain intact", path.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Resolves on SIGINT or SIGTERM, the latter being how systemd stops us.
+async fn shutdown_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = sigterm.recv() => {}
+    }
+    systemd::stopping();
+}
+
+/// Feeds the systemd watchdog, if there is one, and keeps the status line
+/// up to date.
+async fn notify_systemd(verifier: Arc<Verifier>) {
+    let watchdog = systemd::watchdog_interval();
+    let mut ticks = tokio::time::interval(watchdog.unwrap_or(systemd::STATUS_INTERVAL));
+    loop {
+        ticks.tick().await;
+        if watchdog.is_some() {
+            systemd::watchdog();
+        }
+        systemd::status(&systemd::status_line(
+            &verifier.admins(),
+            verifier.metrics(),
+        ));
+    }
+}
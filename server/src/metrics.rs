@@ -0,0 +1,191 @@
+//! Counters for alerting on authentication anomalies, in the Prometheus
+//! text exposition format.
+//!
+//! Every decision the [`Verifier`](crate::Verifier) makes is counted, by the
+//! endpoint that made it and, for rejections, by why; verification latency
+//! goes in a histogram. The metrics are served by [`router`] on a listen
+//! address of their own, so that they are never reachable through nginx's
+//! `auth_request` location.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+];
+
+/// Everything counted since start-up.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    accepted: Mutex<BTreeMap<&'static str, u64>>,
+    rejected: Mutex<BTreeMap<(&'static str, &'static str), u64>>,
+    latency: Histogram,
+    reloads: AtomicU64,
+    failed_reloads: AtomicU64,
+}
+
+impl Metrics {
+    /// Counts an accepted call to the `action` endpoint.
+    pub fn accepted(&self, action: &'static str, elapsed: Duration) {
+        *self.accepted.lock().unwrap().entry(action).or_default() += 1;
+        self.latency.observe(elapsed);
+    }
+
+    /// Counts a call to the `action` endpoint rejected for `reason`.
+    pub fn rejected(&self, action: &'static str, reason: &'static str, elapsed: Duration) {
+        *self
+            .rejected
+            .lock()
+            .unwrap()
+            .entry((action, reason))
+            .or_default() += 1;
+        self.latency.observe(elapsed);
+    }
+
+    /// Counts an attempt to reload configuration, successful or not.
+    pub fn reloaded(&self, ok: bool) {
+        match ok {
+            true => &self.reloads,
+            false => &self.failed_reloads,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// How many calls have been accepted and rejected, across endpoints.
+    pub fn totals(&self) -> (u64, u64) {
+        let accepted = self.accepted.lock().unwrap().values().sum();
+        let rejected = self.rejected.lock().unwrap().values().sum();
+        (accepted, rejected)
+    }
+
+    /// Renders every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP hp_admin_accepted_total Admin calls accepted.\n");
+        out.push_str("# TYPE hp_admin_accepted_total counter\n");
+        for (action, count) in self.accepted.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "hp_admin_accepted_total{{action=\"{action}\"}} {count}"
+            );
+        }
+
+        out.push_str("# HELP hp_admin_rejected_total Admin calls rejected, by reason.\n");
+        out.push_str("# TYPE hp_admin_rejected_total counter\n");
+        for ((action, reason), count) in self.rejected.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "hp_admin_rejected_total{{action=\"{action}\",reason=\"{reason}\"}} {count}"
+            );
+        }
+
+        out.push_str("# HELP hp_admin_verification_seconds Time taken to decide on a call.\n");
+        out.push_str("# TYPE hp_admin_verification_seconds histogram\n");
+        self.latency
+            .render("hp_admin_verification_seconds", &mut out);
+
+        out.push_str("# HELP hp_admin_config_reloads_total Configuration reloads, by result.\n");
+        out.push_str("# TYPE hp_admin_config_reloads_total counter\n");
+        for (result, count) in [("ok", &self.reloads), ("failed", &self.failed_reloads)] {
+            let _ = writeln!(
+                out,
+                "hp_admin_config_reloads_total{{result=\"{result}\"}} {}",
+                count.load(Ordering::Relaxed)
+            );
+        }
+        out
+    }
+}
+
+/// A latency histogram with the fixed [`LATENCY_BUCKETS`].
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative; the last is `+Inf`.
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, name: &str, out: &mut String) {
+        let mut cumulative = 0;
+        let bounds = LATENCY_BUCKETS.iter().map(|bound| bound.to_string());
+        for (bound, count) in bounds.chain(["+Inf".to_owned()]).zip(&self.buckets) {
+            cumulative += count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{name}_sum {sum}");
+        let _ = writeln!(out, "{name}_count {cumulative}");
+    }
+}
+
+/// Builds the router for the metrics listener: `GET /metrics` and nothing
+/// else.
+pub fn router(metrics: Arc<Metrics>) -> Router {
+    Router::new()
+        .route("/metrics", get(render))
+        .with_state(metrics)
+}
+
+async fn render(State(metrics): State<Arc<Metrics>>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_histogram() {
+        let metrics = Metrics::default();
+        metrics.accepted("auth", Duration::from_micros(300));
+        metrics.accepted("auth", Duration::from_millis(3));
+        metrics.rejected("auth", "replay", Duration::from_secs(2));
+        metrics.reloaded(true);
+        assert_eq!(metrics.totals(), (2, 1));
+
+        let text = metrics.render();
+        for line in [
+            "hp_admin_accepted_total{action=\"auth\"} 2",
+            "hp_admin_rejected_total{action=\"auth\",reason=\"replay\"} 1",
+            "hp_admin_verification_seconds_bucket{le=\"0.0005\"} 1",
+            "hp_admin_verification_seconds_bucket{le=\"0.001\"} 1",
+            "hp_admin_verification_seconds_bucket{le=\"0.005\"} 2",
+            "hp_admin_verification_seconds_bucket{le=\"1\"} 2",
+            "hp_admin_verification_seconds_bucket{le=\"+Inf\"} 3",
+            "hp_admin_verification_seconds_sum 2.0033",
+            "hp_admin_verification_seconds_count 3",
+            "hp_admin_config_reloads_total{result=\"ok\"} 1",
+            "hp_admin_config_reloads_total{result=\"failed\"} 0",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "{line} missing from\n{text}"
+            );
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Single-use challenge nonces for hosts whose clocks cannot be trusted.
+//!
+//! The client fetches a nonce from the server, signs it into its payload, and
+//! the server consumes it on the first request that verifies. Outstanding
+//! nonces live in a [`NonceStore`]: [`MemoryNonceStore`] forgets them on
+//! restart, [`FileNonceStore`] keeps them in a JSON file.
+//!
+//! Anyone can ask for a nonce, so a store holds only so many, and only
+//! [`MAX_OUTSTANDING_PER_CLIENT`] of them for any one client address; one
+//! client cannot use up every nonce on its own.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
+use serde::Serialize;
+use thiserror::Error;
+
+/// How long an issued nonce stays valid, by default.
+pub const DEFAULT_NONCE_TTL: u64 = 60;
+/// How many unexpired nonces may be outstanding at once, by default.
+pub const DEFAULT_MAX_OUTSTANDING: usize = 1024;
+/// How many unexpired nonces one client address may have outstanding.
+pub const MAX_OUTSTANDING_PER_CLIENT: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum NonceError {
+    #[error("too many outstanding nonces")]
+    Full,
+    #[error("too many outstanding nonces for {0}")]
+    ClientFull(IpAddr),
+    #[error("nonce store {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("nonce store {path} is corrupt: {source}")]
+    Corrupt {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("no randomness available: {0}")]
+    Random(getrandom::Error),
+}
+
+/// Somewhere to keep the nonces that have been issued but not yet used.
+pub trait NonceStore: Send + Sync {
+    /// Records a new nonce for `client`, if known, that stays valid until
+    /// `expires`.
+    fn insert(
+        &self,
+        nonce: &str,
+        client: Option<IpAddr>,
+        expires: u64,
+        now: u64,
+    ) -> Result<(), NonceError>;
+
+    /// Removes `nonce`, returning whether it was outstanding and unexpired.
+    fn take(&self, nonce: &str, now: u64) -> Result<bool, NonceError>;
+}
+
+/// A freshly issued nonce, as returned to the client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IssuedNonce {
+    pub nonce: String,
+    /// Seconds since the Unix epoch after which the nonce is refused.
+    pub expires: u64,
+}
+
+/// Generates 256 random bits, base64url-encoded.
+pub fn generate() -> Result<String, NonceError> {
+    let mut bytes = [0; 32];
+    getrandom::fill(&mut bytes).map_err(NonceError::Random)?;
+    Ok(BASE64_URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// Outstanding nonces and their expiry times; the bookkeeping shared by
+/// every store.
+#[derive(Debug, Default)]
+struct Outstanding {
+    capacity: usize,
+    expires: HashMap<String, u64>,
+    /// Who each nonce was issued to, where known. Not kept on disk, so
+    /// nonces from before a restart count against no client.
+    clients: HashMap<String, IpAddr>,
+}
+
+impl Outstanding {
+    fn insert(
+        &mut self,
+        nonce: &str,
+        client: Option<IpAddr>,
+        expires: u64,
+        now: u64,
+    ) -> Result<(), NonceError> {
+        self.expires.retain(|_, &mut expires| expires >= now);
+        self.clients
+            .retain(|nonce, _| self.expires.contains_key(nonce));
+        if self.expires.len() >= self.capacity {
+            return Err(NonceError::Full);
+        }
+        if let Some(client) = client {
+            let held = self.clients.values().filter(|&&c| c == client).count();
+            if held >= MAX_OUTSTANDING_PER_CLIENT {
+                return Err(NonceError::ClientFull(client));
+            }
+            self.clients.insert(nonce.to_owned(), client);
+        }
+        self.expires.insert(nonce.to_owned(), expires);
+        Ok(())
+    }
+
+    fn take(&mut self, nonce: &str, now: u64) -> bool {
+        self.clients.remove(nonce);
+        matches!(self.expires.remove(nonce), Some(expires) if expires >= now)
+    }
+}
+
+/// Keeps nonces in memory; a restart invalidates every outstanding nonce.
+#[derive(Debug)]
+pub struct MemoryNonceStore(Mutex<Outstanding>);
+
+impl MemoryNonceStore {
+    pub fn new(capacity: usize) -> Self {
+        MemoryNonceStore(Mutex::new(Outstanding {
+            capacity,
+            ..Default::default()
+        }))
+    }
+}
+
+impl Default for MemoryNonceStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_OUTSTANDING)
+    }
+}
+
+impl NonceStore for MemoryNonceStore {
+    fn insert(
+        &self,
+        nonce: &str,
+        client: Option<IpAddr>,
+        expires: u64,
+        now: u64,
+    ) -> Result<(), NonceError> {
+        self.0.lock().unwrap().insert(nonce, client, expires, now)
+    }
+
+    fn take(&self, nonce: &str, now: u64) -> Result<bool, NonceError> {
+        Ok(self.0.lock().unwrap().take(nonce, now))
+    }
+}
+
+/// Keeps nonces in a JSON file, rewritten atomically on every change, so
+/// that outstanding nonces survive a restart and used ones stay used.
+#[derive(Debug)]
+pub struct FileNonceStore {
+    path: PathBuf,
+    outstanding: Mutex<Outstanding>,
+}
+
+impl FileNonceStore {
+    /// Opens the store at `path`, which need not exist yet.
+    pub fn open(path: &Path, capacity: usize) -> Result<Self, NonceError> {
+        let expires = match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|source| NonceError::Corrupt {
+                path: path.to_owned(),
+                source,
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(source) => {
+                return Err(NonceError::Io {
+                    path: path.to_owned(),
+                    source,
+                })
+            }
+        };
+        Ok(FileNonceStore {
+            path: path.to_owned(),
+            outstanding: Mutex::new(Outstanding {
+                capacity,
+                expires,
+                ..Default::default()
+            }),
+        })
+    }
+
+    fn save(&self, outstanding: &Outstanding) -> Result<(), NonceError> {
+        let io = |source| NonceError::Io {
+            path: self.path.clone(),
+            source,
+        };
+        let tmp = self.path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp).map_err(io)?;
+        serde_json::to_writer(&mut file, &outstanding.expires)
+            .map_err(std::io::Error::from)
+            .map_err(io)?;
+        file.flush().map_err(io)?;
+        file.sync_all().map_err(io)?;
+        std::fs::rename(&tmp, &self.path).map_err(io)
+    }
+}
+
+impl NonceStore for FileNonceStore {
+    fn insert(
+        &self,
+        nonce: &str,
+        client: Option<IpAddr>,
+        expires: u64,
+        now: u64,
+    ) -> Result<(), NonceError> {
+        let mut outstanding = self.outstanding.lock().unwrap();
+        outstanding.insert(nonce, client, expires, now)?;
+        self.save(&outstanding)
+    }
+
+    fn take(&self, nonce: &str, now: u64) -> Result<bool, NonceError> {
+        let mut outstanding = self.outstanding.lock().unwrap();
+        let taken = outstanding.take(nonce, now);
+        if taken {
+            self.save(&outstanding)?;
+        }
+        Ok(taken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single_use(store: &dyn NonceStore) {
+        store.insert("a", None, 160, 100).unwrap();
+        assert!(store.take("a", 120).unwrap());
+        assert!(!store.take("a", 120).unwrap());
+        assert!(!store.take("never-issued", 120).unwrap());
+    }
+
+    fn expires(store: &dyn NonceStore) {
+        store.insert("b", None, 160, 100).unwrap();
+        assert!(!store.take("b", 161).unwrap());
+    }
+
+    #[test]
+    fn memory_store() {
+        single_use(&MemoryNonceStore::default());
+        expires(&MemoryNonceStore::default());
+    }
+
+    #[test]
+    fn memory_store_is_bounded() {
+        let store = MemoryNonceStore::new(2);
+        store.insert("a", None, 160, 100).unwrap();
+        store.insert("b", None, 160, 100).unwrap();
+        assert!(matches!(
+            store.insert("c", None, 160, 100),
+            Err(NonceError::Full)
+        ));
+        // Expired nonces make room.
+        store.insert("c", None, 260, 200).unwrap();
+    }
+
+    #[test]
+    fn bounds_each_client() {
+        let store = MemoryNonceStore::default();
+        let (client, other) = (
+            Some(IpAddr::from([192, 0, 2, 1])),
+            Some(IpAddr::from([192, 0, 2, 2])),
+        );
+        for n in 0..MAX_OUTSTANDING_PER_CLIENT {
+            store.insert(&n.to_string(), client, 160, 100).unwrap();
+        }
+        assert!(matches!(
+            store.insert("full", client, 160, 100),
+            Err(NonceError::ClientFull(_))
+        ));
+        store.insert("other", other, 160, 100).unwrap();
+        store.insert("unknown", None, 160, 100).unwrap();
+        // Using a nonce makes room for another.
+        assert!(store.take("0", 110).unwrap());
+        store.insert("full", client, 160, 110).unwrap();
+    }
+
+    #[test]
+    fn file_store_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nonces.json");
+
+        let store = FileNonceStore::open(&path, 16).unwrap();
+        single_use(&store);
+        expires(&store);
+        store.insert("kept", None, 160, 100).unwrap();
+        store.insert("used", None, 160, 100).unwrap();
+        assert!(store.take("used", 110).unwrap());
+        drop(store);
+
+        let reopened = FileNonceStore::open(&path, 16).unwrap();
+        assert!(!reopened.take("used", 120).unwrap());
+        assert!(reopened.take("kept", 120).unwrap());
+    }
+
+    #[test]
+    fn file_store_rejects_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nonces.json");
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            FileNonceStore::open(&path, 16),
+            Err(NonceError::Corrupt { .. })
+        ));
+    }
+
+    #[test]
+    fn generated_nonces_are_valid_and_distinct() {
+        let a = generate().unwrap();
+        let b = generate().unwrap();
+        assert_ne!(a, b);
+        hp_admin_crypto::Payload::new("GET", "/", b"", 0)
+            .unwrap()
+            .with_nonce(&a)
+            .unwrap();
+    }
+}
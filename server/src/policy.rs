@@ -0,0 +1,544 @@
+//! Which admins may make which calls, beyond holding a valid key.
+//!
+//! A policy is a TOML file of `[[rule]]` tables, whose keys are strings or
+//! arrays of strings.
+//!
+//! ```toml
+//! # The host's admins may do anything.
+//! [[rule]]
+//! admins = ["admin@example.com", "alice"]
+//! allow = ["* /**"]
+//!
+//! # Delegated keys may only read the status.
+//! [[rule]]
+//! via = ["delegation"]
+//! allow = ["GET /api/v1/status"]
+//! ```
+//!
+//! A rule applies to the admins it lists by id (every admin if `admins` is
+//! left out) when they authenticate in one of the ways listed in `via`
+//! (`signature`, `delegation` or `session`; any way if left out). `allow`
+//! lists method and path pairs; `*` stands for any method. In paths, `*`
+//! matches within one segment and a `**` segment matches any number of
+//! segments, so `/api/v1/**` allows `/api/v1` and everything under it. The
+//! query string is ignored.
+//!
+//! A call is allowed if any rule that applies to it allows it, after its
+//! signature or session token has been verified; every other call is
+//! refused. [`PolicyFile`] reloads the policy on demand, keeping the old one
+//! if the new one is invalid.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use serde::de::{self, Deserializer, IntoDeserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+use thiserror::Error;
+
+use hp_admin_crypto::delegation::path_of;
+
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    #[error("cannot read policy {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The error locates the offending line and column, where it has one.
+    #[error("{}: {source}", path.display())]
+    Invalid {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+}
+
+/// How a call proved which admin it comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Via {
+    /// Signed with the admin's own key.
+    Signature,
+    /// Signed by a delegate of the admin.
+    Delegation,
+    /// With a session token the admin logged in for.
+    Session,
+}
+
+impl FromStr for Via {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "signature" => Ok(Via::Signature),
+            "delegation" => Ok(Via::Delegation),
+            "session" => Ok(Via::Session),
+            _ => Err(format!(
+                "unknown via {s:?}, expected signature, delegation or session"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Via {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Via::Signature => "signature",
+            Via::Delegation => "delegation",
+            Via::Session => "session",
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Via {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// A parsed policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    rules: Vec<Rule>,
+}
+
+/// A policy file as written.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rules {
+    #[serde(default)]
+    rule: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RuleTable")]
+struct Rule {
+    /// Admin ids, or `None` for every admin.
+    admins: Option<Vec<String>>,
+    via: Option<Vec<Via>>,
+    allow: Vec<Permission>,
+}
+
+/// A `[[rule]]` table as written, before the checks that span its keys.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleTable {
+    #[serde(default, deserialize_with = "some_one_or_many")]
+    admins: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "some_one_or_many")]
+    via: Option<Vec<Via>>,
+    #[serde(deserialize_with = "one_or_many")]
+    allow: Vec<Permission>,
+}
+
+/// One `"METHOD /path/glob"` of a rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Permission {
+    /// Upper-cased, or `None` for `*`.
+    method: Option<String>,
+    path: String,
+}
+
+impl Policy {
+    /// Reads and validates the policy at `path`.
+    pub fn load(path: &Path) -> Result<Self, PolicyError> {
+        let text = std::fs::read_to_string(path).map_err(|source| PolicyError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        Self::parse(path, &text)
+    }
+
+    /// Parses policy text; `path` is only used in error messages.
+    pub fn parse(path: &Path, text: &str) -> Result<Self, PolicyError> {
+        let invalid = |source| PolicyError::Invalid {
+            path: path.to_owned(),
+            source,
+        };
+        let Rules { rule: rules } = toml::from_str(text).map_err(invalid)?;
+        if rules.is_empty() {
+            return Err(invalid(de::Error::custom(
+                "no [[rule]] tables; the policy would refuse every call",
+            )));
+        }
+        Ok(Policy { rules })
+    }
+
+    /// Number of rules.
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Always false; a policy has at least one rule.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether `admin`, authenticated `via` some way, may call `method` on
+    /// `uri`, a path with optional query or an absolute URI.
+    ///
+    /// Paths with dot segments or percent-encoded dots and slashes are never
+    /// allowed, since the upstream may resolve them to another path.
+    pub fn allows(&self, admin: &str, via: Via, method: &str, uri: &str) -> bool {
+        let Some(path) = path_of(uri) else {
+            return false;
+        };
+        self.rules
+            .iter()
+            .filter(|rule| rule.applies_to(admin, via))
+            .flat_map(|rule| &rule.allow)
+            .any(|permission| permission.allows(method, path))
+    }
+}
+
+impl TryFrom<RuleTable> for Rule {
+    type Error = String;
+
+    fn try_from(table: RuleTable) -> Result<Self, String> {
+        if table.admins.iter().flatten().any(|id| id.is_empty()) {
+            return Err("empty admin id".into());
+        }
+        if table.allow.is_empty() {
+            return Err("rule has no allow list".into());
+        }
+        Ok(Rule {
+            admins: table.admins,
+            via: table.via,
+            allow: table.allow,
+        })
+    }
+}
+
+impl Rule {
+    fn applies_to(&self, admin: &str, via: Via) -> bool {
+        self.admins
+            .as_ref()
+            .is_none_or(|admins| admins.iter().any(|id| id == admin))
+            && self.via.as_ref().is_none_or(|ways| ways.contains(&via))
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let Some((method, path)) = s.split_once(' ') else {
+            return Err(format!("{s:?} is not \"METHOD /path\""));
+        };
+        let method = match method {
+            "*" => None,
+            _ if method.bytes().all(|b| b.is_ascii_alphabetic()) => {
+                Some(method.to_ascii_uppercase())
+            }
+            _ => return Err(format!("invalid method {method:?}")),
+        };
+        let path = path.trim();
+        if !path.starts_with('/')
+            || !path.bytes().all(|b| b.is_ascii_graphic())
+            || path.contains(['?', '#'])
+        {
+            return Err(format!("invalid path {path:?}"));
+        }
+        if path
+            .split('/')
+            .any(|segment| segment.contains("**") && segment != "**")
+        {
+            return Err(format!("`**` must be a whole segment in {path:?}"));
+        }
+        Ok(Permission {
+            method,
+            path: path.to_owned(),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Permission {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Permission {
+    fn allows(&self, method: &str, path: &str) -> bool {
+        let pattern: Vec<&str> = self.path.split('/').collect();
+        let path: Vec<&str> = path.split('/').collect();
+        self.method
+            .as_ref()
+            .is_none_or(|allowed| allowed.eq_ignore_ascii_case(method))
+            && glob_matches(&pattern, &path)
+    }
+}
+
+/// Deserializes a string or an array of them, as a rule's keys may be.
+fn one_or_many<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    struct OneOrMany<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for OneOrMany<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a string or an array of strings")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Vec<T>, E> {
+            T::deserialize(value.into_deserializer()).map(|value| vec![value])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<T>, A::Error> {
+            let mut values = Vec::new();
+            while let Some(value) = seq.next_element()? {
+                values.push(value);
+            }
+            Ok(values)
+        }
+    }
+
+    deserializer.deserialize_any(OneOrMany(PhantomData))
+}
+
+fn some_one_or_many<'de, T, D>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    one_or_many(deserializer).map(Some)
+}
+
+/// Matches path segments against glob segments.
+fn glob_matches(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| glob_matches(rest, &path[skip..])),
+        Some((glob, rest)) => path.split_first().is_some_and(|(segment, path)| {
+            segment_matches(glob, segment) && glob_matches(rest, path)
+        }),
+    }
+}
+
+/// Matches one segment against a glob in which `*` stands for any run of
+/// characters.
+fn segment_matches(glob: &str, segment: &str) -> bool {
+    let mut parts = glob.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = segment.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// A policy file that can be reloaded while the server runs.
+#[derive(Debug)]
+pub struct PolicyFile {
+    path: PathBuf,
+    policy: RwLock<Arc<Policy>>,
+}
+
+impl PolicyFile {
+    pub fn open(path: &Path) -> Result<Self, PolicyError> {
+        Ok(PolicyFile {
+            path: path.to_owned(),
+            policy: RwLock::new(Arc::new(Policy::load(path)?)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The policy in force.
+    pub fn policy(&self) -> Arc<Policy> {
+        self.policy.read().unwrap().clone()
+    }
+
+    /// Re-reads the file, keeping the policy in force if the new one is
+    /// invalid.
+    pub fn reload(&self) -> Result<Arc<Policy>, PolicyError> {
+        let policy = Arc::new(Policy::load(&self.path)?);
+        *self.policy.write().unwrap() = policy.clone();
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = r#"
+# The host's admins may do anything.
+[[rule]]
+admins = ["admin@example.com", "alice"]
+allow = ["* /**"]
+
+[[rule]] # bots
+via = 'delegation'
+allow = [
+    "GET /api/v1/status",   # read-only
+    "get /api/v1/zomes/*/state",
+]
+
+[[rule]]
+admins = "bob"
+via = ["session"]
+allow = ["PUT /api/v1/config/**"]
+"#;
+
+    fn policy() -> Policy {
+        Policy::parse(Path::new("policy.toml"), POLICY).unwrap()
+    }
+
+    #[test]
+    fn parses_rules() {
+        let policy = policy();
+        assert_eq!(policy.len(), 3);
+        assert_eq!(policy.rules[1].via, Some(vec![Via::Delegation]));
+        assert_eq!(
+            policy.rules[1].allow[1],
+            Permission {
+                method: Some("GET".into()),
+                path: "/api/v1/zomes/*/state".into(),
+            }
+        );
+        assert_eq!(policy.rules[2].admins, Some(vec!["bob".to_owned()]));
+    }
+
+    #[test]
+    fn allows_what_some_applicable_rule_allows() {
+        let policy = policy();
+        let allows = |admin, via, method, uri| policy.allows(admin, via, method, uri);
+
+        assert!(allows("alice", Via::Signature, "DELETE", "/anything"));
+        assert!(allows("alice", Via::Delegation, "POST", "/api/v1/config"));
+
+        assert!(allows("bob", Via::Delegation, "GET", "/api/v1/status?x=1"));
+        assert!(allows(
+            "bob",
+            Via::Delegation,
+            "GET",
+            "https://hpos.example/api/v1/zomes/hha/state"
+        ));
+        assert!(!allows("bob", Via::Delegation, "POST", "/api/v1/status"));
+        assert!(!allows("bob", Via::Delegation, "GET", "/api/v1/status/x"));
+        assert!(!allows(
+            "bob",
+            Via::Delegation,
+            "GET",
+            "/api/v1/zomes/hha/x/state"
+        ));
+        assert!(!allows("bob", Via::Signature, "GET", "/api/v1/status"));
+
+        assert!(allows("bob", Via::Session, "PUT", "/api/v1/config"));
+        assert!(allows("bob", Via::Session, "PUT", "/api/v1/config/a/b"));
+        assert!(!allows("bob", Via::Session, "PUT", "/api/v1/configx"));
+        assert!(!allows("carol", Via::Session, "PUT", "/api/v1/config"));
+
+        // Paths that could resolve elsewhere are never allowed.
+        assert!(!allows("alice", Via::Signature, "GET", "/api/v1/../x"));
+        assert!(!allows(
+            "bob",
+            Via::Session,
+            "PUT",
+            "/api/v1/config/%2e%2e/x"
+        ));
+    }
+
+    #[test]
+    fn globs() {
+        let matches = |glob: &str, path: &str| {
+            Permission::from_str(&format!("* {glob}"))
+                .unwrap()
+                .allows("GET", path)
+        };
+        assert!(matches("/a/**", "/a"));
+        assert!(matches("/a/**", "/a/b/c"));
+        assert!(matches("/a/**/z", "/a/z"));
+        assert!(matches("/a/**/z", "/a/b/c/z"));
+        assert!(!matches("/a/**/z", "/a/b/c"));
+        assert!(matches("/a/*.json", "/a/config.json"));
+        assert!(matches("/a/x*y*z", "/a/xyz"));
+        assert!(matches("/a/x*y*z", "/a/x1y2z"));
+        assert!(!matches("/a/x*y*z", "/a/x1z2y"));
+        assert!(!matches("/a/*", "/a/b/c"));
+        assert!(!matches("/a", "/a/"));
+    }
+
+    #[test]
+    fn reports_the_offending_line() {
+        let line = |text: &str| match Policy::parse(Path::new("p.toml"), text) {
+            Err(PolicyError::Invalid { source, .. }) => source
+                .span()
+                .map(|span| text[..span.start].matches('\n').count() + 1),
+            other => panic!("{other:?}"),
+        };
+        assert_eq!(line(""), None);
+        assert_eq!(line("allow = [\"* /\"]"), Some(1));
+        assert_eq!(line("[[rule]]\nallow = [\"* /\"]\n[rules]"), Some(3));
+        assert_eq!(
+            line("[[rule]]\n\nallow = [\n\"* /\",\n\"GET relative\"]"),
+            Some(3)
+        );
+        assert_eq!(
+            line("[[rule]]\nallow = [\"* /\"]\nvia = [\"carrier pigeon\"]"),
+            Some(3)
+        );
+        assert_eq!(line("[[rule]]\nadmins = [\"a\"]\n"), Some(1));
+        assert_eq!(line("[[rule]]\nallow = []\nadmins = [\"\"]"), Some(1));
+        assert_eq!(
+            line("[[rule]]\nallow = [\"* /\"]\nallow = [\"* /\"]"),
+            Some(3)
+        );
+        assert_eq!(line("[[rule]]\nallow = [\"* /a**\"]"), Some(2));
+        assert_eq!(line("[[rule]]\nallow = \"* /\" extra"), Some(2));
+        assert_eq!(line("[[rule]]\nallow = \"* /"), Some(2));
+        assert_eq!(line("[[rule]]\nmethods = \"GET\""), Some(2));
+        assert_eq!(line("[[rule]]\nallow = 1"), Some(2));
+    }
+
+    #[test]
+    fn errors_name_the_file_and_position() {
+        let error = Policy::parse(
+            Path::new("p.toml"),
+            "[[rule]]\nallow = [\"* /\"]\nvia = \"carrier pigeon\"",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.starts_with("p.toml: "), "{error}");
+        assert!(error.contains("line 3, column 7"), "{error}");
+        assert!(error.contains("unknown via \"carrier pigeon\""), "{error}");
+    }
+
+    #[test]
+    fn reload_keeps_the_old_policy_on_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.toml");
+        std::fs::write(&path, POLICY).unwrap();
+        let file = PolicyFile::open(&path).unwrap();
+        assert_eq!(file.policy().len(), 3);
+
+        std::fs::write(&path, "[[rule]]\nallow = [\"GET /\"]\n").unwrap();
+        assert_eq!(file.reload().unwrap().len(), 1);
+        assert_eq!(file.policy().len(), 1);
+
+        std::fs::write(&path, "[[rule]]\n").unwrap();
+        assert!(file.reload().is_err());
+        assert_eq!(file.policy().len(), 1);
+    }
+}
@@ -0,0 +1,296 @@
+//! Throttling of failed verifications.
+//!
+//! The admin key is derived from a password, so every failed signature is
+//! potentially someone guessing. Failures are paid for from token buckets:
+//! one per client address and one shared by every client. A client that
+//! empties its bucket is locked out, for twice as long each time it happens
+//! again, and an empty global bucket turns everyone away until it refills.
+//! Calls turned away are answered 429 with `Retry-After`.
+//!
+//! The client address is the one nginx passes in [`CLIENT_ADDR`]; without
+//! it only the global bucket applies. Only calls that fail to verify (401)
+//! are charged. A call that verifies refills its client's bucket but keeps
+//! the count of lockouts, which only time wipes out.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+use axum::http::HeaderMap;
+
+use crate::verify::CLIENT_ADDR;
+
+/// How many clients are tracked at most; idle ones are forgotten first.
+const MAX_CLIENTS: usize = 10_000;
+
+/// The thresholds, all in whole failures and seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimits {
+    /// Failures a client may make in a row before it is locked out.
+    pub client_burst: u32,
+    /// Seconds it takes a client to earn back one failure.
+    pub client_interval: u64,
+    /// Failures every client together may make in a row.
+    pub global_burst: u32,
+    /// Seconds it takes to earn back one global failure.
+    pub global_interval: u64,
+    /// Length of a client's first lockout; each further one doubles it.
+    pub lockout: u64,
+    /// The longest a client is ever locked out for. A client that stays out
+    /// of trouble for this long after a lockout starts over from
+    /// [`lockout`](Self::lockout).
+    pub max_lockout: u64,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            client_burst: 10,
+            client_interval: 30,
+            global_burst: 100,
+            global_interval: 1,
+            lockout: 60,
+            max_lockout: 3600,
+        }
+    }
+}
+
+/// A bucket of `burst` tokens that regains one every `interval` seconds.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: u32,
+    /// When the bucket last gained a token, or was last full.
+    updated: u64,
+}
+
+impl Bucket {
+    fn full(burst: u32, now: u64) -> Self {
+        Bucket {
+            tokens: burst,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, burst: u32, interval: u64, now: u64) {
+        let earned = now.saturating_sub(self.updated) / interval.max(1);
+        if self.tokens as u64 + earned >= burst as u64 {
+            *self = Bucket::full(burst, now);
+        } else if earned > 0 {
+            self.tokens += earned as u32;
+            self.updated += earned * interval.max(1);
+        }
+    }
+
+    /// Seconds until the next token, if the bucket is empty.
+    fn wait(&self, interval: u64, now: u64) -> Option<u64> {
+        (self.tokens == 0).then(|| (self.updated + interval.max(1)).saturating_sub(now).max(1))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Client {
+    bucket: Bucket,
+    /// How many times in a row the client has been locked out.
+    strikes: u32,
+    locked_until: u64,
+}
+
+#[derive(Debug)]
+struct State {
+    global: Bucket,
+    clients: HashMap<IpAddr, Client>,
+}
+
+/// Keeps the buckets and lockouts.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    state: Mutex<State>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter {
+            limits,
+            state: Mutex::new(State {
+                global: Bucket::full(limits.global_burst, 0),
+                clients: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn limits(&self) -> &RateLimits {
+        &self.limits
+    }
+
+    /// Answers whether `client` may try at `now`, or else how many seconds
+    /// it should wait.
+    pub fn check(&self, client: Option<IpAddr>, now: u64) -> Result<(), u64> {
+        let limits = &self.limits;
+        let mut state = self.state.lock().unwrap();
+        if let Some(client) = client.and_then(|client| state.clients.get(&client)) {
+            if client.locked_until > now {
+                return Err(client.locked_until - now);
+            }
+        }
+        state
+            .global
+            .refill(limits.global_burst, limits.global_interval, now);
+        match state.global.wait(limits.global_interval, now) {
+            Some(wait) => Err(wait),
+            None => Ok(()),
+        }
+    }
+
+    /// Charges a failed verification by `client` at `now`, locking the
+    /// client out if that empties its bucket.
+    pub fn failed(&self, client: Option<IpAddr>, now: u64) {
+        let limits = &self.limits;
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state
+            .global
+            .refill(limits.global_burst, limits.global_interval, now);
+        state.global.tokens = state.global.tokens.saturating_sub(1);
+
+        let Some(addr) = client else {
+            return;
+        };
+        if state.clients.len() >= MAX_CLIENTS && !state.clients.contains_key(&addr) {
+            state.clients.retain(|_, client| !client.idle(limits, now));
+        }
+        let client = state.clients.entry(addr).or_insert(Client {
+            bucket: Bucket::full(limits.client_burst, now),
+            strikes: 0,
+            locked_until: 0,
+        });
+        if client.idle(limits, now) {
+            client.strikes = 0;
+        }
+        client
+            .bucket
+            .refill(limits.client_burst, limits.client_interval, now);
+        client.bucket.tokens = client.bucket.tokens.saturating_sub(1);
+        if client.bucket.tokens == 0 {
+            let lockout = limits
+                .lockout
+                .saturating_mul(1 << client.strikes.min(32))
+                .min(limits.max_lockout);
+            client.strikes += 1;
+            client.locked_until = now + lockout;
+            client.bucket = Bucket::full(limits.client_burst, client.locked_until);
+        }
+    }
+
+    /// Refills the bucket of `client`, which has just verified at `now`.
+    /// Its lockouts still count until it has been idle long enough, since
+    /// a guesser may share its address.
+    pub fn succeeded(&self, client: Option<IpAddr>, now: u64) {
+        let Some(addr) = client else {
+            return;
+        };
+        if let Some(client) = self.state.lock().unwrap().clients.get_mut(&addr) {
+            client.bucket = Bucket::full(self.limits.client_burst, now);
+        }
+    }
+}
+
+impl Client {
+    /// Whether the client has stayed out of trouble long enough to be
+    /// forgotten.
+    fn idle(&self, limits: &RateLimits, now: u64) -> bool {
+        let mut bucket = self.bucket;
+        bucket.refill(limits.client_burst, limits.client_interval, now);
+        bucket.tokens == limits.client_burst
+            && now >= self.locked_until.saturating_add(limits.max_lockout)
+    }
+}
+
+/// The client address nginx passed along, if it did and it parses.
+pub fn client_addr(headers: &HeaderMap) -> Option<IpAddr> {
+    headers.get(CLIENT_ADDR)?.to_str().ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> RateLimits {
+        RateLimits {
+            client_burst: 3,
+            client_interval: 10,
+            global_burst: 100,
+            global_interval: 1,
+            lockout: 60,
+            max_lockout: 200,
+        }
+    }
+
+    fn addr(last: u8) -> Option<IpAddr> {
+        Some(IpAddr::from([192, 0, 2, last]))
+    }
+
+    #[test]
+    fn locks_out_a_client_for_longer_each_time() {
+        let limiter = RateLimiter::new(limits());
+        let mut now = 1000;
+        for lockout in [60, 120, 200, 200] {
+            for _ in 0..3 {
+                assert_eq!(limiter.check(addr(1), now), Ok(()));
+                limiter.failed(addr(1), now);
+            }
+            assert_eq!(limiter.check(addr(1), now), Err(lockout));
+            assert_eq!(limiter.check(addr(2), now), Ok(()));
+            now += lockout;
+        }
+
+        // A success refills the bucket, but the next lockout is as long as
+        // if it had not happened.
+        limiter.failed(addr(1), now);
+        limiter.succeeded(addr(1), now);
+        limiter.failed(addr(1), now);
+        limiter.failed(addr(1), now);
+        assert_eq!(limiter.check(addr(1), now), Ok(()));
+        limiter.failed(addr(1), now);
+        assert_eq!(limiter.check(addr(1), now), Err(200));
+    }
+
+    #[test]
+    fn clients_earn_failures_back() {
+        let limiter = RateLimiter::new(limits());
+        limiter.failed(addr(1), 1000);
+        limiter.failed(addr(1), 1000);
+        // Ten seconds later one failure has been earned back, so two more
+        // are needed for a lockout.
+        limiter.failed(addr(1), 1010);
+        assert_eq!(limiter.check(addr(1), 1010), Ok(()));
+        limiter.failed(addr(1), 1010);
+        assert_eq!(limiter.check(addr(1), 1010), Err(60));
+    }
+
+    #[test]
+    fn global_bucket_turns_everyone_away() {
+        let limiter = RateLimiter::new(RateLimits {
+            global_burst: 5,
+            global_interval: 2,
+            ..limits()
+        });
+        for last in 0..5 {
+            limiter.failed(addr(last), 1000);
+        }
+        limiter.failed(None, 1000);
+        assert_eq!(limiter.check(addr(9), 1000), Err(2));
+        assert_eq!(limiter.check(None, 1001), Err(1));
+        assert_eq!(limiter.check(addr(9), 1002), Ok(()));
+    }
+
+    #[test]
+    fn reads_the_client_address() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(client_addr(&headers), None);
+        headers.insert(CLIENT_ADDR, "2001:db8::1".parse().unwrap());
+        assert_eq!(client_addr(&headers), "2001:db8::1".parse().ok());
+        headers.insert(CLIENT_ADDR, "not an address".parse().unwrap());
+        assert_eq!(client_addr(&headers), None);
+    }
+}
@@ -0,0 +1,178 @@
+//! Picking up a changed HPOS config without a restart.
+//!
+//! HPOS rewrites its config when it is re-provisioned or the admin changes
+//! their password, and with it the admin public key. [`AdminSource`] loads
+//! the admins the way the server does at start-up, so that
+//! [`Verifier::reload_admins`](crate::Verifier::reload_admins) can swap them
+//! in whole or not at all, and [`watch`] notices when the files change.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+use thiserror::Error;
+
+use crate::admins::{AdminKeys, AdminsError};
+use crate::config::{ConfigError, HposConfig};
+use crate::rotation::{Rotation, RotationRecord};
+
+#[derive(Debug, Error)]
+pub enum ReloadError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Admins(#[from] AdminsError),
+}
+
+/// Where the admins come from: the HPOS config, an optional admin keys
+/// file, and the rotations recorded since.
+#[derive(Debug, Clone)]
+pub struct AdminSource {
+    hpos_config: PathBuf,
+    admin_keys: Option<PathBuf>,
+    rotations: Arc<RotationRecord>,
+}
+
+/// The admins as loaded, and what loading them had to leave out.
+#[derive(Debug)]
+pub struct LoadedAdmins {
+    /// Schema version of the HPOS config.
+    pub version: &'static str,
+    pub admins: AdminKeys,
+    /// Recorded rotations that no longer apply, with why.
+    pub skipped: Vec<(Rotation, AdminsError)>,
+}
+
+impl AdminSource {
+    pub fn new(
+        hpos_config: &Path,
+        admin_keys: Option<&Path>,
+        rotations: Arc<RotationRecord>,
+    ) -> Self {
+        AdminSource {
+            hpos_config: hpos_config.to_owned(),
+            admin_keys: admin_keys.map(Path::to_owned),
+            rotations,
+        }
+    }
+
+    pub fn hpos_config(&self) -> &Path {
+        &self.hpos_config
+    }
+
+    pub fn admin_keys(&self) -> Option<&Path> {
+        self.admin_keys.as_deref()
+    }
+
+    pub fn rotations(&self) -> &Arc<RotationRecord> {
+        &self.rotations
+    }
+
+    /// Reads the files afresh and applies the recorded rotations.
+    pub fn load(&self) -> Result<LoadedAdmins, ReloadError> {
+        let config = HposConfig::load(&self.hpos_config)?;
+        let mut admins = config
+            .admins()
+            .map_err(|source| ConfigError::InvalidAdmins {
+                path: self.hpos_config.clone(),
+                source,
+            })?;
+        if let Some(path) = &self.admin_keys {
+            admins = admins.with_file(path)?;
+        }
+        let skipped = self.rotations.apply(&mut admins);
+        Ok(LoadedAdmins {
+            version: config.version(),
+            admins,
+            skipped,
+        })
+    }
+
+    /// The files that [`load`](Self::load) reads, bar the rotation record,
+    /// which only the server itself writes.
+    pub fn files(&self) -> Vec<&Path> {
+        std::iter::once(self.hpos_config.as_path())
+            .chain(self.admin_keys.as_deref())
+            .collect()
+    }
+}
+
+/// Calls `changed` with the path of any of `files` that has been written or
+/// replaced, from a thread of its own, until the process exits.
+///
+/// The directories holding the files are watched rather than the files
+/// themselves, so that a file replaced by renaming another over it, as
+/// careful writers do, is still noticed.
+pub fn watch(
+    files: &[&Path],
+    mut changed: impl FnMut(&Path) + Send + 'static,
+) -> std::io::Result<()> {
+    let mut inotify = Inotify::init()?;
+    let mut watched: Vec<(WatchDescriptor, PathBuf)> = Vec::new();
+    for &file in files {
+        let dir = match file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let wd = inotify
+            .watches()
+            .add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+        watched.push((wd, file.to_owned()));
+    }
+    std::thread::Builder::new()
+        .name("config-watch".into())
+        .spawn(move || {
+            let mut buffer = [0; 4096];
+            loop {
+                let events = match inotify.read_events_blocking(&mut buffer) {
+                    Ok(events) => events,
+                    Err(e) => {
+                        tracing::error!("no longer watching for config changes: {e}");
+                        return;
+                    }
+                };
+                // Once per file however many events a read brings.
+                let mut hits = HashSet::new();
+                for event in events {
+                    for (wd, file) in &watched {
+                        if event.wd == *wd && event.name == file.file_name() {
+                            hits.insert(file.as_path());
+                        }
+                    }
+                }
+                for file in hits {
+                    changed(file);
+                }
+            }
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn notices_writes_and_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("hpos-config.json");
+        std::fs::write(&config, "{}").unwrap();
+        let (tx, rx) = mpsc::channel();
+        watch(&[&config], move |path| drop(tx.send(path.to_owned()))).unwrap();
+        let next = || rx.recv_timeout(Duration::from_secs(5));
+
+        std::fs::write(dir.path().join("unrelated.json"), "{}").unwrap();
+        std::fs::write(&config, "{\"v1\": {}}").unwrap();
+        assert_eq!(next().unwrap(), config);
+
+        let tmp = dir.path().join("hpos-config.json.tmp");
+        std::fs::write(&tmp, "{}").unwrap();
+        std::fs::rename(&tmp, &config).unwrap();
+        assert_eq!(next().unwrap(), config);
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
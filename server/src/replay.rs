@@ -0,0 +1,94 @@
+//! Bounded memory of recently accepted signatures.
+//!
+//! Ed25519 signatures are deterministic and strict verification rejects
+//! malleated ones, so a signature identifies its payload: seeing the same
+//! signature twice inside the acceptance window means a replay.
+
+use std::collections::{HashSet, VecDeque};
+
+#[derive(Debug)]
+pub struct ReplayCache {
+    capacity: usize,
+    seen: HashSet<[u8; 64]>,
+    /// Insertion order, with each signature's signed timestamp.
+    order: VecDeque<(u64, [u8; 64])>,
+}
+
+impl ReplayCache {
+    pub fn new(capacity: usize) -> Self {
+        ReplayCache {
+            capacity,
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Records `signature`, returning `false` if it was already present.
+    ///
+    /// Entries signed before `oldest_valid` can no longer pass the timestamp
+    /// check, so they are dropped first. If the cache is still full, the
+    /// oldest entry is evicted; only signatures that already verified are
+    /// inserted, so only the admin could fill the cache that way.
+    pub fn insert(&mut self, signature: [u8; 64], timestamp: u64, oldest_valid: u64) -> bool {
+        if self.seen.contains(&signature) {
+            return false;
+        }
+        while let Some(&(ts, old)) = self.order.front() {
+            if ts >= oldest_valid && self.order.len() < self.capacity {
+                break;
+            }
+            if ts >= oldest_valid {
+                tracing::warn!("replay cache full, evicting a signature still inside the window");
+            }
+            self.order.pop_front();
+            self.seen.remove(&old);
+        }
+        if self.capacity > 0 {
+            self.seen.insert(signature);
+            self.order.push_back((timestamp, signature));
+        }
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_repeat() {
+        let mut cache = ReplayCache::new(8);
+        assert!(cache.insert([1; 64], 100, 40));
+        assert!(!cache.insert([1; 64], 100, 40));
+        assert!(cache.insert([2; 64], 100, 40));
+    }
+
+    #[test]
+    fn prunes_entries_outside_the_window() {
+        let mut cache = ReplayCache::new(8);
+        cache.insert([1; 64], 100, 40);
+        cache.insert([2; 64], 150, 90);
+        assert_eq!(cache.len(), 2);
+        cache.insert([3; 64], 200, 140);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.insert([1; 64], 100, 140));
+    }
+
+    #[test]
+    fn stays_bounded() {
+        let mut cache = ReplayCache::new(3);
+        for i in 0..10 {
+            assert!(cache.insert([i; 64], 100, 0));
+        }
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.insert([9; 64], 100, 0));
+    }
+}
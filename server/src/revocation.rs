@@ -0,0 +1,258 @@
+//! Session tokens that were given up before they expired.
+//!
+//! Logging out revokes one token by id; "revoke all sessions" revokes every
+//! token issued up to that moment, without having to know them, by starting
+//! a new epoch: tokens carry the epoch they were issued in, and those from
+//! earlier epochs are refused. Epochs count revocations rather than seconds,
+//! so a login in the same second as the revocation survives it. A revoked
+//! token id is forgotten once the token has expired anyway, so the list only
+//! ever holds live tokens. [`MemoryRevocationStore`] forgets revocations on
+//! restart, so it is only for a session key that is forgotten too: the
+//! server refuses to keep the key in a file without a
+//! [`FileRevocationStore`], which keeps revocations in a JSON file.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::session::Session;
+
+#[derive(Debug, Error)]
+pub enum RevocationError {
+    #[error("revocation list {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("revocation list {path} is corrupt: {source}")]
+    Corrupt {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+/// Somewhere to keep revoked session tokens.
+pub trait RevocationStore: Send + Sync {
+    /// Revokes the token `id`, which expires at `expires` anyway.
+    fn revoke(&self, id: &str, expires: u64, now: u64) -> Result<(), RevocationError>;
+
+    /// Revokes every token issued so far, by starting a new epoch.
+    fn revoke_all(&self, now: u64) -> Result<(), RevocationError>;
+
+    /// The current epoch, which new tokens carry.
+    fn epoch(&self) -> u64;
+
+    fn is_revoked(&self, session: &Session) -> bool;
+}
+
+/// The revocations themselves; the bookkeeping shared by every store.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Revoked {
+    /// Tokens issued in an earlier epoch are revoked.
+    #[serde(default)]
+    epoch: u64,
+    /// Revoked token ids and their expiry times.
+    #[serde(default)]
+    tokens: HashMap<String, u64>,
+}
+
+impl Revoked {
+    fn revoke(&mut self, id: &str, expires: u64, now: u64) {
+        self.prune(now);
+        if expires >= now {
+            self.tokens.insert(id.to_owned(), expires);
+        }
+    }
+
+    fn revoke_all(&mut self, now: u64) {
+        self.prune(now);
+        self.epoch += 1;
+    }
+
+    fn prune(&mut self, now: u64) {
+        self.tokens.retain(|_, &mut expires| expires >= now);
+    }
+
+    fn is_revoked(&self, session: &Session) -> bool {
+        session.epoch < self.epoch || self.tokens.contains_key(&session.id)
+    }
+}
+
+/// Keeps revocations in memory; a restart forgets them.
+#[derive(Debug, Default)]
+pub struct MemoryRevocationStore(Mutex<Revoked>);
+
+impl RevocationStore for MemoryRevocationStore {
+    fn revoke(&self, id: &str, expires: u64, now: u64) -> Result<(), RevocationError> {
+        self.0.lock().unwrap().revoke(id, expires, now);
+        Ok(())
+    }
+
+    fn revoke_all(&self, now: u64) -> Result<(), RevocationError> {
+        self.0.lock().unwrap().revoke_all(now);
+        Ok(())
+    }
+
+    fn epoch(&self) -> u64 {
+        self.0.lock().unwrap().epoch
+    }
+
+    fn is_revoked(&self, session: &Session) -> bool {
+        self.0.lock().unwrap().is_revoked(session)
+    }
+}
+
+/// Keeps revocations in a JSON file, rewritten atomically on every change,
+/// so that revoked tokens stay revoked across a restart.
+#[derive(Debug)]
+pub struct FileRevocationStore {
+    path: PathBuf,
+    revoked: Mutex<Revoked>,
+}
+
+impl FileRevocationStore {
+    /// Opens the list at `path`, which need not exist yet.
+    pub fn open(path: &Path) -> Result<Self, RevocationError> {
+        let revoked = match std::fs::read(path) {
+            Ok(bytes) => {
+                serde_json::from_slice(&bytes).map_err(|source| RevocationError::Corrupt {
+                    path: path.to_owned(),
+                    source,
+                })?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Revoked::default(),
+            Err(source) => {
+                return Err(RevocationError::Io {
+                    path: path.to_owned(),
+                    source,
+                })
+            }
+        };
+        Ok(FileRevocationStore {
+            path: path.to_owned(),
+            revoked: Mutex::new(revoked),
+        })
+    }
+
+    fn save(&self, revoked: &Revoked) -> Result<(), RevocationError> {
+        let io = |source| RevocationError::Io {
+            path: self.path.clone(),
+            source,
+        };
+        let tmp = self.path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp).map_err(io)?;
+        serde_json::to_writer(&mut file, revoked)
+            .map_err(std::io::Error::from)
+            .map_err(io)?;
+        file.flush().map_err(io)?;
+        file.sync_all().map_err(io)?;
+        std::fs::rename(&tmp, &self.path).map_err(io)
+    }
+}
+
+impl RevocationStore for FileRevocationStore {
+    fn revoke(&self, id: &str, expires: u64, now: u64) -> Result<(), RevocationError> {
+        let mut revoked = self.revoked.lock().unwrap();
+        revoked.revoke(id, expires, now);
+        self.save(&revoked)
+    }
+
+    fn revoke_all(&self, now: u64) -> Result<(), RevocationError> {
+        let mut revoked = self.revoked.lock().unwrap();
+        revoked.revoke_all(now);
+        self.save(&revoked)
+    }
+
+    fn epoch(&self) -> u64 {
+        self.revoked.lock().unwrap().epoch
+    }
+
+    fn is_revoked(&self, session: &Session) -> bool {
+        self.revoked.lock().unwrap().is_revoked(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str, issued: u64, epoch: u64) -> Session {
+        Session {
+            id: id.to_owned(),
+            admin: "admin".to_owned(),
+            host: "hpos.example".to_owned(),
+            issued,
+            expires: issued + 900,
+            epoch,
+        }
+    }
+
+    fn revokes(store: &dyn RevocationStore) {
+        let (a, b) = (session("a", 100, 0), session("b", 100, 0));
+        store.revoke("a", a.expires, 200).unwrap();
+        assert!(store.is_revoked(&a));
+        assert!(!store.is_revoked(&b));
+
+        store.revoke_all(300).unwrap();
+        assert_eq!(store.epoch(), 1);
+        assert!(store.is_revoked(&b));
+        assert!(!store.is_revoked(&session("c", 300, 1)));
+
+        // A second revocation within the same second still starts a new epoch.
+        store.revoke_all(300).unwrap();
+        assert_eq!(store.epoch(), 2);
+        assert!(store.is_revoked(&session("c", 300, 1)));
+        assert!(!store.is_revoked(&session("d", 300, 2)));
+    }
+
+    #[test]
+    fn memory_store() {
+        revokes(&MemoryRevocationStore::default());
+    }
+
+    #[test]
+    fn prunes_expired_tokens() {
+        let mut revoked = Revoked::default();
+        revoked.revoke("a", 1000, 100);
+        revoked.revoke("b", 2000, 100);
+        revoked.revoke("c", 3000, 1500);
+        assert_eq!(revoked.tokens.len(), 2);
+        assert!(!revoked.tokens.contains_key("a"));
+
+        // Tokens that have already expired are not worth recording.
+        revoked.revoke("d", 1000, 2500);
+        assert_eq!(revoked.tokens.len(), 1);
+    }
+
+    #[test]
+    fn file_store_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("revoked.json");
+
+        let store = FileRevocationStore::open(&path).unwrap();
+        revokes(&store);
+        store.revoke("e", 2000, 400).unwrap();
+        drop(store);
+
+        let reopened = FileRevocationStore::open(&path).unwrap();
+        assert_eq!(reopened.epoch(), 2);
+        assert!(reopened.is_revoked(&session("b", 100, 0)));
+        assert!(reopened.is_revoked(&session("e", 1000, 2)));
+        assert!(!reopened.is_revoked(&session("f", 1000, 2)));
+    }
+
+    #[test]
+    fn file_store_rejects_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("revoked.json");
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            FileRevocationStore::open(&path),
+            Err(RevocationError::Corrupt { .. })
+        ));
+    }
+}
@@ -0,0 +1,227 @@
+//! The admin key rotations a host has accepted, kept next to the HPOS
+//! config.
+//!
+//! The HPOS config still names an admin's old key after their password
+//! changes, so every rotation accepted through `POST /rotate` is appended to
+//! a [`RotationRecord`] and applied again on top of the config at start-up.
+//! The old key of each stays valid until the rotation's `retires` time, so
+//! that signatures, delegations and browser tabs made with it keep working
+//! while the admin moves over.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use hp_admin_crypto::Endorsement;
+
+use crate::admins::{AdminKeys, AdminsError};
+
+/// How long an old admin key stays valid after rotation, by default.
+pub const DEFAULT_ROTATION_GRACE: Duration = Duration::from_secs(24 * 3600);
+
+#[derive(Debug, Error)]
+pub enum RotationError {
+    #[error("rotation record {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("rotation record {path} is corrupt: {source}")]
+    Corrupt {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+/// One accepted rotation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rotation {
+    /// Id of the admin whose key changed.
+    pub admin: String,
+    /// The old key's endorsement of the new one.
+    #[serde(with = "text")]
+    pub endorsement: Endorsement,
+    /// When the rotation was accepted, in seconds since the Unix epoch.
+    pub rotated: u64,
+    /// When the old key stops being accepted.
+    pub retires: u64,
+}
+
+/// The file name of the rotation record that goes with the HPOS config at
+/// `hpos_config`.
+pub fn default_path(hpos_config: &Path) -> PathBuf {
+    hpos_config.with_file_name("hp-admin-rotations.json")
+}
+
+/// Every rotation accepted so far, in a JSON file rewritten atomically on
+/// every change.
+#[derive(Debug)]
+pub struct RotationRecord {
+    path: PathBuf,
+    rotations: Mutex<Vec<Rotation>>,
+}
+
+impl RotationRecord {
+    /// Opens the record at `path`, which need not exist yet.
+    pub fn open(path: &Path) -> Result<Self, RotationError> {
+        let rotations = match std::fs::read(path) {
+            Ok(bytes) => {
+                serde_json::from_slice(&bytes).map_err(|source| RotationError::Corrupt {
+                    path: path.to_owned(),
+                    source,
+                })?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(source) => {
+                return Err(RotationError::Io {
+                    path: path.to_owned(),
+                    source,
+                })
+            }
+        };
+        Ok(RotationRecord {
+            path: path.to_owned(),
+            rotations: Mutex::new(rotations),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The recorded rotations, oldest first.
+    pub fn rotations(&self) -> Vec<Rotation> {
+        self.rotations.lock().unwrap().clone()
+    }
+
+    /// Applies the recorded rotations to `admins`, as loaded from the HPOS
+    /// config, in order. Rotations whose old key is no admin's current key,
+    /// say because the config has since been updated, are skipped and
+    /// returned along with the reason.
+    pub fn apply(&self, admins: &mut AdminKeys) -> Vec<(Rotation, AdminsError)> {
+        let mut skipped = Vec::new();
+        for rotation in self.rotations() {
+            if let Err(e) = admins.rotate(&rotation.endorsement, rotation.retires) {
+                skipped.push((rotation, e));
+            }
+        }
+        skipped
+    }
+
+    /// Records a rotation, and only returns once it is on disk.
+    pub fn append(&self, rotation: Rotation) -> Result<(), RotationError> {
+        let mut rotations = self.rotations.lock().unwrap();
+        rotations.push(rotation);
+        if let Err(e) = self.save(&rotations) {
+            rotations.pop();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn save(&self, rotations: &[Rotation]) -> Result<(), RotationError> {
+        let io = |source| RotationError::Io {
+            path: self.path.clone(),
+            source,
+        };
+        let tmp = self.path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp).map_err(io)?;
+        serde_json::to_writer_pretty(&mut file, rotations)
+            .map_err(std::io::Error::from)
+            .map_err(io)?;
+        file.flush().map_err(io)?;
+        file.sync_all().map_err(io)?;
+        std::fs::rename(&tmp, &self.path).map_err(io)
+    }
+}
+
+/// Endorsements as their base64 text form.
+mod text {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    use hp_admin_crypto::Endorsement;
+
+    pub fn serialize<S: Serializer>(endorsement: &Endorsement, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(endorsement)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Endorsement, D::Error> {
+        String::deserialize(d)?.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hp_admin_crypto::{KeyRotation, SigningKey};
+
+    use super::*;
+    use crate::admins::Admin;
+
+    fn key(byte: u8) -> SigningKey {
+        SigningKey::from_bytes(&[byte; 32])
+    }
+
+    fn rotation(old: u8, new: u8, retires: u64) -> Rotation {
+        let endorsement =
+            KeyRotation::new(&key(old).verifying_key(), &key(new).verifying_key(), 100)
+                .unwrap()
+                .sign(&key(old))
+                .unwrap();
+        Rotation {
+            admin: "owner".into(),
+            endorsement,
+            rotated: 100,
+            retires,
+        }
+    }
+
+    #[test]
+    fn survives_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_path(&dir.path().join("hpos-config.json"));
+        assert_eq!(path, dir.path().join("hp-admin-rotations.json"));
+
+        let record = RotationRecord::open(&path).unwrap();
+        assert!(record.rotations().is_empty());
+        record.append(rotation(1, 2, 200)).unwrap();
+        record.append(rotation(2, 3, 300)).unwrap();
+
+        let reopened = RotationRecord::open(&path).unwrap();
+        assert_eq!(reopened.rotations(), record.rotations());
+        let mut admins = AdminKeys::new(Admin::new("owner", key(1).verifying_key()).unwrap());
+        assert!(reopened.apply(&mut admins).is_empty());
+        let owner = admins.primary();
+        assert_eq!(owner.key(), &key(3).verifying_key());
+        assert_eq!(owner.retiring().unwrap().key, key(2).verifying_key());
+    }
+
+    #[test]
+    fn skips_rotations_the_config_has_caught_up_with() {
+        let dir = tempfile::tempdir().unwrap();
+        let record = RotationRecord::open(&dir.path().join("rotations.json")).unwrap();
+        record.append(rotation(1, 2, 200)).unwrap();
+
+        let mut admins = AdminKeys::new(Admin::new("owner", key(2).verifying_key()).unwrap());
+        assert_eq!(record.apply(&mut admins).len(), 1);
+        assert_eq!(admins.primary().key(), &key(2).verifying_key());
+    }
+
+    #[test]
+    fn refuses_a_corrupt_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rotations.json");
+        std::fs::write(
+            &path,
+            r#"[{"admin": "owner", "endorsement": "bm9wZQ==", "rotated": 1, "retires": 2}]"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            RotationRecord::open(&path),
+            Err(RotationError::Corrupt { .. })
+        ));
+    }
+}
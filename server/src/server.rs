@@ -0,0 +1,155 @@
+//! HTTP front end that nginx `auth_request` talks to.
+
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::header::{CACHE_CONTROL, RETRY_AFTER};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+
+use crate::admins::ADMIN_ID;
+use crate::nonce::NonceError;
+use crate::verify::{Credential, Rejection, Verifier};
+
+/// Builds the router. `POST /nonce` issues a challenge nonce and
+/// `POST /login` a session token, which `POST /logout` revokes; a signed
+/// `POST /revoke-sessions` revokes them all, and a signed `POST /rotate`
+/// hands an admin's key over to the one it endorses. Every other path
+/// answers the same check, so nginx can point `auth_request` at whatever
+/// internal location it likes, and names the admin in `X-Hpos-Admin-Id`
+/// when it passes.
+pub fn router(verifier: Arc<Verifier>) -> Router {
+    Router::new()
+        .route("/nonce", post(issue_nonce))
+        .route("/login", post(login))
+        .route("/logout", post(logout))
+        .route("/revoke-sessions", post(revoke_sessions))
+        .route("/rotate", post(rotate))
+        .fallback(auth)
+        .with_state(verifier)
+}
+
+async fn issue_nonce(State(verifier): State<Arc<Verifier>>, headers: HeaderMap) -> Response {
+    match verifier.issue_nonce(&headers) {
+        Ok(issued) => ([(CACHE_CONTROL, "no-store")], Json(issued)).into_response(),
+        Err(NonceError::Full) => {
+            tracing::warn!("refusing to issue a nonce: too many outstanding");
+            StatusCode::SERVICE_UNAVAILABLE.into_response()
+        }
+        Err(NonceError::ClientFull(client)) => {
+            tracing::info!(%client, "refusing to issue a nonce: too many outstanding for client");
+            StatusCode::TOO_MANY_REQUESTS.into_response()
+        }
+        Err(e) => {
+            tracing::error!("cannot issue nonce: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn login(State(verifier): State<Arc<Verifier>>, headers: HeaderMap, body: Bytes) -> Response {
+    match verifier.login(&headers, &body) {
+        Ok(issued) => {
+            tracing::info!(expires = issued.expires, "session issued");
+            ([(CACHE_CONTROL, "no-store")], Json(issued)).into_response()
+        }
+        Err(rejection) => {
+            tracing::info!(%rejection, "login rejected");
+            rejection.into_response()
+        }
+    }
+}
+
+async fn logout(State(verifier): State<Arc<Verifier>>, headers: HeaderMap) -> Response {
+    match verifier.logout(&headers) {
+        Ok(session) => {
+            tracing::info!(session = session.id, "session revoked");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(rejection) => {
+            tracing::info!(%rejection, "logout rejected");
+            rejection.into_response()
+        }
+    }
+}
+
+async fn revoke_sessions(
+    State(verifier): State<Arc<Verifier>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match verifier.revoke_sessions(&headers, &body) {
+        Ok(()) => {
+            tracing::warn!("every session revoked");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(rejection) => {
+            tracing::info!(%rejection, "session revocation rejected");
+            rejection.into_response()
+        }
+    }
+}
+
+async fn rotate(
+    State(verifier): State<Arc<Verifier>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match verifier.rotate(&headers, &body) {
+        Ok(rotation) => {
+            tracing::warn!(
+                admin = rotation.admin,
+                key = hp_admin_crypto::encode_public_key(rotation.endorsement.rotation().new_key()),
+                retires = rotation.retires,
+                "admin key rotated"
+            );
+            Json(rotation).into_response()
+        }
+        Err(rejection) => {
+            tracing::info!(%rejection, "key rotation rejected");
+            rejection.into_response()
+        }
+    }
+}
+
+async fn auth(State(verifier): State<Arc<Verifier>>, headers: HeaderMap, body: Bytes) -> Response {
+    let credential = match verifier.authenticate(&headers, &body) {
+        Ok(credential) => credential,
+        Err(rejection) => {
+            tracing::info!(%rejection, "rejected");
+            return rejection.into_response();
+        }
+    };
+    match &credential {
+        Credential::Signature(claims) => tracing::debug!(
+            admin = claims.admin(),
+            method = claims.method,
+            uri = claims.uri,
+            scheme = ?claims.scheme,
+            "accepted"
+        ),
+        Credential::Session(session) => tracing::debug!(
+            admin = session.admin,
+            session = session.id,
+            "accepted session token"
+        ),
+    }
+    // Admin ids are checked for control characters when loaded, so this
+    // cannot fail.
+    let admin = HeaderValue::from_bytes(credential.admin().as_bytes()).expect("admin id");
+    ([(ADMIN_ID, admin)], StatusCode::OK).into_response()
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        match self {
+            Rejection::RateLimited { retry_after } => {
+                (self.status(), [(RETRY_AFTER, retry_after.to_string())]).into_response()
+            }
+            rejection => rejection.status().into_response(),
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! Short-lived session tokens, so that HP Admin can sign one login call
+//! instead of every call.
+//!
+//! A client that proves possession of the admin key on `POST /login` gets a
+//! PASETO `v4.public` token signed by the server's own session key and bound
+//! to the host it logged in to. It then sends the token as
+//! `Authorization: Bearer <token>` until the token expires.
+//!
+//! The token carries the registered claims `sub` (the id of the admin who
+//! logged in), `aud` (the host), `iat`, `exp` and `jti` (a random token id),
+//! and `epoch`, the revocation epoch it was issued in. The session key is
+//! generated at start-up, or kept in a file by [`Sessions::open`] so that
+//! tokens survive a restart. Tokens can be revoked before they expire; see
+//! [`crate::revocation`].
+
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::prelude::{Engine, BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD};
+use ed25519_dalek::Signer;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use hp_admin_crypto::{Signature, SigningKey, VerifyingKey};
+
+use crate::revocation::{MemoryRevocationStore, RevocationError, RevocationStore};
+
+/// How long an issued token stays valid, by default.
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// PASETO version and purpose, which prefix every token.
+const HEADER: &str = "v4.public.";
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("session key {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("session key {path} is not a base64 32-byte Ed25519 seed")]
+    CorruptKey { path: PathBuf },
+    #[error("no randomness available: {0}")]
+    Random(getrandom::Error),
+}
+
+/// Why a session token was refused.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TokenError {
+    #[error("not a v4.public token: {0}")]
+    Malformed(String),
+    #[error("signature does not verify against the session key")]
+    BadSignature,
+    #[error("issued for host {issued_for:?}, not {host:?}")]
+    WrongHost { issued_for: String, host: String },
+    #[error("expired at {expires}, now {now}")]
+    Expired { expires: u64, now: u64 },
+    #[error("revoked")]
+    Revoked,
+    #[error("issued to {0:?}, who is no longer an admin of this host")]
+    UnknownAdmin(String),
+}
+
+/// A freshly issued token, as returned to the client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IssuedSession {
+    pub token: String,
+    /// Seconds since the Unix epoch after which the token is refused.
+    pub expires: u64,
+}
+
+/// What a verified token vouches for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub id: String,
+    /// Id of the admin who logged in; see [`crate::admins`].
+    pub admin: String,
+    pub host: String,
+    pub issued: u64,
+    pub expires: u64,
+    /// The revocation epoch the token was issued in; see
+    /// [`RevocationStore::epoch`].
+    pub epoch: u64,
+}
+
+/// The token body, as signed.
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenClaims {
+    sub: String,
+    aud: String,
+    iat: String,
+    exp: String,
+    jti: String,
+    epoch: u64,
+}
+
+/// Issues and checks session tokens.
+pub struct Sessions {
+    key: SigningKey,
+    ttl: u64,
+    revocations: Arc<dyn RevocationStore>,
+}
+
+impl Sessions {
+    pub fn new(key: SigningKey, ttl: Duration) -> Self {
+        Sessions {
+            key,
+            ttl: ttl.as_secs(),
+            revocations: Arc::new(MemoryRevocationStore::default()),
+        }
+    }
+
+    /// Keep revoked tokens in `store`.
+    pub fn with_revocation_store(mut self, store: Arc<dyn RevocationStore>) -> Self {
+        self.revocations = store;
+        self
+    }
+
+    /// Uses a fresh random key; every token is invalidated by a restart.
+    pub fn generate(ttl: Duration) -> Result<Self, SessionError> {
+        Ok(Self::new(random_key()?, ttl))
+    }
+
+    /// Uses the key kept in `path`, creating the file with a fresh key, and
+    /// readable only by us, if it does not exist yet.
+    pub fn open(path: &Path, ttl: Duration) -> Result<Self, SessionError> {
+        let io = |source| SessionError::Io {
+            path: path.to_owned(),
+            source,
+        };
+        let key = match std::fs::read_to_string(path) {
+            Ok(encoded) => {
+                let seed = BASE64_STANDARD
+                    .decode(encoded.trim())
+                    .ok()
+                    .and_then(|seed| <[u8; 32]>::try_from(seed).ok())
+                    .ok_or_else(|| SessionError::CorruptKey {
+                        path: path.to_owned(),
+                    })?;
+                SigningKey::from_bytes(&seed)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key = random_key()?;
+                let mut file = std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(path)
+                    .map_err(io)?;
+                writeln!(file, "{}", BASE64_STANDARD.encode(key.to_bytes())).map_err(io)?;
+                file.sync_all().map_err(io)?;
+                key
+            }
+            Err(source) => return Err(io(source)),
+        };
+        Ok(Self::new(key, ttl))
+    }
+
+    /// The public half of the session key.
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    /// Issues a token for `admin` on `host`, valid from `now` for the
+    /// session lifetime.
+    pub fn issue(&self, admin: &str, host: &str, now: u64) -> Result<IssuedSession, SessionError> {
+        let mut id = [0; 16];
+        getrandom::fill(&mut id).map_err(SessionError::Random)?;
+        let expires = now + self.ttl;
+        let claims = TokenClaims {
+            sub: admin.to_owned(),
+            aud: host.to_owned(),
+            iat: rfc3339(now),
+            exp: rfc3339(expires),
+            jti: BASE64_URL_SAFE_NO_PAD.encode(id),
+            epoch: self.revocations.epoch(),
+        };
+        let message = serde_json::to_vec(&claims).expect("claims serialize");
+        Ok(IssuedSession {
+            token: sign(&self.key, &message),
+            expires,
+        })
+    }
+
+    /// Checks that `token` was issued by us for `host` and has neither
+    /// expired nor been revoked.
+    pub fn verify(&self, token: &str, host: &str, now: u64) -> Result<Session, TokenError> {
+        let message = open(&self.key.verifying_key(), token)?;
+        let claims: TokenClaims = serde_json::from_slice(&message)
+            .map_err(|e| TokenError::Malformed(format!("claims: {e}")))?;
+        let time = |name, value: &str| {
+            parse_rfc3339(value)
+                .ok_or_else(|| TokenError::Malformed(format!("{name} is not an RFC 3339 time")))
+        };
+        let session = Session {
+            issued: time("iat", &claims.iat)?,
+            expires: time("exp", &claims.exp)?,
+            epoch: claims.epoch,
+            id: claims.jti,
+            admin: claims.sub,
+            host: claims.aud,
+        };
+        if session.host != host {
+            return Err(TokenError::WrongHost {
+                issued_for: session.host,
+                host: host.to_owned(),
+            });
+        }
+        if session.expires <= now {
+            return Err(TokenError::Expired {
+                expires: session.expires,
+                now,
+            });
+        }
+        if self.revocations.is_revoked(&session) {
+            return Err(TokenError::Revoked);
+        }
+        Ok(session)
+    }
+
+    /// Revokes a single verified token, as on logout.
+    pub fn revoke(&self, session: &Session, now: u64) -> Result<(), RevocationError> {
+        self.revocations.revoke(&session.id, session.expires, now)
+    }
+
+    /// Revokes every token issued so far.
+    pub fn revoke_all(&self, now: u64) -> Result<(), RevocationError> {
+        self.revocations.revoke_all(now)
+    }
+}
+
+fn random_key() -> Result<SigningKey, SessionError> {
+    let mut seed = [0; 32];
+    getrandom::fill(&mut seed).map_err(SessionError::Random)?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// PASETO pre-authentication encoding: the piece count, then each piece
+/// preceded by its length, all lengths as little-endian 64-bit integers.
+fn pae(pieces: &[&[u8]]) -> Vec<u8> {
+    let mut encoded = (pieces.len() as u64).to_le_bytes().to_vec();
+    for piece in pieces {
+        encoded.extend_from_slice(&(piece.len() as u64).to_le_bytes());
+        encoded.extend_from_slice(piece);
+    }
+    encoded
+}
+
+/// Signs `message` into a `v4.public` token with no footer.
+fn sign(key: &SigningKey, message: &[u8]) -> String {
+    let signature = key.sign(&pae(&[HEADER.as_bytes(), message, b"", b""]));
+    let mut body = message.to_vec();
+    body.extend_from_slice(&signature.to_bytes());
+    format!("{HEADER}{}", BASE64_URL_SAFE_NO_PAD.encode(body))
+}
+
+/// Verifies a footerless `v4.public` token and returns its message.
+fn open(key: &VerifyingKey, token: &str) -> Result<Vec<u8>, TokenError> {
+    let body = token
+        .strip_prefix(HEADER)
+        .ok_or_else(|| TokenError::Malformed("wrong header".into()))?;
+    if body.contains('.') {
+        return Err(TokenError::Malformed("unexpected footer".into()));
+    }
+    let mut message = BASE64_URL_SAFE_NO_PAD
+        .decode(body)
+        .map_err(|e| TokenError::Malformed(e.to_string()))?;
+    if message.len() < Signature::BYTE_SIZE {
+        return Err(TokenError::Malformed("too short".into()));
+    }
+    let signature = message.split_off(message.len() - Signature::BYTE_SIZE);
+    let signature = Signature::from_slice(&signature).map_err(|_| TokenError::BadSignature)?;
+    key.verify_strict(&pae(&[HEADER.as_bytes(), &message, b"", b""]), &signature)
+        .map_err(|_| TokenError::BadSignature)?;
+    Ok(message)
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SSZ`.
+fn rfc3339(secs: u64) -> String {
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Parses an RFC 3339 date-time at or after the Unix epoch, ignoring
+/// fractional seconds.
+fn parse_rfc3339(s: &str) -> Option<u64> {
+    let bytes = s.as_bytes();
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = s.get(range)?;
+        digits
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| digits.parse().ok())?
+    };
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    // Leap seconds are folded into the following second.
+    if second > 60 {
+        return None;
+    }
+
+    let mut rest = &s[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return None;
+        }
+        rest = &fraction[digits..];
+    }
+    let offset = match rest.as_bytes() {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+            let [hours, minutes] = [[h1, h2], [m1, m2]].map(|[tens, ones]| {
+                (tens.is_ascii_digit() && ones.is_ascii_digit())
+                    .then(|| i64::from(tens - b'0') * 10 + i64::from(ones - b'0'))
+            });
+            let offset = hours? * 3600 + minutes? * 60;
+            if *sign == b'+' {
+                offset
+            } else {
+                -offset
+            }
+        }
+        _ => return None,
+    };
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    u64::try_from(secs).ok()
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date (Howard Hinnant's
+/// algorithm).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn sessions() -> Sessions {
+        Sessions::new(SigningKey::from_bytes(&[9; 32]), DEFAULT_SESSION_TTL)
+    }
+
+    #[test]
+    fn paseto_v4_public_vector() {
+        // 4-S-1 from the PASETO test vectors.
+        let seed = hex::decode("b4cbfb43df4ce210727d953e4a713307fa19bb7d9f85041438d9e11b942a3774")
+            .unwrap();
+        let key = SigningKey::from_bytes(&seed.try_into().unwrap());
+        assert_eq!(
+            hex::encode(key.verifying_key().as_bytes()),
+            "1eb9dbbbbc047c03fd70604e0071f0987e16b28b757225c11f00415d0e20b1a2"
+        );
+        let message = br#"{"data":"this is a signed message","exp":"2022-01-01T00:00:00+00:00"}"#;
+        let token = "v4.public.eyJkYXRhIjoidGhpcyBpcyBhIHNpZ25lZCBtZXNzYWdlIiwiZXhwIjoiMjAyMi0wMS0wMVQwMDowMDowMCswMDowMCJ9bg_XBBzds8lTZShVlwwKSgeKpLT3yukTw6JUz3W4h_ExsQV-P0V54zemZDcAxFaSeef1QlXEFtkqxT1ciiQEDA";
+        assert_eq!(sign(&key, message), token);
+        assert_eq!(open(&key.verifying_key(), token).unwrap(), message);
+    }
+
+    #[test]
+    fn issued_tokens_verify_for_their_host_until_expiry() {
+        let sessions = sessions();
+        let issued = sessions
+            .issue("admin", "hpos.example", 1_600_000_000)
+            .unwrap();
+        assert_eq!(issued.expires, 1_600_000_900);
+
+        let session = sessions
+            .verify(&issued.token, "hpos.example", 1_600_000_899)
+            .unwrap();
+        assert_eq!(session.admin, "admin");
+        assert_eq!(session.host, "hpos.example");
+        assert_eq!(session.issued, 1_600_000_000);
+        assert_eq!(session.expires, 1_600_000_900);
+
+        assert!(matches!(
+            sessions.verify(&issued.token, "other.example", 1_600_000_000),
+            Err(TokenError::WrongHost { .. })
+        ));
+        assert_eq!(
+            sessions.verify(&issued.token, "hpos.example", 1_600_000_900),
+            Err(TokenError::Expired {
+                expires: 1_600_000_900,
+                now: 1_600_000_900
+            })
+        );
+    }
+
+    #[test]
+    fn login_in_the_same_second_survives_revoke_all() {
+        let sessions = sessions();
+        let now = 1_600_000_000;
+        let before = sessions.issue("admin", "hpos.example", now).unwrap();
+        sessions.revoke_all(now).unwrap();
+        let after = sessions.issue("admin", "hpos.example", now).unwrap();
+
+        assert_eq!(
+            sessions.verify(&before.token, "hpos.example", now),
+            Err(TokenError::Revoked)
+        );
+        sessions.verify(&after.token, "hpos.example", now).unwrap();
+
+        // Nor does a second revocation in that second spare the new token.
+        sessions.revoke_all(now).unwrap();
+        assert_eq!(
+            sessions.verify(&after.token, "hpos.example", now),
+            Err(TokenError::Revoked)
+        );
+    }
+
+    #[test]
+    fn token_ids_are_distinct() {
+        let sessions = sessions();
+        let verify = |token: &str| sessions.verify(token, "h", 0).unwrap().id;
+        let a = verify(&sessions.issue("admin", "h", 0).unwrap().token);
+        let b = verify(&sessions.issue("admin", "h", 0).unwrap().token);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn rejects_tokens_from_other_keys_and_tampering() {
+        let issued = sessions().issue("admin", "hpos.example", 0).unwrap();
+        let other = Sessions::new(SigningKey::from_bytes(&[10; 32]), DEFAULT_SESSION_TTL);
+        assert_eq!(
+            other.verify(&issued.token, "hpos.example", 0),
+            Err(TokenError::BadSignature)
+        );
+
+        let forged = sign(
+            &SigningKey::from_bytes(&[10; 32]),
+            br#"{"sub":"admin","aud":"hpos.example","iat":"1970-01-01T00:00:00Z","exp":"2100-01-01T00:00:00Z","jti":"x","epoch":0}"#,
+        );
+        assert_eq!(
+            sessions().verify(&forged, "hpos.example", 0),
+            Err(TokenError::BadSignature)
+        );
+
+        for token in [
+            "v3.public.AAAA",
+            "v4.public.AAAA",
+            &format!("{}.Zm9vdGVy", issued.token),
+        ] {
+            assert!(matches!(
+                sessions().verify(token, "hpos.example", 0),
+                Err(TokenError::Malformed(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rfc3339_round_trips() {
+        for secs in [0, 951_782_400, 1_600_000_000, 4_102_444_800] {
+            assert_eq!(parse_rfc3339(&rfc3339(secs)), Some(secs));
+        }
+        assert_eq!(rfc3339(1_600_000_000), "2020-09-13T12:26:40Z");
+        assert_eq!(
+            parse_rfc3339("2020-09-13T14:26:40.5+02:00"),
+            Some(1_600_000_000)
+        );
+        for bad in ["2020-09-13", "2020-13-01T00:00:00Z", "2020-09-13T12:26:40"] {
+            assert_eq!(parse_rfc3339(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn key_file_is_created_once_and_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.key");
+
+        let first = Sessions::open(&path, DEFAULT_SESSION_TTL).unwrap();
+        let issued = first.issue("admin", "hpos.example", 0).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let reopened = Sessions::open(&path, DEFAULT_SESSION_TTL).unwrap();
+        reopened.verify(&issued.token, "hpos.example", 0).unwrap();
+
+        std::fs::write(&path, "not a key").unwrap();
+        assert!(matches!(
+            Sessions::open(&path, DEFAULT_SESSION_TTL),
+            Err(SessionError::CorruptKey { .. })
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+//! Telling systemd how the service is doing (see `sd_notify(3)`).
+//!
+//! The server reports `READY=1` once its config has loaded and it is
+//! listening, keeps the watchdog fed if the unit sets `WatchdogSec=`, and
+//! keeps a one-line status for `systemctl status`: which admin keys are in
+//! force and how many calls have been decided. Outside systemd, when
+//! `NOTIFY_SOCKET` is unset, all of this does nothing.
+
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+use sha2::{Digest, Sha256};
+
+use hp_admin_crypto::VerifyingKey;
+
+use crate::admins::AdminKeys;
+use crate::metrics::Metrics;
+
+/// How often the status is refreshed when there is no watchdog to feed.
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Reports that the service is up, along with its status.
+pub fn ready(status: &str) {
+    notify(&[NotifyState::Ready, NotifyState::Status(status)]);
+}
+
+/// Replaces the status line.
+pub fn status(status: &str) {
+    notify(&[NotifyState::Status(status)]);
+}
+
+/// Feeds the watchdog.
+pub fn watchdog() {
+    notify(&[NotifyState::Watchdog]);
+}
+
+/// Reports that the service is shutting down.
+pub fn stopping() {
+    notify(&[NotifyState::Stopping]);
+}
+
+/// How often to feed the watchdog: half its timeout, if the unit set one
+/// for us.
+pub fn watchdog_interval() -> Option<Duration> {
+    let mut usec = 0;
+    sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec / 2))
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        tracing::warn!("cannot notify systemd: {e}");
+    }
+}
+
+/// A short, stable fingerprint of a public key: the start of its SHA-256,
+/// in hex.
+pub fn fingerprint(key: &VerifyingKey) -> String {
+    hex::encode(&Sha256::digest(key.as_bytes())[..8])
+}
+
+/// The status line: each admin with the fingerprint of their key, then the
+/// calls decided so far.
+pub fn status_line(admins: &AdminKeys, metrics: &Metrics) -> String {
+    let admins: Vec<String> = admins
+        .iter()
+        .map(|admin| format!("{} {}", admin.id(), fingerprint(admin.key())))
+        .collect();
+    let (accepted, rejected) = metrics.totals();
+    format!(
+        "admins: {}; {accepted} accepted, {rejected} rejected",
+        admins.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use hp_admin_crypto::SigningKey;
+
+    use super::*;
+    use crate::admins::Admin;
+
+    #[test]
+    fn status_names_admins_and_counts() {
+        let key = SigningKey::from_bytes(&[42; 32]).verifying_key();
+        let admins = AdminKeys::new(Admin::new("admin@example.com", key).unwrap());
+        let metrics = Metrics::default();
+        metrics.accepted("auth", Duration::ZERO);
+        metrics.rejected("login", "replay", Duration::ZERO);
+
+        let fingerprint = fingerprint(&key);
+        assert_eq!(fingerprint.len(), 16);
+        assert_eq!(
+            status_line(&admins, &metrics),
+            format!("admins: admin@example.com {fingerprint}; 1 accepted, 1 rejected")
+        );
+    }
+}
@@ -0,0 +1,953 @@
+//! The checks applied to every admin call that nginx forwards to us.
+
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, StatusCode};
+use thiserror::Error;
+
+use hp_admin_crypto::delegation::{DelegationChain, DELEGATION};
+use hp_admin_crypto::rfc9421::{self, MessageSignature, COVERED_COMPONENTS};
+use hp_admin_crypto::{
+    headers, ContentDigest, Endorsement, Payload, Signature, SignatureHeaders, VerifyingKey,
+};
+
+use crate::admins::{Admin, AdminKeys, AdminsError};
+use crate::audit::{AuditError, AuditLog, Event};
+use crate::clock::{Clock, SystemClock};
+use crate::metrics::Metrics;
+use crate::nonce::{
+    self, IssuedNonce, MemoryNonceStore, NonceError, NonceStore, DEFAULT_NONCE_TTL,
+};
+use crate::policy::{PolicyFile, Via};
+use crate::ratelimit::{client_addr, RateLimiter, RateLimits};
+use crate::reload::{AdminSource, LoadedAdmins, ReloadError};
+use crate::replay::ReplayCache;
+use crate::revocation::RevocationError;
+use crate::rotation::{Rotation, RotationError, RotationRecord, DEFAULT_ROTATION_GRACE};
+use crate::session::{IssuedSession, Session, SessionError, Sessions, TokenError};
+
+/// Method of the original client request, set by nginx.
+pub const ORIGINAL_METHOD: &str = "x-original-method";
+/// URI of the original client request, set by nginx.
+pub const ORIGINAL_URI: &str = "x-original-uri";
+/// Scheme of the original client request, set by nginx; RFC 9421 only.
+pub const ORIGINAL_SCHEME: &str = "x-original-scheme";
+/// `Host` of the original client request, set by nginx; RFC 9421 only.
+pub const ORIGINAL_HOST: &str = "x-original-host";
+/// Address of the client that made the original request, set by nginx;
+/// failed calls are rate-limited by it.
+pub const CLIENT_ADDR: &str = "x-real-ip";
+
+/// Why a request was turned away.
+#[derive(Debug, Clone, Error)]
+pub enum Rejection {
+    #[error("malformed request: {0}")]
+    Malformed(String),
+    #[error("signature does not verify against any admin public key")]
+    BadSignature,
+    #[error("signed with key {0:?}, which is neither an admin key nor a delegate")]
+    UnknownKey(String),
+    #[error("X-Hpos-Admin signatures are no longer accepted; sign with RFC 9421")]
+    LegacyScheme,
+    #[error("request body does not match the signed body digest")]
+    BodyDigestMismatch,
+    #[error("signed at {timestamp}, outside the accepted window around {now}")]
+    Expired { timestamp: u64, now: u64 },
+    #[error("delegation expired at {expires}, now {now}")]
+    DelegationExpired { expires: u64, now: u64 },
+    #[error("outside the delegated scope: {0}")]
+    OutOfScope(String),
+    #[error("not allowed by policy: {0}")]
+    NotAllowed(String),
+    #[error("signature has already been used")]
+    Replay,
+    #[error("a server-issued nonce is required")]
+    MissingNonce,
+    #[error("nonce is unknown, expired or already used")]
+    UnknownNonce,
+    #[error(transparent)]
+    NonceStore(Arc<NonceError>),
+    #[error("session token rejected: {0}")]
+    InvalidToken(#[from] TokenError),
+    #[error("session logins are not enabled")]
+    SessionsDisabled,
+    #[error(transparent)]
+    Session(Arc<SessionError>),
+    #[error(transparent)]
+    RevocationStore(Arc<RevocationError>),
+    #[error("invalid endorsement: {0}")]
+    InvalidEndorsement(String),
+    #[error("key rotation is not enabled")]
+    RotationDisabled,
+    #[error(transparent)]
+    RotationRecord(Arc<RotationError>),
+    #[error(transparent)]
+    AuditLog(Arc<AuditError>),
+    #[error("too many failed attempts; retry in {retry_after}s")]
+    RateLimited { retry_after: u64 },
+}
+
+impl Rejection {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Rejection::Malformed(_) | Rejection::InvalidEndorsement(_) => StatusCode::BAD_REQUEST,
+            Rejection::BadSignature
+            | Rejection::UnknownKey(_)
+            | Rejection::LegacyScheme
+            | Rejection::BodyDigestMismatch
+            | Rejection::Expired { .. }
+            | Rejection::Replay
+            | Rejection::MissingNonce
+            | Rejection::UnknownNonce
+            | Rejection::InvalidToken(_)
+            | Rejection::DelegationExpired { .. } => StatusCode::UNAUTHORIZED,
+            Rejection::OutOfScope(_) | Rejection::NotAllowed(_) => StatusCode::FORBIDDEN,
+            Rejection::SessionsDisabled | Rejection::RotationDisabled => StatusCode::NOT_FOUND,
+            Rejection::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Rejection::NonceStore(_)
+            | Rejection::Session(_)
+            | Rejection::RevocationStore(_)
+            | Rejection::RotationRecord(_)
+            | Rejection::AuditLog(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl Rejection {
+    /// A short name for why, as metrics label it.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Rejection::Malformed(_) => "malformed",
+            Rejection::BadSignature => "bad_signature",
+            Rejection::UnknownKey(_) => "unknown_key",
+            Rejection::LegacyScheme => "legacy_scheme",
+            Rejection::BodyDigestMismatch => "body_digest_mismatch",
+            Rejection::Expired { .. } => "expired",
+            Rejection::DelegationExpired { .. } => "delegation_expired",
+            Rejection::OutOfScope(_) => "out_of_scope",
+            Rejection::NotAllowed(_) => "not_allowed",
+            Rejection::Replay => "replay",
+            Rejection::MissingNonce => "missing_nonce",
+            Rejection::UnknownNonce => "unknown_nonce",
+            Rejection::InvalidToken(_) => "invalid_token",
+            Rejection::SessionsDisabled | Rejection::RotationDisabled => "disabled",
+            Rejection::InvalidEndorsement(_) => "invalid_endorsement",
+            Rejection::RateLimited { .. } => "rate_limited",
+            Rejection::NonceStore(_)
+            | Rejection::Session(_)
+            | Rejection::RevocationStore(_)
+            | Rejection::RotationRecord(_)
+            | Rejection::AuditLog(_) => "internal",
+        }
+    }
+}
+
+impl From<NonceError> for Rejection {
+    fn from(e: NonceError) -> Self {
+        Rejection::NonceStore(Arc::new(e))
+    }
+}
+
+impl From<SessionError> for Rejection {
+    fn from(e: SessionError) -> Self {
+        Rejection::Session(Arc::new(e))
+    }
+}
+
+impl From<RevocationError> for Rejection {
+    fn from(e: RevocationError) -> Self {
+        Rejection::RevocationStore(Arc::new(e))
+    }
+}
+
+impl From<RotationError> for Rejection {
+    fn from(e: RotationError) -> Self {
+        Rejection::RotationRecord(Arc::new(e))
+    }
+}
+
+impl From<AuditError> for Rejection {
+    fn from(e: AuditError) -> Self {
+        Rejection::AuditLog(Arc::new(e))
+    }
+}
+
+impl From<hp_admin_crypto::Error> for Rejection {
+    fn from(e: hp_admin_crypto::Error) -> Self {
+        match e {
+            hp_admin_crypto::Error::BadSignature => Rejection::BadSignature,
+            other => Rejection::Malformed(other.to_string()),
+        }
+    }
+}
+
+/// How far a signed timestamp may be from our clock, by default.
+pub const DEFAULT_MAX_SKEW: Duration = Duration::from_secs(60);
+/// How many recent signatures are remembered for replay detection, by
+/// default.
+pub const DEFAULT_REPLAY_CACHE_SIZE: usize = 10_000;
+
+/// Verifies `auth_request` subrequests against the HPOS admin public keys.
+pub struct Verifier {
+    admins: RwLock<Arc<AdminKeys>>,
+    check_body: bool,
+    max_skew: u64,
+    clock: Arc<dyn Clock>,
+    replay_cache: Mutex<ReplayCache>,
+    nonces: Arc<dyn NonceStore>,
+    nonce_ttl: u64,
+    require_nonce: bool,
+    require_rfc9421: bool,
+    sessions: Option<Sessions>,
+    policy: Option<Arc<PolicyFile>>,
+    rotations: Option<Arc<RotationRecord>>,
+    rotation_grace: u64,
+    audit_log: Option<Arc<AuditLog>>,
+    metrics: Arc<Metrics>,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl Verifier {
+    pub fn new(admins: AdminKeys) -> Self {
+        Verifier {
+            admins: RwLock::new(Arc::new(admins)),
+            check_body: false,
+            max_skew: DEFAULT_MAX_SKEW.as_secs(),
+            clock: Arc::new(SystemClock),
+            replay_cache: Mutex::new(ReplayCache::new(DEFAULT_REPLAY_CACHE_SIZE)),
+            nonces: Arc::new(MemoryNonceStore::default()),
+            nonce_ttl: DEFAULT_NONCE_TTL,
+            require_nonce: false,
+            require_rfc9421: false,
+            sessions: None,
+            policy: None,
+            rotations: None,
+            rotation_grace: DEFAULT_ROTATION_GRACE.as_secs(),
+            audit_log: None,
+            metrics: Arc::default(),
+            rate_limiter: None,
+        }
+    }
+
+    /// Accept signatures made at most `max_skew` before or after now.
+    pub fn with_max_skew(mut self, max_skew: Duration) -> Self {
+        self.max_skew = max_skew.as_secs();
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Remember up to `size` recent signatures to reject exact replays.
+    pub fn with_replay_cache_size(mut self, size: usize) -> Self {
+        self.replay_cache = Mutex::new(ReplayCache::new(size));
+        self
+    }
+
+    /// Also require the body we are given to hash to the signed digest.
+    ///
+    /// `auth_request` subrequests carry no body, so by default the signed
+    /// digest is trusted as-is and the upstream is responsible for the body.
+    /// Turn this on when nginx forwards the full request body to us.
+    pub fn with_body_check(mut self, check_body: bool) -> Self {
+        self.check_body = check_body;
+        self
+    }
+
+    /// Keep issued nonces in `store`, valid for `ttl` after issue.
+    pub fn with_nonce_store(mut self, store: Arc<dyn NonceStore>, ttl: Duration) -> Self {
+        self.nonces = store;
+        self.nonce_ttl = ttl.as_secs();
+        self
+    }
+
+    /// Challenge mode: refuse every request that does not sign a nonce.
+    pub fn with_nonce_required(mut self, require_nonce: bool) -> Self {
+        self.require_nonce = require_nonce;
+        self
+    }
+
+    /// Refuse requests signed with the `X-Hpos-Admin-*` headers, once every
+    /// client has moved to RFC 9421 message signatures.
+    pub fn with_rfc9421_required(mut self, require_rfc9421: bool) -> Self {
+        self.require_rfc9421 = require_rfc9421;
+        self
+    }
+
+    /// Offer `POST /login`, which trades one signed call for a session
+    /// token, and accept those tokens in place of a signature.
+    pub fn with_sessions(mut self, sessions: Sessions) -> Self {
+        self.sessions = Some(sessions);
+        self
+    }
+
+    /// Only let through the calls that `policy` allows, once they have
+    /// authenticated.
+    pub fn with_policy(mut self, policy: Arc<PolicyFile>) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Offer `POST /rotate`, which replaces an admin's key with one it
+    /// endorses, keeping the old key valid for `grace`. Accepted rotations
+    /// are appended to `record`; the admins given to [`new`](Self::new) must
+    /// already have the recorded ones applied.
+    pub fn with_rotation(mut self, record: Arc<RotationRecord>, grace: Duration) -> Self {
+        self.rotations = Some(record);
+        self.rotation_grace = grace.as_secs();
+        self
+    }
+
+    /// Record every decision in `log`, and refuse calls whose decision
+    /// cannot be recorded.
+    pub fn with_audit_log(mut self, log: Arc<AuditLog>) -> Self {
+        self.audit_log = Some(log);
+        self
+    }
+
+    /// Count decisions in `metrics` rather than in a set of our own.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// Throttle failed verifications, per client address and overall (see
+    /// [`ratelimit`](crate::ratelimit)).
+    pub fn with_rate_limits(mut self, limits: RateLimits) -> Self {
+        self.rate_limiter = Some(RateLimiter::new(limits));
+        self
+    }
+
+    /// Issues a new single-use nonce for the client that a `POST /nonce`
+    /// with `headers` came from to sign.
+    pub fn issue_nonce(&self, headers: &HeaderMap) -> Result<IssuedNonce, NonceError> {
+        let now = self.clock.now();
+        let issued = IssuedNonce {
+            nonce: nonce::generate()?,
+            expires: now + self.nonce_ttl,
+        };
+        self.nonces
+            .insert(&issued.nonce, client_addr(headers), issued.expires, now)?;
+        Ok(issued)
+    }
+
+    /// The admins as of now, key rotations included.
+    pub fn admins(&self) -> Arc<AdminKeys> {
+        self.admins.read().unwrap().clone()
+    }
+
+    /// Loads the admins from `source` and puts them in force, or keeps the
+    /// ones in force if they do not load. Key rotations wait meanwhile, so
+    /// that none is lost between reading the record and swapping.
+    pub fn reload_admins(&self, source: &AdminSource) -> Result<LoadedAdmins, ReloadError> {
+        let mut admins = self.admins.write().unwrap();
+        let loaded = source.load()?;
+        *admins = Arc::new(loaded.admins.clone());
+        Ok(loaded)
+    }
+
+    /// Checks a signed login call and issues a session token bound to the
+    /// host it was made to.
+    pub fn login(&self, headers: &HeaderMap, body: &[u8]) -> Result<IssuedSession, Rejection> {
+        self.audited("login", headers, |admin| {
+            let sessions = self.sessions.as_ref().ok_or(Rejection::SessionsDisabled)?;
+            let claims = self.verify(headers, body)?;
+            *admin = claims.admin.clone();
+            require_admin(&claims)?;
+            let host = header(headers, ORIGINAL_HOST)?;
+            Ok(sessions.issue(claims.admin(), host, self.clock.now())?)
+        })
+    }
+
+    /// Revokes the session token the request carries.
+    pub fn logout(&self, headers: &HeaderMap) -> Result<Session, Rejection> {
+        self.audited("logout", headers, |admin| {
+            let sessions = self.sessions.as_ref().ok_or(Rejection::SessionsDisabled)?;
+            let token = bearer_token(headers)?
+                .ok_or_else(|| Rejection::Malformed("missing bearer token".into()))?;
+            let now = self.clock.now();
+            let session = sessions.verify(token, header(headers, ORIGINAL_HOST)?, now)?;
+            *admin = Some(session.admin.clone());
+            sessions.revoke(&session, now)?;
+            Ok(session)
+        })
+    }
+
+    /// Checks a signed call and revokes every session token issued so far,
+    /// for when a token may have been stolen.
+    pub fn revoke_sessions(&self, headers: &HeaderMap, body: &[u8]) -> Result<(), Rejection> {
+        self.audited("revoke-sessions", headers, |admin| {
+            let sessions = self.sessions.as_ref().ok_or(Rejection::SessionsDisabled)?;
+            let claims = self.verify(headers, body)?;
+            *admin = claims.admin.clone();
+            require_admin(&claims)?;
+            Ok(sessions.revoke_all(self.clock.now())?)
+        })
+    }
+
+    /// Checks a call signed by an admin whose body is an [`Endorsement`] of
+    /// a new key by their current one, and makes the new key theirs. The old
+    /// key is accepted until the grace period ends.
+    pub fn rotate(&self, headers: &HeaderMap, body: &[u8]) -> Result<Rotation, Rejection> {
+        self.audited("rotate", headers, |admin| {
+            self.rotate_key(headers, body, admin)
+        })
+    }
+
+    fn rotate_key(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+        signer: &mut Option<String>,
+    ) -> Result<Rotation, Rejection> {
+        let record = self.rotations.as_ref().ok_or(Rejection::RotationDisabled)?;
+        let claims = self.verify(headers, body)?;
+        *signer = claims.admin.clone();
+        require_admin(&claims)?;
+        // The endorsement is the body, so it must be the body that was
+        // signed, whether or not calls are body-checked in general.
+        check_body(&claims, body)?;
+        let endorsement: Endorsement = std::str::from_utf8(body)
+            .map_err(|_| Rejection::InvalidEndorsement("not UTF-8".into()))?
+            .parse()
+            .map_err(|e: hp_admin_crypto::Error| Rejection::InvalidEndorsement(e.to_string()))?;
+
+        let now = self.clock.now();
+        let mut admins = self.admins.write().unwrap();
+        let mut rotated = AdminKeys::clone(&admins);
+        let admin = match rotated.rotate(&endorsement, now + self.rotation_grace) {
+            Ok(admin) if admin.id() == claims.admin() => admin,
+            Ok(_) | Err(AdminsError::UnknownOldKey(_)) => {
+                return Err(Rejection::OutOfScope(
+                    "an admin can only endorse a successor to their own current key".into(),
+                ))
+            }
+            Err(e) => return Err(Rejection::InvalidEndorsement(e.to_string())),
+        };
+        let rotation = Rotation {
+            admin: admin.id().to_owned(),
+            endorsement,
+            rotated: now,
+            retires: now + self.rotation_grace,
+        };
+        // On disk before it takes effect, so that a restart cannot bring
+        // back a key that has been handed over.
+        record.append(rotation.clone())?;
+        *admins = Arc::new(rotated);
+        Ok(rotation)
+    }
+
+    /// Accepts a subrequest carrying either a session token or a signature,
+    /// if the policy, when there is one, allows the call.
+    pub fn authenticate(&self, headers: &HeaderMap, body: &[u8]) -> Result<Credential, Rejection> {
+        self.audited("auth", headers, |admin| self.admit(headers, body, admin))
+    }
+
+    fn admit(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+        admin: &mut Option<String>,
+    ) -> Result<Credential, Rejection> {
+        let credential = match bearer_token(headers)? {
+            None => Credential::Signature(self.verify(headers, body)?),
+            Some(token) => {
+                let sessions = self.sessions.as_ref().ok_or_else(|| {
+                    TokenError::Malformed("session tokens are not enabled".into())
+                })?;
+                let host = header(headers, ORIGINAL_HOST)?;
+                let session = sessions.verify(token, host, self.clock.now())?;
+                // A reload may have removed the admin since they logged in.
+                if !self
+                    .admins()
+                    .iter()
+                    .any(|admin| admin.id() == session.admin)
+                {
+                    return Err(TokenError::UnknownAdmin(session.admin).into());
+                }
+                Credential::Session(session)
+            }
+        };
+        *admin = Some(credential.admin().to_owned());
+        if let Some(policy) = &self.policy {
+            // Session calls are not signed, so only nginx vouches for what
+            // they are.
+            let (method, uri) = match &credential {
+                Credential::Signature(claims) => (claims.method.as_str(), claims.uri.as_str()),
+                Credential::Session(_) => (
+                    header(headers, ORIGINAL_METHOD)?,
+                    header(headers, ORIGINAL_URI)?,
+                ),
+            };
+            let (admin, via) = (credential.admin(), credential.via());
+            if !policy.policy().allows(admin, via, method, uri) {
+                return Err(Rejection::NotAllowed(format!(
+                    "{method} {uri} by {admin} via {via}"
+                )));
+            }
+        }
+        Ok(credential)
+    }
+
+    /// Runs `decide` unless the client is being throttled; `decide` fills
+    /// in the admin the call came from as soon as it knows, so that calls
+    /// rejected after that are put down to them too. Counts the decision,
+    /// charges it to the client if it failed to verify and records it in
+    /// the audit log, if there is one. A decision that cannot be recorded
+    /// becomes a rejection.
+    fn audited<T>(
+        &self,
+        action: &'static str,
+        headers: &HeaderMap,
+        decide: impl FnOnce(&mut Option<String>) -> Result<T, Rejection>,
+    ) -> Result<T, Rejection> {
+        let started = Instant::now();
+        let mut admin = None;
+        let outcome = match &self.rate_limiter {
+            None => decide(&mut admin),
+            Some(limiter) => {
+                let client = client_addr(headers);
+                let now = self.clock.now();
+                match limiter.check(client, now) {
+                    Err(retry_after) => Err(Rejection::RateLimited { retry_after }),
+                    Ok(()) => {
+                        let outcome = decide(&mut admin);
+                        match &outcome {
+                            Ok(_) => limiter.succeeded(client, now),
+                            Err(rejection) if rejection.status() == StatusCode::UNAUTHORIZED => {
+                                limiter.failed(client, now)
+                            }
+                            Err(_) => {}
+                        }
+                        outcome
+                    }
+                }
+            }
+        };
+        let recorded = match &self.audit_log {
+            None => Ok(()),
+            Some(log) => {
+                let event = match &outcome {
+                    Ok(_) => Event::allowed(action, headers, admin),
+                    Err(rejection) => Event::rejected(action, headers, admin, rejection),
+                };
+                log.record(event, self.clock.now()).map(drop)
+            }
+        };
+        let outcome = recorded.map_err(Rejection::from).and(outcome);
+        match &outcome {
+            Ok(_) => self.metrics.accepted(action, started.elapsed()),
+            Err(rejection) => self
+                .metrics
+                .rejected(action, rejection.reason(), started.elapsed()),
+        }
+        outcome
+    }
+
+    /// Checks the signature carried by a subrequest's headers, in either
+    /// scheme and by an admin key or a delegate of one, and returns what it
+    /// vouches for. `body` is only looked at with the body check on.
+    pub fn verify(&self, headers: &HeaderMap, body: &[u8]) -> Result<Claims, Rejection> {
+        let delegation = delegation(headers)?;
+        let admins = self.admins();
+        let now = self.clock.now();
+        let ((admin, _), mut claims) = if headers.contains_key(rfc9421::SIGNATURE_INPUT) {
+            let message = SignedMessage::from_headers(headers)?;
+            let signer = identify(&admins, now, delegation.as_ref(), |key| message.verify(key))?;
+            (signer, message.claims()?)
+        } else if self.require_rfc9421 {
+            return Err(Rejection::LegacyScheme);
+        } else {
+            let request = SignedRequest::from_headers(headers)?;
+            let signer = identify(&admins, now, delegation.as_ref(), |key| request.verify(key))?;
+            (signer, request.claims()?)
+        };
+        claims.admin = Some(admin.id().to_owned());
+        claims.delegation = delegation;
+
+        check_delegation(&claims, now)?;
+        // A signed nonce proves freshness on its own, so the client clock is
+        // only consulted for requests without one.
+        if claims.nonce.is_none() {
+            if self.require_nonce {
+                return Err(Rejection::MissingNonce);
+            }
+            check_timestamp(&claims, now, self.max_skew)?;
+        }
+        if claims.expires.is_some_and(|expires| expires < now) {
+            return Err(Rejection::Expired {
+                timestamp: claims.timestamp,
+                now,
+            });
+        }
+        if self.check_body {
+            check_body(&claims, body)?;
+        }
+
+        // Last, so that only requests that pass every other check use up
+        // their nonce or are remembered.
+        match &claims.nonce {
+            Some(nonce) => {
+                if !self.nonces.take(nonce, now)? {
+                    return Err(Rejection::UnknownNonce);
+                }
+            }
+            None => {
+                let fresh = self.replay_cache.lock().unwrap().insert(
+                    claims.signature.to_bytes(),
+                    claims.timestamp,
+                    now.saturating_sub(self.max_skew),
+                );
+                if !fresh {
+                    return Err(Rejection::Replay);
+                }
+            }
+        }
+        Ok(claims)
+    }
+}
+
+/// How a subrequest proved that it comes from an admin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credential {
+    Signature(Claims),
+    Session(Session),
+}
+
+impl Credential {
+    /// Id of the admin the subrequest comes from.
+    pub fn admin(&self) -> &str {
+        match self {
+            Credential::Signature(claims) => claims.admin(),
+            Credential::Session(session) => &session.admin,
+        }
+    }
+
+    /// How the subrequest authenticated, as policies name it.
+    pub fn via(&self) -> Via {
+        match self {
+            Credential::Signature(Claims {
+                delegation: Some(_),
+                ..
+            }) => Via::Delegation,
+            Credential::Signature(_) => Via::Signature,
+            Credential::Session(_) => Via::Session,
+        }
+    }
+}
+
+/// Which way a request was signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// The `X-Hpos-Admin-*` headers over the canonical payload.
+    Legacy,
+    /// RFC 9421 `Signature-Input` and `Signature`.
+    Rfc9421,
+}
+
+/// What a request's signature vouches for, whichever scheme carried it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claims {
+    pub scheme: Scheme,
+    pub method: String,
+    /// Path and query for the legacy scheme, the absolute target URI for
+    /// RFC 9421.
+    pub uri: String,
+    /// Signing time in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Time after which the signer wants the signature refused, if it said.
+    pub expires: Option<u64>,
+    pub nonce: Option<String>,
+    /// Digests of the body the client signed.
+    pub content_digest: ContentDigest,
+    pub signature: Signature,
+    /// Id of the admin whose key signed, or issued the delegation; set once
+    /// [`Verifier::verify`] has found which.
+    pub admin: Option<String>,
+    /// The certificates a delegate signed with, or `None` if an admin key
+    /// signed.
+    pub delegation: Option<DelegationChain>,
+}
+
+impl Claims {
+    /// Id of the admin the request comes from, or `""` if not yet known.
+    pub fn admin(&self) -> &str {
+        self.admin.as_deref().unwrap_or_default()
+    }
+}
+
+/// The parts of a legacy subrequest that describe the signed call, as sent.
+///
+/// [`Verifier::verify`] is built from this, [`SignedMessage`] and the
+/// `check_*` functions below, which `hp-admin-verify` also runs one by one
+/// to explain a rejection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedRequest {
+    pub method: String,
+    pub uri: String,
+    pub headers: SignatureHeaders,
+}
+
+impl SignedRequest {
+    /// Reads the original method and URI and the signature headers.
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, Rejection> {
+        Ok(SignedRequest {
+            method: header(headers, ORIGINAL_METHOD)?.to_owned(),
+            uri: header(headers, ORIGINAL_URI)?.to_owned(),
+            headers: SignatureHeaders {
+                signature: header(headers, headers::SIGNATURE)?.to_owned(),
+                timestamp: header(headers, headers::TIMESTAMP)?.to_owned(),
+                body_digest: optional_header(headers, headers::BODY_DIGEST)?.map(str::to_owned),
+                content_digest: optional_header(headers, headers::CONTENT_DIGEST)?
+                    .map(str::to_owned),
+                nonce: optional_header(headers, headers::NONCE)?.map(str::to_owned),
+            },
+        })
+    }
+
+    /// Rebuilds the payload the client must have signed.
+    pub fn payload(&self) -> Result<Payload, Rejection> {
+        Ok(self.headers.payload(&self.method, &self.uri)?)
+    }
+
+    pub fn signature(&self) -> Result<Signature, Rejection> {
+        Ok(self.headers.signature()?)
+    }
+
+    pub fn verify(&self, key: &VerifyingKey) -> Result<(), Rejection> {
+        Ok(hp_admin_crypto::verify(
+            key,
+            &self.payload()?,
+            &self.signature()?,
+        )?)
+    }
+
+    pub fn claims(&self) -> Result<Claims, Rejection> {
+        let payload = self.payload()?;
+        Ok(Claims {
+            scheme: Scheme::Legacy,
+            method: payload.method().to_owned(),
+            uri: payload.uri().to_owned(),
+            timestamp: payload.timestamp(),
+            expires: None,
+            nonce: payload.nonce().map(str::to_owned),
+            content_digest: ContentDigest::from(*payload.body_digest()),
+            signature: self.signature()?,
+            admin: None,
+            delegation: None,
+        })
+    }
+}
+
+/// An RFC 9421 signed subrequest, as sent.
+///
+/// The target URI is put back together from the original scheme, `Host`
+/// and URI that nginx passes along; every other header of the subrequest is
+/// available for the signature to cover.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedMessage {
+    pub request: rfc9421::Request,
+    pub signature: MessageSignature,
+}
+
+impl SignedMessage {
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, Rejection> {
+        let target_uri = format!(
+            "{}://{}{}",
+            header(headers, ORIGINAL_SCHEME)?,
+            header(headers, ORIGINAL_HOST)?,
+            header(headers, ORIGINAL_URI)?
+        );
+        let mut request = rfc9421::Request::new(header(headers, ORIGINAL_METHOD)?, &target_uri)?;
+        for (name, value) in headers {
+            if let Ok(value) = value.to_str() {
+                request = request.with_field(name.as_str(), value);
+            }
+        }
+        let signature = MessageSignature::from_headers(
+            header(headers, rfc9421::SIGNATURE_INPUT)?,
+            header(headers, rfc9421::SIGNATURE)?,
+        )?;
+        Ok(SignedMessage { request, signature })
+    }
+
+    /// Requires the signature to cover everything we rely on, and not to
+    /// name a key other than `key`, an admin key or a delegate.
+    pub fn check_params(&self, key: &VerifyingKey) -> Result<(), Rejection> {
+        let params = self.signature.params();
+        for id in COVERED_COMPONENTS {
+            if !params.components().any(|covered| covered == id) {
+                return Err(Rejection::Malformed(format!(
+                    "signature does not cover {id}"
+                )));
+            }
+        }
+        if params.created().is_none() {
+            return Err(Rejection::Malformed(
+                "signature has no created parameter".into(),
+            ));
+        }
+        if let Some(keyid) = params.keyid() {
+            if hp_admin_crypto::decode_public_key(keyid).ok() != Some(*key) {
+                return Err(Rejection::UnknownKey(keyid.to_owned()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn verify(&self, key: &VerifyingKey) -> Result<(), Rejection> {
+        self.check_params(key)?;
+        Ok(self.signature.verify(key, &self.request)?)
+    }
+
+    pub fn claims(&self) -> Result<Claims, Rejection> {
+        let params = self.signature.params();
+        let content_digest = self.request.component(rfc9421::CONTENT_DIGEST)?;
+        Ok(Claims {
+            scheme: Scheme::Rfc9421,
+            method: self.request.method().to_owned(),
+            uri: self.request.target_uri().to_owned(),
+            timestamp: params.created().unwrap_or_default(),
+            expires: params.expires(),
+            nonce: params.nonce().map(str::to_owned),
+            content_digest: content_digest.parse()?,
+            signature: *self.signature.signature(),
+            admin: None,
+            delegation: None,
+        })
+    }
+}
+
+/// Requires the signing time to be within `max_skew` seconds of `now`.
+pub fn check_timestamp(claims: &Claims, now: u64, max_skew: u64) -> Result<(), Rejection> {
+    if claims.timestamp.abs_diff(now) > max_skew {
+        return Err(Rejection::Expired {
+            timestamp: claims.timestamp,
+            now,
+        });
+    }
+    Ok(())
+}
+
+/// Finds the admin a request comes from, and which of their keys at `now`
+/// vouches for it: the one that makes `verify` accept the signature, or, for
+/// a delegate, the one that issued the chain, whose delegate must then have
+/// made the signature.
+pub fn identify<'a>(
+    admins: &'a AdminKeys,
+    now: u64,
+    delegation: Option<&DelegationChain>,
+    verify: impl Fn(&VerifyingKey) -> Result<(), Rejection>,
+) -> Result<(&'a Admin, &'a VerifyingKey), Rejection> {
+    let Some(chain) = delegation else {
+        return first_accepted(admins, now, verify);
+    };
+    let signer = first_accepted(admins, now, |key| Ok(chain.verify(key)?))?;
+    verify(chain.delegate())?;
+    Ok(signer)
+}
+
+/// The first admin key `check` accepts, or else the most telling rejection.
+fn first_accepted(
+    admins: &AdminKeys,
+    now: u64,
+    check: impl Fn(&VerifyingKey) -> Result<(), Rejection>,
+) -> Result<(&Admin, &VerifyingKey), Rejection> {
+    let mut rejection = None;
+    let keys = admins
+        .iter()
+        .flat_map(|admin| admin.keys(now).map(move |key| (admin, key)));
+    for (admin, key) in keys {
+        match check(key) {
+            Ok(()) => return Ok((admin, key)),
+            // An RFC 9421 keyid names one key and every other is refused
+            // unchecked; why the named key failed says more.
+            Err(unknown @ Rejection::UnknownKey(_)) => {
+                rejection.get_or_insert(unknown);
+            }
+            Err(e) => rejection = Some(e),
+        }
+    }
+    Err(rejection.expect("there is always an admin"))
+}
+
+/// Requires a delegate's certificates to be unexpired and to allow the call.
+pub fn check_delegation(claims: &Claims, now: u64) -> Result<(), Rejection> {
+    let Some(chain) = &claims.delegation else {
+        return Ok(());
+    };
+    if chain.expires() < now {
+        return Err(Rejection::DelegationExpired {
+            expires: chain.expires(),
+            now,
+        });
+    }
+    if !chain.allows(&claims.method, &claims.uri) {
+        return Err(Rejection::OutOfScope(format!(
+            "{} {}",
+            claims.method, claims.uri
+        )));
+    }
+    Ok(())
+}
+
+/// Reads the delegation chain of a request signed by a delegate.
+pub fn delegation(headers: &HeaderMap) -> Result<Option<DelegationChain>, Rejection> {
+    Ok(optional_header(headers, DELEGATION)?
+        .map(str::parse)
+        .transpose()?)
+}
+
+/// Refuses delegates, for the calls that only the admin may make.
+fn require_admin(claims: &Claims) -> Result<(), Rejection> {
+    match claims.delegation {
+        Some(_) => Err(Rejection::OutOfScope(
+            "only an admin key can manage sessions and keys".into(),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Requires `body` to hash to every signed body digest.
+pub fn check_body(claims: &Claims, body: &[u8]) -> Result<(), Rejection> {
+    if !claims.content_digest.matches(body) {
+        return Err(Rejection::BodyDigestMismatch);
+    }
+    Ok(())
+}
+
+/// The token of an `Authorization: Bearer` header, if there is one.
+fn bearer_token(headers: &HeaderMap) -> Result<Option<&str>, Rejection> {
+    let Some(value) = optional_header(headers, AUTHORIZATION.as_str())? else {
+        return Ok(None);
+    };
+    Ok(value
+        .split_once(' ')
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim()))
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str, Rejection> {
+    optional_header(headers, name)?
+        .ok_or_else(|| Rejection::Malformed(format!("missing {name} header")))
+}
+
+fn optional_header<'a>(headers: &'a HeaderMap, name: &str) -> Result<Option<&'a str>, Rejection> {
+    let mut values = headers.get_all(name).iter();
+    match (values.next(), values.next()) {
+        (Some(value), None) => value
+            .to_str()
+            .map(Some)
+            .map_err(|_| Rejection::Malformed(format!("{name} is not visible ASCII"))),
+        (None, _) => Ok(None),
+        (Some(_), Some(_)) => Err(Rejection::Malformed(format!("repeated {name} header"))),
+    }
+}
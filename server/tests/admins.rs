@@ -0,0 +1,160 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use tower::ServiceExt;
+
+use hp_admin_crypto::delegation::{Delegation, DelegationChain, DELEGATION};
+use hp_admin_crypto::SigningKey;
+use hp_admin_crypto_server::admins::{Admin, ADMIN_ID};
+use hp_admin_crypto_server::clock::ManualClock;
+use hp_admin_crypto_server::session::{Sessions, DEFAULT_SESSION_TTL};
+use hp_admin_crypto_server::verify::ORIGINAL_HOST;
+use hp_admin_crypto_server::{router, AdminKeys, Verifier};
+
+use common::*;
+
+fn alice() -> SigningKey {
+    SigningKey::from_bytes(&[7; 32])
+}
+
+/// The host's own admin plus Alice.
+fn team() -> AdminKeys {
+    admins()
+        .with_admin(Admin::new("alice", alice().verifying_key()).unwrap())
+        .unwrap()
+}
+
+fn team_router() -> Router {
+    let sessions = Sessions::new(SigningKey::from_bytes(&[3; 32]), DEFAULT_SESSION_TTL);
+    let verifier = Verifier::new(team())
+        .with_clock(Arc::new(ManualClock::new(NOW)))
+        .with_sessions(sessions);
+    router(Arc::new(verifier))
+}
+
+/// Sends `request` and returns the status and the admin it was attributed
+/// to, if any.
+async fn admin_id(router: &Router, request: Request<Body>) -> (StatusCode, Option<String>) {
+    let response = router.clone().oneshot(request).await.unwrap();
+    let id = response
+        .headers()
+        .get(ADMIN_ID)
+        .map(|id| id.to_str().unwrap().to_owned());
+    (response.status(), id)
+}
+
+#[tokio::test]
+async fn names_the_admin_that_signed() {
+    let router = team_router();
+    for (key, id) in [(admin(), "admin@example.com"), (alice(), "alice")] {
+        let signed = signed(&key, "GET", "/api/v1/status", b"");
+        assert_eq!(
+            admin_id(&router, subrequest("GET", "/api/v1/status", &signed)).await,
+            (StatusCode::OK, Some(id.to_owned()))
+        );
+
+        let signed = signed_message(&key, "GET", "/api/v1/status", b"", None);
+        assert_eq!(
+            admin_id(
+                &router,
+                message_subrequest("GET", "/api/v1/status", &signed, b"")
+            )
+            .await,
+            (StatusCode::OK, Some(id.to_owned()))
+        );
+    }
+}
+
+#[tokio::test]
+async fn rejects_keys_of_no_admin() {
+    let router = team_router();
+    let mallory = SigningKey::from_bytes(&[13; 32]);
+
+    let signed = signed(&mallory, "GET", "/api/v1/status", b"");
+    assert_eq!(
+        admin_id(&router, subrequest("GET", "/api/v1/status", &signed)).await,
+        (StatusCode::UNAUTHORIZED, None)
+    );
+
+    let signed = signed_message(&mallory, "GET", "/api/v1/status", b"", None);
+    assert_eq!(
+        admin_id(
+            &router,
+            message_subrequest("GET", "/api/v1/status", &signed, b"")
+        )
+        .await,
+        (StatusCode::UNAUTHORIZED, None)
+    );
+
+    // The host's own admin is no longer accepted once Alice is the only one.
+    let alone = Verifier::new(AdminKeys::new(
+        Admin::new("alice", alice().verifying_key()).unwrap(),
+    ))
+    .with_clock(Arc::new(ManualClock::new(NOW)));
+    let signed = signed_message(&admin(), "GET", "/api/v1/status", b"", None);
+    assert_eq!(
+        status_with(
+            alone,
+            message_subrequest("GET", "/api/v1/status", &signed, b"")
+        )
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn sessions_remember_who_logged_in() {
+    let router = team_router();
+    let signed = signed(&alice(), "POST", "/api/v1/hp-admin-login", b"");
+    let mut login = subrequest("POST", "/api/v1/hp-admin-login", &signed);
+    *login.uri_mut() = "/login".parse().unwrap();
+    *login.method_mut() = "POST".parse().unwrap();
+    login
+        .headers_mut()
+        .insert(ORIGINAL_HOST, "hpos.example".parse().unwrap());
+    let response = router.clone().oneshot(login).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), 4096)
+        .await
+        .unwrap();
+    let issued: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let token = issued["token"].as_str().unwrap();
+
+    let request = Request::get("/auth")
+        .header(ORIGINAL_HOST, "hpos.example")
+        .header(AUTHORIZATION, format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        admin_id(&router, request).await,
+        (StatusCode::OK, Some("alice".to_owned()))
+    );
+}
+
+#[tokio::test]
+async fn delegates_act_for_the_admin_that_issued_them() {
+    let bot = SigningKey::from_bytes(&[8; 32]);
+    let delegation = Delegation::new(
+        &bot.verifying_key(),
+        &["GET"],
+        &["/api/v1/status"],
+        NOW + 3600,
+    )
+    .unwrap();
+    let chain = DelegationChain::new(vec![delegation.sign(&alice())]).unwrap();
+
+    let signed = signed(&bot, "GET", "/api/v1/status", b"");
+    let mut request = subrequest("GET", "/api/v1/status", &signed);
+    request
+        .headers_mut()
+        .insert(DELEGATION, chain.to_string().parse().unwrap());
+    assert_eq!(
+        admin_id(&team_router(), request).await,
+        (StatusCode::OK, Some("alice".to_owned()))
+    );
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+
+use hp_admin_crypto::SigningKey;
+use hp_admin_crypto_server::audit::{self, AuditLog, Decision, Entry};
+use hp_admin_crypto_server::policy::PolicyFile;
+use hp_admin_crypto_server::router;
+
+use common::*;
+
+#[tokio::test]
+async fn records_every_decision_in_a_chain() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+    let log = Arc::new(AuditLog::open(&path).unwrap());
+    let router = router(Arc::new(verifier().with_audit_log(log)));
+
+    let signed = signed(&admin(), "PUT", "/api/v1/config", b"{}");
+    assert_eq!(
+        send(&router, subrequest("PUT", "/api/v1/config", &signed)).await,
+        StatusCode::OK
+    );
+    let forged = signed_message(
+        &SigningKey::from_bytes(&[13; 32]),
+        "GET",
+        "/api/v1/status",
+        b"",
+        None,
+    );
+    assert_eq!(
+        send(
+            &router,
+            message_subrequest("GET", "/api/v1/status", &forged, b"")
+        )
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    let entries: Vec<Entry> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 2);
+
+    let allowed = &entries[0].event;
+    assert_eq!(entries[0].time, NOW);
+    assert_eq!(allowed.action, "auth");
+    assert_eq!(allowed.decision, Decision::Allowed);
+    assert_eq!(allowed.admin.as_deref(), Some("admin@example.com"));
+    assert_eq!(allowed.method.as_deref(), Some("PUT"));
+    assert_eq!(allowed.uri.as_deref(), Some("/api/v1/config"));
+    // The sha-512 Content-Digest, which carries the same hash as the
+    // legacy body digest.
+    assert!(allowed
+        .body_digest
+        .as_deref()
+        .unwrap()
+        .contains(signed.body_digest.as_deref().unwrap()));
+
+    let rejected = &entries[1].event;
+    assert_eq!(rejected.decision, Decision::Rejected);
+    assert_eq!(rejected.admin, None);
+    assert!(rejected.reason.as_deref().unwrap().contains("neither"));
+    assert!(rejected.body_digest.is_some());
+
+    assert_eq!(audit::verify(&path).unwrap(), 2);
+}
+
+#[tokio::test]
+async fn puts_policy_rejections_down_to_the_admin() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+    let policy = dir.path().join("policy.toml");
+    std::fs::write(
+        &policy,
+        "[[rule]]\nvia = \"signature\"\nallow = [\"GET /api/v1/status\"]\n",
+    )
+    .unwrap();
+    let log = Arc::new(AuditLog::open(&path).unwrap());
+    let verifier = verifier()
+        .with_policy(Arc::new(PolicyFile::open(&policy).unwrap()))
+        .with_audit_log(log);
+    let router = router(Arc::new(verifier));
+
+    let signed = signed(&admin(), "PUT", "/api/v1/config", b"{}");
+    assert_eq!(
+        send(&router, subrequest("PUT", "/api/v1/config", &signed)).await,
+        StatusCode::FORBIDDEN
+    );
+
+    let entry: Entry =
+        serde_json::from_str(std::fs::read_to_string(&path).unwrap().trim()).unwrap();
+    assert_eq!(entry.event.decision, Decision::Rejected);
+    assert_eq!(entry.event.admin.as_deref(), Some("admin@example.com"));
+}
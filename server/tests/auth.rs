@@ -0,0 +1,179 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+
+use hp_admin_crypto::{SignatureHeaders, SigningKey};
+use hp_admin_crypto_server::clock::ManualClock;
+use hp_admin_crypto_server::verify::{ORIGINAL_METHOD, ORIGINAL_URI};
+use hp_admin_crypto_server::{router, Verifier};
+
+use common::*;
+
+#[tokio::test]
+async fn accepts_admin_signature() {
+    let headers = signed(&admin(), "POST", "/api/v1/config", b"{}");
+    assert_eq!(
+        status(subrequest("POST", "/api/v1/config", &headers)).await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn rejects_other_key() {
+    let other = SigningKey::from_bytes(&[1; 32]);
+    let headers = signed(&other, "POST", "/api/v1/config", b"{}");
+    assert_eq!(
+        status(subrequest("POST", "/api/v1/config", &headers)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn rejects_signature_for_other_request() {
+    let headers = signed(&admin(), "GET", "/api/v1/status", b"");
+    assert_eq!(
+        status(subrequest("DELETE", "/api/v1/status", &headers)).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status(subrequest("GET", "/api/v1/config", &headers)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn malformed_subrequests_are_bad_requests() {
+    let good = signed(&admin(), "GET", "/api/v1/status", b"");
+
+    let missing = Request::get("/auth")
+        .header(ORIGINAL_METHOD, "GET")
+        .header(ORIGINAL_URI, "/api/v1/status")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(status(missing).await, StatusCode::BAD_REQUEST);
+
+    let bad_signature = SignatureHeaders {
+        signature: "not base64!".into(),
+        ..good.clone()
+    };
+    assert_eq!(
+        status(subrequest("GET", "/api/v1/status", &bad_signature)).await,
+        StatusCode::BAD_REQUEST
+    );
+
+    let bad_timestamp = SignatureHeaders {
+        timestamp: "yesterday".into(),
+        ..good.clone()
+    };
+    assert_eq!(
+        status(subrequest("GET", "/api/v1/status", &bad_timestamp)).await,
+        StatusCode::BAD_REQUEST
+    );
+
+    assert_eq!(
+        status(subrequest("G T", "/api/v1/status", &good)).await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn body_check_accepts_signed_body() {
+    let verifier = verifier().with_body_check(true);
+    let headers = signed(&admin(), "POST", "/api/v1/config", b"{\"a\":1}");
+    let request = forwarded("POST", "/api/v1/config", &headers, b"{\"a\":1}");
+    assert_eq!(status_with(verifier, request).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn body_check_rejects_swapped_body() {
+    let verifier = verifier().with_body_check(true);
+    let headers = signed(&admin(), "POST", "/api/v1/config", b"{\"a\":1}");
+    let request = forwarded("POST", "/api/v1/config", &headers, b"{\"a\":2}");
+    assert_eq!(
+        status_with(verifier, request).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn body_is_ignored_without_body_check() {
+    let headers = signed(&admin(), "POST", "/api/v1/config", b"{\"a\":1}");
+    let request = forwarded("POST", "/api/v1/config", &headers, b"");
+    assert_eq!(status(request).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn rejects_timestamps_outside_the_window() {
+    for timestamp in [NOW - 61, NOW + 61] {
+        let headers = signed_at(&admin(), "GET", "/api/v1/status", b"", timestamp);
+        assert_eq!(
+            status(subrequest("GET", "/api/v1/status", &headers)).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+    for timestamp in [NOW - 60, NOW + 60] {
+        let headers = signed_at(&admin(), "GET", "/api/v1/status", b"", timestamp);
+        assert_eq!(
+            status(subrequest("GET", "/api/v1/status", &headers)).await,
+            StatusCode::OK
+        );
+    }
+}
+
+#[tokio::test]
+async fn window_follows_the_clock_and_is_configurable() {
+    let clock = Arc::new(ManualClock::new(NOW));
+    let verifier = Verifier::new(admins())
+        .with_clock(clock.clone())
+        .with_max_skew(Duration::from_secs(5));
+    let router = router(Arc::new(verifier));
+
+    let first = signed(&admin(), "GET", "/api/v1/status", b"");
+    assert_eq!(
+        send(&router, subrequest("GET", "/api/v1/status", &first)).await,
+        StatusCode::OK
+    );
+
+    clock.advance(6);
+    let second = signed(&admin(), "GET", "/api/v1/config", b"");
+    assert_eq!(
+        send(&router, subrequest("GET", "/api/v1/config", &second)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn rejects_exact_replay_inside_the_window() {
+    let router = router(Arc::new(verifier()));
+    let headers = signed(&admin(), "DELETE", "/api/v1/holochain/apps/x", b"");
+    let request = || subrequest("DELETE", "/api/v1/holochain/apps/x", &headers);
+
+    assert_eq!(send(&router, request()).await, StatusCode::OK);
+    assert_eq!(send(&router, request()).await, StatusCode::UNAUTHORIZED);
+
+    // The same call signed a second later is a new request.
+    let again = signed_at(&admin(), "DELETE", "/api/v1/holochain/apps/x", b"", NOW + 1);
+    assert_eq!(
+        send(
+            &router,
+            subrequest("DELETE", "/api/v1/holochain/apps/x", &again)
+        )
+        .await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn rejected_requests_do_not_burn_the_signature() {
+    let router = router(Arc::new(verifier().with_body_check(true)));
+    let headers = signed(&admin(), "POST", "/api/v1/config", b"{}");
+
+    let swapped = forwarded("POST", "/api/v1/config", &headers, b"{\"evil\":1}");
+    assert_eq!(send(&router, swapped).await, StatusCode::UNAUTHORIZED);
+    let genuine = forwarded("POST", "/api/v1/config", &headers, b"{}");
+    assert_eq!(send(&router, genuine).await, StatusCode::OK);
+}
@@ -0,0 +1,108 @@
+//! Helpers shared by the server integration tests.
+
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use tower::ServiceExt;
+
+use hp_admin_crypto::{MessageSignatureHeaders, Payload, SignatureHeaders, SigningKey};
+use hp_admin_crypto_server::admins::Admin;
+use hp_admin_crypto_server::clock::ManualClock;
+use hp_admin_crypto_server::verify::{
+    ORIGINAL_HOST, ORIGINAL_METHOD, ORIGINAL_SCHEME, ORIGINAL_URI,
+};
+use hp_admin_crypto_server::{router, AdminKeys, Verifier};
+
+pub fn admin() -> SigningKey {
+    SigningKey::from_bytes(&[42; 32])
+}
+
+/// The host's admins: just [`admin`], as `admin@example.com`.
+pub fn admins() -> AdminKeys {
+    AdminKeys::new(Admin::new("admin@example.com", admin().verifying_key()).unwrap())
+}
+
+pub fn subrequest(method: &str, uri: &str, signed: &SignatureHeaders) -> Request<Body> {
+    forwarded(method, uri, signed, b"")
+}
+
+/// A subrequest that carries the original request body, as nginx sends it
+/// when body forwarding is configured.
+pub fn forwarded(method: &str, uri: &str, signed: &SignatureHeaders, body: &[u8]) -> Request<Body> {
+    let mut request = Request::get("/auth")
+        .header(ORIGINAL_METHOD, method)
+        .header(ORIGINAL_URI, uri);
+    for (name, value) in signed.iter() {
+        request = request.header(name, value);
+    }
+    request.body(Body::from(body.to_vec())).unwrap()
+}
+
+/// An RFC 9421 subrequest for a call to `https://hpos.example` + `uri`,
+/// carrying `body`.
+pub fn message_subrequest(
+    method: &str,
+    uri: &str,
+    signed: &MessageSignatureHeaders,
+    body: &[u8],
+) -> Request<Body> {
+    let mut request = Request::get("/auth")
+        .header(ORIGINAL_METHOD, method)
+        .header(ORIGINAL_SCHEME, "https")
+        .header(ORIGINAL_HOST, "hpos.example")
+        .header(ORIGINAL_URI, uri);
+    for (name, value) in signed.iter() {
+        request = request.header(name, value);
+    }
+    request.body(Body::from(body.to_vec())).unwrap()
+}
+
+pub const NOW: u64 = 1_600_000_000;
+
+pub fn signed(key: &SigningKey, method: &str, uri: &str, body: &[u8]) -> SignatureHeaders {
+    signed_at(key, method, uri, body, NOW)
+}
+
+pub fn signed_at(
+    key: &SigningKey,
+    method: &str,
+    uri: &str,
+    body: &[u8],
+    timestamp: u64,
+) -> SignatureHeaders {
+    let payload = Payload::new(method, uri, body, timestamp).unwrap();
+    SignatureHeaders::sign(key, &payload)
+}
+
+/// Signs a call to `https://hpos.example` + `uri` with RFC 9421 at [`NOW`].
+pub fn signed_message(
+    key: &SigningKey,
+    method: &str,
+    uri: &str,
+    body: &[u8],
+    nonce: Option<&str>,
+) -> MessageSignatureHeaders {
+    let target = format!("https://hpos.example{uri}");
+    MessageSignatureHeaders::sign(key, method, &target, body, NOW, nonce).unwrap()
+}
+
+/// A verifier for [`admins`] whose clock reads [`NOW`].
+pub fn verifier() -> Verifier {
+    Verifier::new(admins()).with_clock(Arc::new(ManualClock::new(NOW)))
+}
+
+pub async fn status(request: Request<Body>) -> StatusCode {
+    status_with(verifier(), request).await
+}
+
+pub async fn status_with(verifier: Verifier, request: Request<Body>) -> StatusCode {
+    send(&router(Arc::new(verifier)), request).await
+}
+
+pub async fn send(router: &Router, request: Request<Body>) -> StatusCode {
+    router.clone().oneshot(request).await.unwrap().status()
+}
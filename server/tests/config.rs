@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+
+use hp_admin_crypto::KdfDescriptor;
+use hp_admin_crypto_server::config::{ConfigError, HposConfig};
+
+const ADMIN_PUBLIC_KEY: &str = "+x0B5rH0tqYvbHXHrFYbJu9876199JjQQdWKZ//n32s=";
+const ALICE_PUBLIC_KEY: &str = "BFfOkq+t8jkajUT22XZxo47P60ybE4jCIpfyUbPHKCo=";
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[test]
+fn loads_every_shipped_version() {
+    for (file, version) in [
+        ("hpos-config-v1.json", "v1"),
+        ("hpos-config-v2.json", "v2"),
+        ("hpos-config-v3.json", "v3"),
+    ] {
+        let config = HposConfig::load(&fixture(file)).unwrap();
+        assert_eq!(config.version(), version);
+        assert_eq!(config.settings().admin.email, "admin@example.com");
+        assert_eq!(
+            hp_admin_crypto::encode_public_key(&config.admin_key().unwrap()),
+            ADMIN_PUBLIC_KEY
+        );
+    }
+}
+
+#[test]
+fn only_the_settings_are_required() {
+    for version in ["v1", "v2", "v3"] {
+        let text = format!(
+            r#"{{"{version}": {{"settings": {{"admin": {{"email": "admin@example.com", "public_key": "{ADMIN_PUBLIC_KEY}"}}}}}}}}"#
+        );
+        let config = HposConfig::parse(Path::new("hpos-config.json"), &text).unwrap();
+        assert_eq!(config.version(), version);
+    }
+}
+
+#[test]
+fn loads_further_admins() {
+    let text = format!(
+        r#"{{"v2": {{"device_bundle": "", "device_derivation_path": "", "registration_code": "",
+            "settings": {{"admin": {{"email": "admin@example.com", "public_key": "{ADMIN_PUBLIC_KEY}"}},
+                         "admins": [{{"id": "alice", "public_key": "{ALICE_PUBLIC_KEY}"}}]}}}}}}"#
+    );
+    let config = HposConfig::parse(Path::new("hpos-config.json"), &text).unwrap();
+    let admins = config.admins().unwrap();
+    let ids: Vec<&str> = admins.iter().map(|admin| admin.id()).collect();
+    assert_eq!(ids, ["admin@example.com", "alice"]);
+
+    // Further admins are checked as strictly as the main one.
+    let text = text.replace(ALICE_PUBLIC_KEY, ADMIN_PUBLIC_KEY);
+    assert!(matches!(
+        HposConfig::parse(Path::new("hpos-config.json"), &text),
+        Err(ConfigError::InvalidAdmins { .. })
+    ));
+}
+
+#[test]
+fn missing_file() {
+    assert!(matches!(
+        HposConfig::load(&fixture("does-not-exist.json")),
+        Err(ConfigError::Read { .. })
+    ));
+}
+
+#[test]
+fn malformed_file() {
+    let path = Path::new("hpos-config.json");
+    for text in [
+        "",
+        "[]",
+        r#"{"v1": {}, "v2": {}}"#,
+        r#"{"v2": {"settings": {"admin": {"email": "a@b"}}}}"#,
+    ] {
+        assert!(
+            matches!(
+                HposConfig::parse(path, text),
+                Err(ConfigError::Malformed { .. })
+            ),
+            "{text}"
+        );
+    }
+}
+
+#[test]
+fn unsupported_version() {
+    let err = HposConfig::parse(Path::new("hpos-config.json"), r#"{"v9": {}}"#).unwrap_err();
+    assert!(matches!(&err, ConfigError::UnsupportedVersion { version, .. } if version == "v9"));
+    assert!(err.to_string().contains("\"v9\""));
+}
+
+#[test]
+fn invalid_admin_key() {
+    let text =
+        r#"{"v1": {"seed": "", "settings": {"admin": {"email": "a@b", "public_key": "AAAA"}}}}"#;
+    assert!(matches!(
+        HposConfig::parse(Path::new("hpos-config.json"), text),
+        Err(ConfigError::InvalidAdminKey { .. })
+    ));
+}
+
+#[test]
+fn checks_the_kdf_descriptor() {
+    let config = |kdf: &str| {
+        HposConfig::parse(
+            Path::new("hpos-config.json"),
+            &format!(
+                r#"{{"v2": {{"settings": {{"admin": {{"email": "a@b", "public_key": "{ADMIN_PUBLIC_KEY}"{kdf}}}}}}}}}"#
+            ),
+        )
+    };
+    assert_eq!(config("").unwrap().kdf(), Ok(KdfDescriptor::LEGACY));
+    assert_eq!(
+        config(r#", "kdf": "hp-admin-crypto/kdf/v1:m=131072,t=3,p=1""#)
+            .unwrap()
+            .kdf(),
+        Ok(KdfDescriptor::RECOMMENDED)
+    );
+    for kdf in [
+        "argon2id",
+        "hp-admin-crypto/kdf/v1:m=1024,t=1,p=1",
+        "hp-admin-crypto/kdf/v2:m=131072,t=3,p=1",
+    ] {
+        let err = config(&format!(r#", "kdf": "{kdf}""#)).unwrap_err();
+        assert!(
+            matches!(err, ConfigError::InvalidKdf { .. }),
+            "{kdf}: {err}"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use axum::http::StatusCode;
+
+use hp_admin_crypto::rfc9421::{MessageSignature, Request as Message, SignatureParams};
+use hp_admin_crypto::{ContentDigest, MessageSignatureHeaders, SignatureHeaders};
+
+use common::*;
+
+/// An RFC 9421 signature over an arbitrary `Content-Digest` value, as
+/// standard tooling might send it.
+fn message_with_digest(content_digest: &str) -> MessageSignatureHeaders {
+    let request = Message::new("PUT", "https://hpos.example/api/v1/config")
+        .unwrap()
+        .with_field("content-digest", content_digest);
+    let params = SignatureParams::new(NOW).with_key(&admin().verifying_key());
+    let signed = MessageSignature::sign(&admin(), &request, params).unwrap();
+    MessageSignatureHeaders {
+        content_digest: content_digest.to_owned(),
+        signature_input: signed.signature_input_header(),
+        signature: signed.signature_header(),
+    }
+}
+
+#[tokio::test]
+async fn accepts_sha256_only() {
+    let sha256 = "sha-256=:RBNvo1WzZ4oRRq0W9+hknpT7T8If536DEMBg9hyq/4o=:";
+    let signed = message_with_digest(sha256);
+    let request = |body: &[u8]| message_subrequest("PUT", "/api/v1/config", &signed, body);
+    let checked = || verifier().with_body_check(true);
+    assert_eq!(status_with(checked(), request(b"{}")).await, StatusCode::OK);
+    assert_eq!(
+        status_with(checked(), request(b"{ }")).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn every_supported_digest_must_match() {
+    // A correct SHA-512 does not excuse a wrong SHA-256.
+    let sha512 = ContentDigest::from(hp_admin_crypto::BodyDigest::of(b"{}"));
+    let mixed = format!("{sha512}, sha-256=:{}:", "A".repeat(43) + "=");
+    let signed = message_with_digest(&mixed);
+    assert_eq!(
+        status_with(
+            verifier().with_body_check(true),
+            message_subrequest("PUT", "/api/v1/config", &signed, b"{}")
+        )
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn unsupported_algorithms_are_bad_requests() {
+    let signed = message_with_digest("md5=:mZFLkyvTelC5g8XnyQrpOw==:");
+    assert_eq!(
+        status(message_subrequest("PUT", "/api/v1/config", &signed, b"")).await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn legacy_signatures_accept_content_digest_alone() {
+    let signed = SignatureHeaders {
+        body_digest: None,
+        ..signed(&admin(), "PUT", "/api/v1/config", b"{}")
+    };
+    assert!(signed.content_digest.is_some());
+    let checked = || verifier().with_body_check(true);
+    assert_eq!(
+        status_with(
+            checked(),
+            forwarded("PUT", "/api/v1/config", &signed, b"{}")
+        )
+        .await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status_with(
+            checked(),
+            forwarded("PUT", "/api/v1/config", &signed, b"{ }")
+        )
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn legacy_digest_headers_must_agree() {
+    let signed = SignatureHeaders {
+        content_digest: Some(ContentDigest::of(b"{ }").to_string()),
+        ..signed(&admin(), "PUT", "/api/v1/config", b"{}")
+    };
+    assert_eq!(
+        status(subrequest("PUT", "/api/v1/config", &signed)).await,
+        StatusCode::BAD_REQUEST
+    );
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+
+use hp_admin_crypto::delegation::{Delegation, DelegationChain, DELEGATION};
+use hp_admin_crypto::SigningKey;
+use hp_admin_crypto_server::router;
+use hp_admin_crypto_server::session::{Sessions, DEFAULT_SESSION_TTL};
+use hp_admin_crypto_server::verify::ORIGINAL_HOST;
+
+use common::*;
+
+fn bot() -> SigningKey {
+    SigningKey::from_bytes(&[8; 32])
+}
+
+/// The admin lets the CI bot read host status for an hour.
+fn status_reader() -> DelegationChain {
+    let delegation = Delegation::new(
+        &bot().verifying_key(),
+        &["GET", "HEAD"],
+        &["/api/v1/status"],
+        NOW + 3600,
+    )
+    .unwrap();
+    DelegationChain::new(vec![delegation.sign(&admin())]).unwrap()
+}
+
+fn delegated(mut request: Request<Body>, chain: &DelegationChain) -> Request<Body> {
+    request
+        .headers_mut()
+        .insert(DELEGATION, chain.to_string().parse().unwrap());
+    request
+}
+
+fn bot_request(method: &str, uri: &str, chain: &DelegationChain) -> Request<Body> {
+    let signed = signed(&bot(), method, uri, b"");
+    delegated(subrequest(method, uri, &signed), chain)
+}
+
+#[tokio::test]
+async fn accepts_delegate_inside_its_scope() {
+    let chain = status_reader();
+    assert_eq!(
+        status(bot_request("GET", "/api/v1/status", &chain)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(bot_request("HEAD", "/api/v1/status/disk?human=1", &chain)).await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn forbids_delegate_outside_its_scope() {
+    let chain = status_reader();
+    for (method, uri) in [
+        ("PUT", "/api/v1/status"),
+        ("GET", "/api/v1/config"),
+        ("GET", "/api/v1/statusx"),
+        ("GET", "/api/v1/status/../config"),
+    ] {
+        assert_eq!(
+            status(bot_request(method, uri, &chain)).await,
+            StatusCode::FORBIDDEN,
+            "{method} {uri}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn rejects_expired_delegation() {
+    let delegation = Delegation::new(
+        &bot().verifying_key(),
+        &["GET"],
+        &["/api/v1/status"],
+        NOW - 1,
+    )
+    .unwrap();
+    let chain = DelegationChain::new(vec![delegation.sign(&admin())]).unwrap();
+    assert_eq!(
+        status(bot_request("GET", "/api/v1/status", &chain)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn requires_the_chain_to_start_at_the_admin_key() {
+    let delegation = Delegation::new(&bot().verifying_key(), &["GET"], &["/"], NOW + 3600).unwrap();
+    let self_signed = DelegationChain::new(vec![delegation.sign(&bot())]).unwrap();
+    assert_eq!(
+        status(bot_request("GET", "/api/v1/status", &self_signed)).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // Nor does a delegate's signature pass without its certificate.
+    let signed = signed(&bot(), "GET", "/api/v1/status", b"");
+    assert_eq!(
+        status(subrequest("GET", "/api/v1/status", &signed)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn sub_delegation_only_narrows() {
+    let deploy = SigningKey::from_bytes(&[9; 32]);
+    let wider = Delegation::new(
+        &deploy.verifying_key(),
+        &["GET", "PUT"],
+        &["/api/v1"],
+        NOW + 3600,
+    )
+    .unwrap();
+    let chain = status_reader().push(wider.sign(&bot())).unwrap();
+
+    let request = |method: &str, uri: &str| {
+        let signed = signed(&deploy, method, uri, b"");
+        delegated(subrequest(method, uri, &signed), &chain)
+    };
+    assert_eq!(
+        status(request("GET", "/api/v1/status")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(request("PUT", "/api/v1/status")).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status(request("GET", "/api/v1/config")).await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn message_signatures_name_the_delegate_key() {
+    let chain = status_reader();
+    let signed = signed_message(&bot(), "GET", "/api/v1/status", b"", None);
+    assert_eq!(
+        status(delegated(
+            message_subrequest("GET", "/api/v1/status", &signed, b""),
+            &chain
+        ))
+        .await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(delegated(
+            message_subrequest("PUT", "/api/v1/status", &signed, b""),
+            &chain
+        ))
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn delegates_cannot_open_sessions() {
+    let verifier = verifier().with_sessions(Sessions::new(
+        SigningKey::from_bytes(&[3; 32]),
+        DEFAULT_SESSION_TTL,
+    ));
+    let router = router(Arc::new(verifier));
+
+    let everything =
+        Delegation::new(&bot().verifying_key(), &["POST"], &["/"], NOW + 3600).unwrap();
+    let chain = DelegationChain::new(vec![everything.sign(&admin())]).unwrap();
+    let mut login = bot_request("POST", "/api/v1/hp-admin-login", &chain);
+    *login.uri_mut() = "/login".parse().unwrap();
+    *login.method_mut() = "POST".parse().unwrap();
+    login
+        .headers_mut()
+        .insert(ORIGINAL_HOST, "hpos.example".parse().unwrap());
+    assert_eq!(send(&router, login).await, StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use tower::ServiceExt;
+
+use hp_admin_crypto::SigningKey;
+use hp_admin_crypto_server::{metrics, router};
+
+use common::*;
+
+#[tokio::test]
+async fn counts_decisions_by_reason() {
+    let verifier = verifier();
+    let metrics = metrics::router(verifier.metrics().clone());
+    let auth = router(Arc::new(verifier));
+
+    let signed = signed(&admin(), "GET", "/api/v1/status", b"");
+    let call = || subrequest("GET", "/api/v1/status", &signed);
+    assert_eq!(send(&auth, call()).await, StatusCode::OK);
+    assert_eq!(send(&auth, call()).await, StatusCode::UNAUTHORIZED);
+    let forged = signed_message(
+        &SigningKey::from_bytes(&[13; 32]),
+        "GET",
+        "/api/v1/status",
+        b"",
+        None,
+    );
+    assert_eq!(
+        send(
+            &auth,
+            message_subrequest("GET", "/api/v1/status", &forged, b"")
+        )
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    let response = metrics
+        .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), 1 << 16)
+        .await
+        .unwrap();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    for line in [
+        "hp_admin_accepted_total{action=\"auth\"} 1",
+        "hp_admin_rejected_total{action=\"auth\",reason=\"replay\"} 1",
+        "hp_admin_rejected_total{action=\"auth\",reason=\"unknown_key\"} 1",
+        "hp_admin_verification_seconds_count 3",
+    ] {
+        assert!(
+            text.lines().any(|l| l == line),
+            "{line} missing from\n{text}"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use tower::ServiceExt;
+
+use hp_admin_crypto::{Payload, SignatureHeaders};
+use hp_admin_crypto_server::clock::ManualClock;
+use hp_admin_crypto_server::nonce::{FileNonceStore, NonceStore, MAX_OUTSTANDING_PER_CLIENT};
+use hp_admin_crypto_server::verify::CLIENT_ADDR;
+use hp_admin_crypto_server::{router, Verifier};
+
+use common::*;
+
+async fn issue(router: &Router) -> String {
+    let response = router
+        .clone()
+        .oneshot(Request::post("/nonce").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), 1024)
+        .await
+        .unwrap();
+    let issued: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(issued["expires"], NOW + 60);
+    issued["nonce"].as_str().unwrap().to_owned()
+}
+
+fn signed_with_nonce(uri: &str, timestamp: u64, nonce: &str) -> SignatureHeaders {
+    let payload = Payload::new("GET", uri, b"", timestamp)
+        .unwrap()
+        .with_nonce(nonce)
+        .unwrap();
+    SignatureHeaders::sign(&admin(), &payload)
+}
+
+fn challenge_router() -> Router {
+    router(Arc::new(verifier().with_nonce_required(true)))
+}
+
+#[tokio::test]
+async fn nonce_is_single_use() {
+    let router = challenge_router();
+    let nonce = issue(&router).await;
+    let headers = signed_with_nonce("/api/v1/status", NOW, &nonce);
+
+    assert_eq!(
+        send(&router, subrequest("GET", "/api/v1/status", &headers)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send(&router, subrequest("GET", "/api/v1/status", &headers)).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // Re-signing another request with the spent nonce does not help.
+    let reused = signed_with_nonce("/api/v1/config", NOW, &nonce);
+    assert_eq!(
+        send(&router, subrequest("GET", "/api/v1/config", &reused)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn challenge_mode_requires_a_nonce() {
+    let headers = signed(&admin(), "GET", "/api/v1/status", b"");
+    assert_eq!(
+        send(
+            &challenge_router(),
+            subrequest("GET", "/api/v1/status", &headers)
+        )
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn nonces_must_be_issued_by_us() {
+    let headers = signed_with_nonce("/api/v1/status", NOW, "made-up-by-the-client");
+    assert_eq!(
+        send(
+            &challenge_router(),
+            subrequest("GET", "/api/v1/status", &headers)
+        )
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn client_clock_is_not_trusted_with_a_nonce() {
+    let router = challenge_router();
+    let nonce = issue(&router).await;
+    let headers = signed_with_nonce("/api/v1/status", NOW - 86_400, &nonce);
+    assert_eq!(
+        send(&router, subrequest("GET", "/api/v1/status", &headers)).await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn nonces_expire() {
+    let clock = Arc::new(ManualClock::new(NOW));
+    let verifier = Verifier::new(admins())
+        .with_clock(clock.clone())
+        .with_nonce_required(true);
+    let router = router(Arc::new(verifier));
+    let nonce = issue(&router).await;
+
+    clock.advance(61);
+    let headers = signed_with_nonce("/api/v1/status", NOW + 61, &nonce);
+    assert_eq!(
+        send(&router, subrequest("GET", "/api/v1/status", &headers)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn file_store_survives_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nonces.json");
+    let server = |store: FileNonceStore| {
+        let store: Arc<dyn NonceStore> = Arc::new(store);
+        router(Arc::new(
+            verifier()
+                .with_nonce_store(store, Duration::from_secs(60))
+                .with_nonce_required(true),
+        ))
+    };
+
+    let nonce = issue(&server(FileNonceStore::open(&path, 16).unwrap())).await;
+
+    let restarted = server(FileNonceStore::open(&path, 16).unwrap());
+    let headers = signed_with_nonce("/api/v1/status", NOW, &nonce);
+    assert_eq!(
+        send(&restarted, subrequest("GET", "/api/v1/status", &headers)).await,
+        StatusCode::OK
+    );
+
+    let restarted = server(FileNonceStore::open(&path, 16).unwrap());
+    assert_eq!(
+        send(&restarted, subrequest("GET", "/api/v1/status", &headers)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn limits_nonces_per_client() {
+    let router = challenge_router();
+    let from = |addr: &str| {
+        Request::post("/nonce")
+            .header(CLIENT_ADDR, addr)
+            .body(Body::empty())
+            .unwrap()
+    };
+    for _ in 0..MAX_OUTSTANDING_PER_CLIENT {
+        assert_eq!(send(&router, from("192.0.2.1")).await, StatusCode::OK);
+    }
+    assert_eq!(
+        send(&router, from("192.0.2.1")).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(send(&router, from("192.0.2.2")).await, StatusCode::OK);
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use tower::ServiceExt;
+
+use hp_admin_crypto::delegation::{Delegation, DelegationChain, DELEGATION};
+use hp_admin_crypto::SigningKey;
+use hp_admin_crypto_server::policy::PolicyFile;
+use hp_admin_crypto_server::router;
+use hp_admin_crypto_server::session::{Sessions, DEFAULT_SESSION_TTL};
+use hp_admin_crypto_server::verify::{ORIGINAL_HOST, ORIGINAL_METHOD, ORIGINAL_URI};
+
+use common::*;
+
+const POLICY: &str = r#"
+[[rule]]
+via = ["signature", "session"]
+allow = ["* /api/v1/**"]
+
+[[rule]]
+via = "delegation"
+allow = ["GET /api/v1/status"]
+"#;
+
+fn policy_file(dir: &Path, text: &str) -> Arc<PolicyFile> {
+    let path = dir.join("policy.toml");
+    std::fs::write(&path, text).unwrap();
+    Arc::new(PolicyFile::open(&path).unwrap())
+}
+
+/// A subrequest from a bot that the admin let call anything under
+/// `/api/v1`, so that only the policy narrows it.
+fn delegated(method: &str, uri: &str) -> Request<Body> {
+    let bot = SigningKey::from_bytes(&[8; 32]);
+    let delegation = Delegation::new(
+        &bot.verifying_key(),
+        &["GET", "PUT"],
+        &["/api/v1"],
+        NOW + 3600,
+    )
+    .unwrap();
+    let chain = DelegationChain::new(vec![delegation.sign(&admin())]).unwrap();
+    let mut request = subrequest(method, uri, &signed(&bot, method, uri, b""));
+    request
+        .headers_mut()
+        .insert(DELEGATION, chain.to_string().parse().unwrap());
+    request
+}
+
+#[tokio::test]
+async fn limits_calls_after_verifying_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let verifier = || verifier().with_policy(policy_file(dir.path(), POLICY));
+    let status = |request| status_with(verifier(), request);
+
+    let admin_call = |method, uri| subrequest(method, uri, &signed(&admin(), method, uri, b""));
+    assert_eq!(
+        status(admin_call("PUT", "/api/v1/config")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(admin_call("GET", "/api/v2/status")).await,
+        StatusCode::FORBIDDEN
+    );
+
+    assert_eq!(
+        status(delegated("GET", "/api/v1/status")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(delegated("PUT", "/api/v1/status")).await,
+        StatusCode::FORBIDDEN
+    );
+
+    // The policy is only consulted once the call has authenticated.
+    let forged = subrequest(
+        "GET",
+        "/api/v1/status",
+        &signed(
+            &SigningKey::from_bytes(&[9; 32]),
+            "GET",
+            "/api/v1/status",
+            b"",
+        ),
+    );
+    assert_eq!(status(forged).await, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn checks_session_calls_against_the_forwarded_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let text = "[[rule]]\nvia = \"session\"\nallow = [\"GET /api/v1/status\"]\n";
+    let sessions = Sessions::new(SigningKey::from_bytes(&[3; 32]), DEFAULT_SESSION_TTL);
+    let token = sessions
+        .issue("admin@example.com", "hpos.example", NOW)
+        .unwrap()
+        .token;
+    let router = router(Arc::new(
+        verifier()
+            .with_sessions(sessions)
+            .with_policy(policy_file(dir.path(), text)),
+    ));
+
+    let call = |method: &str| {
+        Request::get("/auth")
+            .header(ORIGINAL_METHOD, method)
+            .header(ORIGINAL_URI, "/api/v1/status")
+            .header(ORIGINAL_HOST, "hpos.example")
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+    assert_eq!(send(&router, call("GET")).await, StatusCode::OK);
+    assert_eq!(send(&router, call("DELETE")).await, StatusCode::FORBIDDEN);
+
+    let mut unknown = call("GET");
+    unknown.headers_mut().remove(ORIGINAL_URI);
+    let response = router.clone().oneshot(unknown).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn reloads_without_a_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let policy = policy_file(dir.path(), POLICY);
+    let router = router(Arc::new(verifier().with_policy(policy.clone())));
+
+    assert_eq!(
+        send(&router, delegated("PUT", "/api/v1/config")).await,
+        StatusCode::FORBIDDEN
+    );
+
+    let widened = POLICY.replace("GET /api/v1/status", "* /api/v1/**");
+    std::fs::write(policy.path(), widened).unwrap();
+    policy.reload().unwrap();
+    assert_eq!(
+        send(&router, delegated("PUT", "/api/v1/apps")).await,
+        StatusCode::OK
+    );
+
+    // A broken policy leaves the previous one in force.
+    std::fs::write(policy.path(), "[[rule]]\n").unwrap();
+    assert!(policy.reload().is_err());
+    assert_eq!(
+        send(&router, delegated("GET", "/api/v1/status")).await,
+        StatusCode::OK
+    );
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::header::RETRY_AFTER;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use tower::ServiceExt;
+
+use hp_admin_crypto::SigningKey;
+use hp_admin_crypto_server::ratelimit::RateLimits;
+use hp_admin_crypto_server::router;
+use hp_admin_crypto_server::verify::CLIENT_ADDR;
+
+use common::*;
+
+/// A status call from `client`, signed by `key`; `n` keeps the calls apart
+/// so that none is a replay.
+fn call(client: &str, key: &SigningKey, n: u32) -> Request<Body> {
+    let uri = format!("/api/v1/status?n={n}");
+    let mut request = subrequest("GET", &uri, &signed(key, "GET", &uri, b""));
+    request
+        .headers_mut()
+        .insert(CLIENT_ADDR, client.parse().unwrap());
+    request
+}
+
+#[tokio::test]
+async fn locks_out_a_client_that_keeps_failing() {
+    let router: Router = router(Arc::new(verifier().with_rate_limits(RateLimits {
+        client_burst: 3,
+        lockout: 60,
+        ..RateLimits::default()
+    })));
+    let guess = SigningKey::from_bytes(&[13; 32]);
+
+    for n in 0..3 {
+        assert_eq!(
+            send(&router, call("192.0.2.1", &guess, n)).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+    // Locked out even with the right key, while other clients are not.
+    let response = router
+        .clone()
+        .oneshot(call("192.0.2.1", &admin(), 3))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[RETRY_AFTER], "60");
+    assert_eq!(
+        send(&router, call("192.0.2.2", &admin(), 4)).await,
+        StatusCode::OK
+    );
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use tower::ServiceExt;
+
+use hp_admin_crypto::SigningKey;
+use hp_admin_crypto_server::clock::ManualClock;
+use hp_admin_crypto_server::reload::{AdminSource, ReloadError};
+use hp_admin_crypto_server::rotation::RotationRecord;
+use hp_admin_crypto_server::session::{Sessions, DEFAULT_SESSION_TTL};
+use hp_admin_crypto_server::verify::{ORIGINAL_HOST, ORIGINAL_METHOD, ORIGINAL_URI};
+use hp_admin_crypto_server::{router, Verifier};
+
+use common::*;
+
+fn write_config(path: &Path, key: &SigningKey) {
+    write_config_with(path, key, &[]);
+}
+
+/// Writes a config whose admin has `key`, plus the further admins given.
+fn write_config_with(path: &Path, key: &SigningKey, further: &[(&str, &SigningKey)]) {
+    let public_key = |key: &SigningKey| hp_admin_crypto::encode_public_key(&key.verifying_key());
+    let further: Vec<String> = further
+        .iter()
+        .map(|(id, key)| format!(r#"{{"id": "{id}", "public_key": "{}"}}"#, public_key(key)))
+        .collect();
+    std::fs::write(
+        path,
+        format!(
+            r#"{{"v1": {{"seed": "", "settings": {{"admin": {{"email": "admin@example.com", "public_key": "{}"}}, "admins": [{}]}}}}}}"#,
+            public_key(key),
+            further.join(", ")
+        ),
+    )
+    .unwrap();
+}
+
+#[tokio::test]
+async fn swaps_in_a_new_admin_key_only_if_it_loads() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("hpos-config.json");
+    write_config(&config, &admin());
+    let record = RotationRecord::open(&dir.path().join("rotations.json")).unwrap();
+    let source = AdminSource::new(&config, None, Arc::new(record));
+    let verifier = Arc::new(
+        Verifier::new(source.load().unwrap().admins).with_clock(Arc::new(ManualClock::new(NOW))),
+    );
+    let router = router(verifier.clone());
+    let call = |key: &SigningKey, n: u32| {
+        let uri = format!("/api/v1/status?n={n}");
+        subrequest("GET", &uri, &signed(key, "GET", &uri, b""))
+    };
+
+    let new_key = SigningKey::from_bytes(&[44; 32]);
+    write_config(&config, &new_key);
+    let loaded = verifier.reload_admins(&source).unwrap();
+    assert_eq!(loaded.version, "v1");
+    assert_eq!(
+        send(&router, call(&admin(), 0)).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(send(&router, call(&new_key, 1)).await, StatusCode::OK);
+
+    std::fs::write(&config, r#"{"v1": {"seed": ""#).unwrap();
+    assert!(matches!(
+        verifier.reload_admins(&source),
+        Err(ReloadError::Config(_))
+    ));
+    assert_eq!(send(&router, call(&new_key, 2)).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn sessions_end_with_their_admin() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("hpos-config.json");
+    let alice = SigningKey::from_bytes(&[45; 32]);
+    write_config_with(&config, &admin(), &[("alice", &alice)]);
+    let record = RotationRecord::open(&dir.path().join("rotations.json")).unwrap();
+    let source = AdminSource::new(&config, None, Arc::new(record));
+    let verifier = Arc::new(
+        Verifier::new(source.load().unwrap().admins)
+            .with_clock(Arc::new(ManualClock::new(NOW)))
+            .with_sessions(Sessions::new(
+                SigningKey::from_bytes(&[3; 32]),
+                DEFAULT_SESSION_TTL,
+            )),
+    );
+    let router = router(verifier.clone());
+
+    let login = "/api/v1/hp-admin-login";
+    let mut request = subrequest("POST", login, &signed(&alice, "POST", login, b""));
+    *request.uri_mut() = "/login".parse().unwrap();
+    *request.method_mut() = "POST".parse().unwrap();
+    request
+        .headers_mut()
+        .insert(ORIGINAL_HOST, "hpos.example".parse().unwrap());
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), 4096)
+        .await
+        .unwrap();
+    let issued: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let token = issued["token"].as_str().unwrap().to_owned();
+    let call = || {
+        Request::get("/auth")
+            .header(ORIGINAL_METHOD, "GET")
+            .header(ORIGINAL_URI, "/api/v1/status")
+            .header(ORIGINAL_HOST, "hpos.example")
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+    assert_eq!(send(&router, call()).await, StatusCode::OK);
+
+    write_config(&config, &admin());
+    verifier.reload_admins(&source).unwrap();
+    assert_eq!(send(&router, call()).await, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::http::{HeaderMap, StatusCode};
+
+use hp_admin_crypto::rfc9421::{MessageSignature, Request as Message, SignatureParams};
+use hp_admin_crypto::{ContentDigest, MessageSignatureHeaders, SigningKey};
+use hp_admin_crypto_server::router;
+use hp_admin_crypto_server::verify::ORIGINAL_HOST;
+
+use common::*;
+
+#[tokio::test]
+async fn accepts_admin_message_signature() {
+    let signed = signed_message(&admin(), "POST", "/api/v1/config?x=1", b"{}", None);
+    assert_eq!(
+        status(message_subrequest(
+            "POST",
+            "/api/v1/config?x=1",
+            &signed,
+            b""
+        ))
+        .await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn rejects_message_signed_for_other_request() {
+    let signed = signed_message(&admin(), "POST", "/api/v1/config", b"{}", None);
+    assert_eq!(
+        status(message_subrequest("PUT", "/api/v1/config", &signed, b"")).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status(message_subrequest(
+            "POST",
+            "/api/v1/config?x=1",
+            &signed,
+            b""
+        ))
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    let mut other_host = message_subrequest("POST", "/api/v1/config", &signed, b"");
+    other_host
+        .headers_mut()
+        .insert(ORIGINAL_HOST, "evil.example".parse().unwrap());
+    assert_eq!(status(other_host).await, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn rejects_other_key_id() {
+    let other = SigningKey::from_bytes(&[1; 32]);
+    let signed = signed_message(&other, "GET", "/api/v1/status", b"", None);
+    assert_eq!(
+        status(message_subrequest("GET", "/api/v1/status", &signed, b"")).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn requires_the_hp_admin_components() {
+    // Validly signed by the admin key, but without covering content-digest.
+    let target = "https://hpos.example/api/v1/config";
+    let params = SignatureParams::covering(&["@method", "@target-uri"], NOW)
+        .with_key(&admin().verifying_key());
+    let signed =
+        MessageSignature::sign(&admin(), &Message::new("PUT", target).unwrap(), params).unwrap();
+    let headers = MessageSignatureHeaders {
+        content_digest: ContentDigest::of(b"{}").to_string(),
+        signature_input: signed.signature_input_header(),
+        signature: signed.signature_header(),
+    };
+    assert_eq!(
+        status(message_subrequest("PUT", "/api/v1/config", &headers, b"")).await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn body_check_uses_the_content_digest() {
+    let signed = signed_message(&admin(), "PUT", "/api/v1/config", b"{}", None);
+    let checked = || verifier().with_body_check(true);
+    assert_eq!(
+        status_with(
+            checked(),
+            message_subrequest("PUT", "/api/v1/config", &signed, b"{}")
+        )
+        .await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status_with(
+            checked(),
+            message_subrequest("PUT", "/api/v1/config", &signed, b"{ }")
+        )
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // The digest header is covered, so it cannot be swapped to match.
+    let swapped = MessageSignatureHeaders {
+        content_digest: ContentDigest::of(b"{ }").to_string(),
+        ..signed
+    };
+    assert_eq!(
+        status_with(
+            checked(),
+            message_subrequest("PUT", "/api/v1/config", &swapped, b"{ }")
+        )
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn rejects_replayed_message() {
+    let router = router(Arc::new(verifier()));
+    let signed = signed_message(&admin(), "GET", "/api/v1/status", b"", None);
+    let request = || message_subrequest("GET", "/api/v1/status", &signed, b"");
+    assert_eq!(send(&router, request()).await, StatusCode::OK);
+    assert_eq!(send(&router, request()).await, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn signs_challenge_nonces() {
+    let verifier = verifier().with_nonce_required(true);
+    let nonce = verifier.issue_nonce(&HeaderMap::new()).unwrap().nonce;
+    let router = router(Arc::new(verifier));
+
+    let unchallenged = signed_message(&admin(), "GET", "/api/v1/status", b"", None);
+    assert_eq!(
+        send(
+            &router,
+            message_subrequest("GET", "/api/v1/status", &unchallenged, b"")
+        )
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    let challenged = signed_message(&admin(), "GET", "/api/v1/status", b"", Some(&nonce));
+    let request = || message_subrequest("GET", "/api/v1/status", &challenged, b"");
+    assert_eq!(send(&router, request()).await, StatusCode::OK);
+    assert_eq!(send(&router, request()).await, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn legacy_headers_can_be_refused() {
+    let legacy = signed(&admin(), "GET", "/api/v1/status", b"");
+    let message = signed_message(&admin(), "GET", "/api/v1/status", b"", None);
+    let strict = || verifier().with_rfc9421_required(true);
+
+    assert_eq!(
+        status(subrequest("GET", "/api/v1/status", &legacy)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status_with(strict(), subrequest("GET", "/api/v1/status", &legacy)).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status_with(
+            strict(),
+            message_subrequest("GET", "/api/v1/status", &message, b"")
+        )
+        .await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn message_subrequest_needs_the_original_host() {
+    let signed = signed_message(&admin(), "GET", "/api/v1/status", b"", None);
+    let mut request = message_subrequest("GET", "/api/v1/status", &signed, b"");
+    request.headers_mut().remove(ORIGINAL_HOST);
+    assert_eq!(status(request).await, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::Router;
+use tower::ServiceExt;
+
+use hp_admin_crypto::{KeyRotation, SigningKey};
+use hp_admin_crypto_server::admins::ADMIN_ID;
+use hp_admin_crypto_server::clock::ManualClock;
+use hp_admin_crypto_server::rotation::RotationRecord;
+use hp_admin_crypto_server::{router, Verifier};
+
+use common::*;
+
+const ROTATE: &str = "/api/v1/hp-admin-rotate";
+const GRACE: u64 = 3600;
+
+fn new_key() -> SigningKey {
+    SigningKey::from_bytes(&[43; 32])
+}
+
+/// `old`'s endorsement of `new`, as the client sends it.
+fn endorsement(old: &SigningKey, new: &SigningKey) -> String {
+    KeyRotation::new(&old.verifying_key(), &new.verifying_key(), NOW)
+        .unwrap()
+        .sign(old)
+        .unwrap()
+        .to_string()
+}
+
+fn rotating_router(record: &Path, clock: Arc<ManualClock>) -> Router {
+    let mut admins = admins();
+    let record = Arc::new(RotationRecord::open(record).unwrap());
+    assert!(record.apply(&mut admins).is_empty());
+    let verifier = Verifier::new(admins)
+        .with_clock(clock)
+        .with_rotation(record, Duration::from_secs(GRACE));
+    router(Arc::new(verifier))
+}
+
+/// Sends a rotation signed by `key` whose body is `body`.
+async fn rotate(router: &Router, key: &SigningKey, body: &str) -> StatusCode {
+    let signed = signed(key, "POST", ROTATE, body.as_bytes());
+    let mut request = forwarded("POST", ROTATE, &signed, body.as_bytes());
+    *request.uri_mut() = "/rotate".parse().unwrap();
+    *request.method_mut() = "POST".parse().unwrap();
+    send(router, request).await
+}
+
+/// Signs a status call with `key` at `timestamp` and returns the status and
+/// the admin it was attributed to.
+async fn call(router: &Router, key: &SigningKey, timestamp: u64) -> (StatusCode, Option<String>) {
+    let uri = format!("/api/v1/status?key={}", key.verifying_key().as_bytes()[0]);
+    let signed = signed_at(key, "GET", &uri, b"", timestamp);
+    let response = router
+        .clone()
+        .oneshot(subrequest("GET", &uri, &signed))
+        .await
+        .unwrap();
+    let id = response
+        .headers()
+        .get(ADMIN_ID)
+        .map(|id| id.to_str().unwrap().to_owned());
+    (response.status(), id)
+}
+
+#[tokio::test]
+async fn accepts_both_keys_until_the_old_one_retires() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = Arc::new(ManualClock::new(NOW));
+    let router = rotating_router(&dir.path().join("rotations.json"), clock.clone());
+
+    assert_eq!(
+        call(&router, &new_key(), NOW).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        rotate(&router, &admin(), &endorsement(&admin(), &new_key())).await,
+        StatusCode::OK
+    );
+
+    let admin_id = Some("admin@example.com".to_owned());
+    assert_eq!(
+        call(&router, &new_key(), NOW).await,
+        (StatusCode::OK, admin_id.clone())
+    );
+    assert_eq!(
+        call(&router, &admin(), NOW).await,
+        (StatusCode::OK, admin_id.clone())
+    );
+
+    clock.advance(GRACE);
+    assert_eq!(
+        call(&router, &new_key(), NOW + GRACE).await,
+        (StatusCode::OK, admin_id)
+    );
+    assert_eq!(
+        call(&router, &admin(), NOW + GRACE).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn refuses_endorsements_it_cannot_trust() {
+    let dir = tempfile::tempdir().unwrap();
+    let rotating = rotating_router(
+        &dir.path().join("rotations.json"),
+        Arc::new(ManualClock::new(NOW)),
+    );
+    let mallory = SigningKey::from_bytes(&[13; 32]);
+
+    assert_eq!(
+        rotate(&rotating, &admin(), "not an endorsement").await,
+        StatusCode::BAD_REQUEST
+    );
+    // Someone else's key endorsing theirs, sent by the admin.
+    assert_eq!(
+        rotate(&rotating, &admin(), &endorsement(&mallory, &new_key())).await,
+        StatusCode::FORBIDDEN
+    );
+    // The admin's endorsement, replayed by someone else.
+    assert_eq!(
+        rotate(&rotating, &mallory, &endorsement(&admin(), &mallory)).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // The endorsement must be the body that was signed.
+    let body = endorsement(&admin(), &new_key());
+    let signed = signed(&admin(), "POST", ROTATE, b"");
+    let mut request = forwarded("POST", ROTATE, &signed, body.as_bytes());
+    *request.uri_mut() = "/rotate".parse().unwrap();
+    *request.method_mut() = "POST".parse().unwrap();
+    assert_eq!(send(&rotating, request).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        call(&rotating, &new_key(), NOW).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // Without a record there is nowhere to keep rotations.
+    let disabled = router(Arc::new(verifier()));
+    assert_eq!(
+        rotate(&disabled, &admin(), &endorsement(&admin(), &new_key())).await,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn rotations_survive_a_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rotations.json");
+    let clock = Arc::new(ManualClock::new(NOW));
+    let before = rotating_router(&path, clock.clone());
+    assert_eq!(
+        rotate(&before, &admin(), &endorsement(&admin(), &new_key())).await,
+        StatusCode::OK
+    );
+
+    let after = rotating_router(&path, clock.clone());
+    assert_eq!(call(&after, &new_key(), NOW).await.0, StatusCode::OK);
+    assert_eq!(call(&after, &admin(), NOW).await.0, StatusCode::OK);
+    clock.advance(GRACE);
+    assert_eq!(
+        call(&after, &admin(), NOW + GRACE).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+}
@@ -0,0 +1,302 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use tower::ServiceExt;
+
+use hp_admin_crypto::SigningKey;
+use hp_admin_crypto_server::clock::ManualClock;
+use hp_admin_crypto_server::revocation::FileRevocationStore;
+use hp_admin_crypto_server::session::{Sessions, DEFAULT_SESSION_TTL};
+use hp_admin_crypto_server::verify::{ORIGINAL_HOST, ORIGINAL_METHOD, ORIGINAL_URI};
+use hp_admin_crypto_server::{router, Verifier};
+
+use common::*;
+
+const LOGIN: &str = "/api/v1/hp-admin-login";
+
+fn sessions() -> Sessions {
+    Sessions::new(SigningKey::from_bytes(&[3; 32]), DEFAULT_SESSION_TTL)
+}
+
+fn session_router(verifier: Verifier) -> Router {
+    router(Arc::new(verifier.with_sessions(sessions())))
+}
+
+fn session_router_with(sessions: Sessions) -> Router {
+    router(Arc::new(verifier().with_sessions(sessions)))
+}
+
+/// Sends `POST` to `path` on the verification server, as nginx proxies it
+/// from `hpos.example`, returning the response status and JSON body.
+async fn post_json(
+    router: &Router,
+    path: &str,
+    mut request: Request<Body>,
+) -> (StatusCode, Option<serde_json::Value>) {
+    *request.uri_mut() = path.parse().unwrap();
+    *request.method_mut() = "POST".parse().unwrap();
+    request
+        .headers_mut()
+        .insert(ORIGINAL_HOST, "hpos.example".parse().unwrap());
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), 4096)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).ok())
+}
+
+async fn post(router: &Router, path: &str, request: Request<Body>) -> StatusCode {
+    post_json(router, path, request).await.0
+}
+
+/// Logs in with `request`, returning the status and the token, if one was
+/// issued.
+async fn login(router: &Router, request: Request<Body>) -> (StatusCode, Option<String>) {
+    let (status, issued) = post_json(router, "/login", request).await;
+    let token = issued.map(|issued| issued["token"].as_str().unwrap().to_owned());
+    (status, token)
+}
+
+async fn signed_login(router: &Router) -> String {
+    signed_login_at(router, NOW).await
+}
+
+/// Logs in with a signature made at `timestamp`, for a second login that is
+/// not a replay of the first.
+async fn signed_login_at(router: &Router, timestamp: u64) -> String {
+    let signed = signed_at(&admin(), "POST", LOGIN, b"", timestamp);
+    let (status, issued) = post_json(router, "/login", subrequest("POST", LOGIN, &signed)).await;
+    assert_eq!(status, StatusCode::OK);
+    let issued = issued.unwrap();
+    assert_eq!(issued["expires"], NOW + DEFAULT_SESSION_TTL.as_secs());
+    issued["token"].as_str().unwrap().to_owned()
+}
+
+fn with_token(host: Option<&str>, token: &str) -> Request<Body> {
+    let mut request = Request::get("/auth")
+        .header(ORIGINAL_METHOD, "PUT")
+        .header(ORIGINAL_URI, "/api/v1/config")
+        .header(AUTHORIZATION, format!("Bearer {token}"));
+    if let Some(host) = host {
+        request = request.header(ORIGINAL_HOST, host);
+    }
+    request.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn token_stands_in_for_a_signature_on_its_host() {
+    let router = session_router(verifier());
+    let token = signed_login(&router).await;
+
+    assert_eq!(
+        send(&router, with_token(Some("hpos.example"), &token)).await,
+        StatusCode::OK
+    );
+    // Tokens are not single-use.
+    assert_eq!(
+        send(&router, with_token(Some("hpos.example"), &token)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send(&router, with_token(Some("other.example"), &token)).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        send(&router, with_token(None, &token)).await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn signatures_are_still_accepted() {
+    let router = session_router(verifier());
+    let signed = signed(&admin(), "GET", "/api/v1/status", b"");
+    assert_eq!(
+        send(&router, subrequest("GET", "/api/v1/status", &signed)).await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn login_requires_an_admin_signature() {
+    let router = session_router(verifier());
+
+    let other = signed(&SigningKey::from_bytes(&[1; 32]), "POST", LOGIN, b"");
+    let (status, token) = login(&router, subrequest("POST", LOGIN, &other)).await;
+    assert_eq!((status, token), (StatusCode::UNAUTHORIZED, None));
+
+    // A signature for some other call does not log in.
+    let elsewhere = signed(&admin(), "POST", "/api/v1/config", b"");
+    let (status, _) = login(&router, subrequest("POST", LOGIN, &elsewhere)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Nor does replaying a login.
+    let signed = signed(&admin(), "POST", LOGIN, b"");
+    let (status, _) = login(&router, subrequest("POST", LOGIN, &signed)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = login(&router, subrequest("POST", LOGIN, &signed)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn login_accepts_message_signatures() {
+    let router = session_router(verifier());
+    let signed = signed_message(&admin(), "POST", LOGIN, b"", None);
+    let (status, token) = login(&router, message_subrequest("POST", LOGIN, &signed, b"")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        send(&router, with_token(Some("hpos.example"), &token.unwrap())).await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn tokens_expire() {
+    let clock = Arc::new(ManualClock::new(NOW));
+    let router = session_router(verifier().with_clock(clock.clone()));
+    let token = signed_login(&router).await;
+
+    clock.advance(DEFAULT_SESSION_TTL.as_secs() - 1);
+    assert_eq!(
+        send(&router, with_token(Some("hpos.example"), &token)).await,
+        StatusCode::OK
+    );
+    clock.advance(1);
+    assert_eq!(
+        send(&router, with_token(Some("hpos.example"), &token)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn tokens_from_another_session_key_are_refused() {
+    let token = signed_login(&session_router(verifier())).await;
+    let restarted = router(Arc::new(verifier().with_sessions(Sessions::new(
+        SigningKey::from_bytes(&[4; 32]),
+        DEFAULT_SESSION_TTL,
+    ))));
+    assert_eq!(
+        send(&restarted, with_token(Some("hpos.example"), &token)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn login_is_not_found_without_sessions() {
+    let router = router(Arc::new(verifier()));
+    let signed = signed(&admin(), "POST", LOGIN, b"");
+    let (status, _) = login(&router, subrequest("POST", LOGIN, &signed)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        send(&router, with_token(Some("hpos.example"), "v4.public.AAAA")).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn logout_revokes_only_that_token() {
+    let router = session_router(verifier());
+    let token = signed_login(&router).await;
+    let other = signed_login_at(&router, NOW - 1).await;
+
+    assert_eq!(
+        post(&router, "/logout", with_token(None, &token)).await,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        send(&router, with_token(Some("hpos.example"), &token)).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        send(&router, with_token(Some("hpos.example"), &other)).await,
+        StatusCode::OK
+    );
+    // Logging out twice, or without a token, gets nowhere.
+    assert_eq!(
+        post(&router, "/logout", with_token(None, &token)).await,
+        StatusCode::UNAUTHORIZED
+    );
+    let signed = signed(&admin(), "POST", "/api/v1/hp-admin-logout", b"");
+    assert_eq!(
+        post(
+            &router,
+            "/logout",
+            subrequest("POST", "/api/v1/hp-admin-logout", &signed)
+        )
+        .await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn signed_revocation_ends_every_session() {
+    let clock = Arc::new(ManualClock::new(NOW));
+    let router = session_router(verifier().with_clock(clock.clone()));
+    let tokens = [
+        signed_login(&router).await,
+        signed_login_at(&router, NOW - 1).await,
+    ];
+
+    // A session token cannot revoke every session; only the admin key can.
+    assert_eq!(
+        post(&router, "/revoke-sessions", with_token(None, &tokens[0])).await,
+        StatusCode::BAD_REQUEST
+    );
+
+    let revoke = signed(&admin(), "POST", "/api/v1/hp-admin-revoke-sessions", b"");
+    assert_eq!(
+        post(
+            &router,
+            "/revoke-sessions",
+            subrequest("POST", "/api/v1/hp-admin-revoke-sessions", &revoke)
+        )
+        .await,
+        StatusCode::NO_CONTENT
+    );
+    for token in &tokens {
+        assert_eq!(
+            send(&router, with_token(Some("hpos.example"), token)).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    // Sessions started afterwards are unaffected, even within the same
+    // second as the revocation.
+    let later = signed_at(&admin(), "POST", LOGIN, b"", NOW - 2);
+    let (status, token) = login(&router, subrequest("POST", LOGIN, &later)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        send(&router, with_token(Some("hpos.example"), &token.unwrap())).await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn revocations_survive_a_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("revoked.json");
+    let restart = || {
+        session_router_with(
+            sessions().with_revocation_store(Arc::new(FileRevocationStore::open(&path).unwrap())),
+        )
+    };
+
+    let router = restart();
+    let token = signed_login(&router).await;
+    assert_eq!(
+        post(&router, "/logout", with_token(None, &token)).await,
+        StatusCode::NO_CONTENT
+    );
+    drop(router);
+
+    assert_eq!(
+        send(&restart(), with_token(Some("hpos.example"), &token)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}